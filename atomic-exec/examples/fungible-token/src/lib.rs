@@ -338,6 +338,8 @@ impl Actor {
                 nonce: 0,
             },
             wrapped: true,
+            reply_method: None,
+            fee: TokenAmount::default(),
         };
         rt.send(
             &st.ipc_gateway(),
@@ -428,6 +430,8 @@ impl Actor {
                 nonce: 0,
             },
             wrapped: true,
+            reply_method: None,
+            fee: TokenAmount::default(),
         };
         rt.send(
             &st.ipc_gateway(),
@@ -112,6 +112,8 @@ impl Actor {
                             nonce: 0,
                         },
                         wrapped: true,
+                        reply_method: None,
+                        fee: TokenAmount::default(),
                     });
                 }
                 Ok(Some(msgs))
@@ -194,6 +196,8 @@ impl Actor {
                         nonce: 0,
                     },
                     wrapped: true,
+                    reply_method: None,
+                    fee: TokenAmount::default(),
                 }))
             })
             .map_err(|e| {
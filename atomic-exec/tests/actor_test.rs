@@ -171,5 +171,7 @@ fn wrap_cross_msg(
             nonce: 0,
         },
         wrapped: true,
+        reply_method: None,
+        fee: TokenAmount::default(),
     }
 }
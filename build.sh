@@ -9,6 +9,10 @@ echo "building IPC gateway"
 GATEWAY_FLAGS="-p=ipc-gateway --features=fil-gateway-actor"
 cargo build $WASM_FLAGS $GATEWAY_FLAGS
 
+echo "building IPC treasury"
+TREASURY_FLAGS="-p=ipc-treasury --features=fil-treasury-actor"
+cargo build $WASM_FLAGS $TREASURY_FLAGS
+
 echo "building the rest of ipc-actors"
 IPC_FLAGS="-p=ipc-subnet-actor -p=ipc_atomic_execution --features=fil-actor"
 cargo build $WASM_FLAGS $IPC_FLAGS
@@ -1,11 +1,16 @@
-use crate::ensure_message_sorted;
+use crate::upgrade::BottomUpCheckpointV0;
+use crate::{ensure_message_sorted, CheckpointArray, CHECKPOINT_AMT_BITWIDTH};
 use anyhow::anyhow;
 use cid::multihash::Code;
 use cid::multihash::MultihashDigest;
 use cid::Cid;
+use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
 use fil_actors_runtime::runtime::Runtime;
+use fil_actors_runtime::{make_map_with_root_and_bitwidth, Map};
+use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::DAG_CBOR;
 use fvm_ipld_encoding::{serde_bytes, to_vec};
+use fvm_ipld_hamt::BytesKey;
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
@@ -15,7 +20,7 @@ use ipc_sdk::subnet_id::SubnetID;
 use ipc_sdk::ValidatorSet;
 use lazy_static::lazy_static;
 use num_traits::Zero;
-use primitives::{TCid, TLink};
+use primitives::{TAmt, TCid, THamt, TLink};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
@@ -74,6 +79,12 @@ impl BottomUpCheckpoint {
         self.sig = sig;
     }
 
+    /// return the subnet-specific proof of the checkpoint. This is the root used
+    /// to recover user funds if the source subnet is frozen.
+    pub fn proof(&self) -> &Vec<u8> {
+        &self.data.proof
+    }
+
     /// return checkpoint source
     pub fn source(&self) -> &SubnetID {
         &self.data.source
@@ -84,6 +95,11 @@ impl BottomUpCheckpoint {
         &self.data.prev_check
     }
 
+    /// return the nonce of the next top-down message to be applied in the source.
+    pub fn applied_topdown_nonce(&self) -> u64 {
+        self.data.applied_topdown_nonce
+    }
+
     /// Take the cross messages out of the checkpoint. This will empty the `self.data.cross_msgs`
     /// and replace with None.
     pub fn cross_msgs(&mut self) -> Option<Vec<CrossMsg>> {
@@ -105,6 +121,21 @@ impl BottomUpCheckpoint {
         Ok(())
     }
 
+    /// Get the sum of the fees left attached to the cross messages for the
+    /// hops ahead in their route
+    pub fn total_attached_fee(&self) -> TokenAmount {
+        match &self.data.cross_msgs.cross_msgs {
+            None => TokenAmount::zero(),
+            Some(cross_msgs) => {
+                let mut fee = TokenAmount::zero();
+                cross_msgs.iter().for_each(|cross_msg| {
+                    fee += &cross_msg.fee;
+                });
+                fee
+            }
+        }
+    }
+
     /// Get the sum of values in cross messages
     pub fn total_value(&self) -> TokenAmount {
         match &self.data.cross_msgs.cross_msgs {
@@ -177,6 +208,10 @@ pub struct CheckData {
     pub prev_check: TCid<TLink<BottomUpCheckpoint>>,
     pub children: Vec<ChildCheck>,
     pub cross_msgs: BatchCrossMsgs,
+    // nonce of the next top-down message to be applied in the source subnet. All
+    // top-down messages with a lower nonce have been applied and can be pruned
+    // from the parent.
+    pub applied_topdown_nonce: u64,
 }
 
 #[derive(Default, PartialEq, Eq, Clone, Debug)]
@@ -194,6 +229,7 @@ impl CheckData {
             prev_check: (*CHECKPOINT_GENESIS_CID).into(),
             children: Vec::new(),
             cross_msgs: BatchCrossMsgs::default(),
+            applied_topdown_nonce: 0,
         }
     }
 }
@@ -210,21 +246,64 @@ pub struct ChildCheck {
 /// Return the template of the checkpoint template that has been
 /// frozen and that is ready for signing and commitment in the
 /// current window.
-pub fn checkpoint_epoch(epoch: ChainEpoch, period: ChainEpoch) -> ChainEpoch {
-    // TODO: Once we consider different genesis_epoch different to zero
-    // we should account for this here.
-    (epoch / period) * period
+///
+/// Windows are multiples of `period` since `genesis_epoch`.
+pub fn checkpoint_epoch(
+    epoch: ChainEpoch,
+    genesis_epoch: ChainEpoch,
+    period: ChainEpoch,
+) -> ChainEpoch {
+    genesis_epoch + (epoch - genesis_epoch).div_euclid(period) * period
 }
 
 /// WindowEpoch returns the epoch of the active checkpoint window
 ///
 /// Determines the epoch to which new checkpoints and cross-net transactions need
 /// to be assigned (i.e. the next checkpoint to be committed)
-pub fn window_epoch(epoch: ChainEpoch, period: ChainEpoch) -> ChainEpoch {
-    // TODO: Once we consider different genesis_epoch different to zero
-    // we should account for this here.
-    let ind = epoch / period;
-    period * (ind + 1)
+pub fn window_epoch(
+    epoch: ChainEpoch,
+    genesis_epoch: ChainEpoch,
+    period: ChainEpoch,
+) -> ChainEpoch {
+    checkpoint_epoch(epoch, genesis_epoch, period) + period
+}
+
+/// Index of the checkpoint for `epoch` in the AMT of checkpoints.
+///
+/// Checkpoints are committed every `period` epochs, so they are
+/// densely indexed by `epoch / period`.
+pub fn checkpoint_index(epoch: ChainEpoch, period: ChainEpoch) -> u64 {
+    (epoch / period) as u64
+}
+
+/// Migrates checkpoints stored in a HAMT keyed by epoch, the layout used by
+/// previous versions of the actors, to an AMT indexed by `checkpoint_index`.
+/// Legacy checkpoints are decoded in their version 0 layout, see `upgrade`.
+///
+/// It returns `None` if `root` already points to an AMT of checkpoints, so
+/// it is safe to call it more than once.
+pub fn migrate_checkpoints<BS: Blockstore>(
+    store: &BS,
+    root: &Cid,
+    period: ChainEpoch,
+) -> anyhow::Result<Option<TCid<TAmt<BottomUpCheckpoint, CHECKPOINT_AMT_BITWIDTH>>>> {
+    if CheckpointArray::load(root, store).is_ok() {
+        return Ok(None);
+    }
+
+    let legacy: Map<BS, BottomUpCheckpointV0> =
+        make_map_with_root_and_bitwidth(root, store, HAMT_BIT_WIDTH)
+            .map_err(|e| anyhow!("failed to load legacy checkpoints: {:?}", e))?;
+    let mut checkpoints = TCid::new_amt(store)?;
+    checkpoints.update(store, |amt| {
+        legacy.for_each(|_, ch| {
+            let ch = BottomUpCheckpoint::from(ch.clone());
+            amt.set(checkpoint_index(ch.epoch(), period), ch)
+                .map_err(|e| anyhow!("failed to set checkpoint: {:?}", e))
+        })?;
+        Ok(())
+    })?;
+    Ok(Some(checkpoints))
 }
 
 /// Validators tracks all the validator in the subnet. It is useful in handling top-down checkpoints.
@@ -234,31 +313,70 @@ pub struct Validators {
     pub validators: ValidatorSet,
     /// Tracks the total weight of the validators
     pub total_weight: TokenAmount,
+    /// Weights of the validators indexed by their ID address, resolved once
+    /// when the validator set is set.
+    pub weights: TCid<THamt<Address, TokenAmount>>,
+    /// Addresses of the validators that couldn't be resolved when the validator
+    /// set was set, as their actor may not have been created yet.
+    pub unresolved: Vec<Address>,
 }
 
 impl Validators {
-    pub fn new(validators: ValidatorSet) -> Self {
-        let mut weight = TokenAmount::zero();
-        for v in validators.validators() {
-            weight += v.weight.clone();
-        }
-        Self {
+    /// Creates the validators from `validators`, indexing their weights by the
+    /// ID address returned by `resolve`.
+    pub fn new<BS: Blockstore>(
+        store: &BS,
+        validators: ValidatorSet,
+        resolve: impl Fn(&Address) -> Option<Address>,
+    ) -> anyhow::Result<Self> {
+        let mut total_weight = TokenAmount::zero();
+        let mut unresolved = Vec::new();
+        let mut weights = TCid::new_hamt(store)?;
+        weights.update(store, |index| {
+            for v in validators.validators() {
+                total_weight += &v.weight;
+                let id = match resolve(&v.addr) {
+                    Some(id) => id,
+                    None => {
+                        unresolved.push(v.addr);
+                        continue;
+                    }
+                };
+                // the first validator resolving to an address is the one voting with it
+                let key = BytesKey::from(id.to_bytes());
+                if !index.contains_key(&key)? {
+                    index.set(key, v.weight.clone())?;
+                }
+            }
+            Ok(())
+        })?;
+        Ok(Self {
             validators,
-            total_weight: weight,
-        }
+            total_weight,
+            weights,
+            unresolved,
+        })
     }
 
     /// Get the weight of a validator
     /// It expects ID addresses as an input
-    pub fn get_validator_weight(&self, rt: &impl Runtime, addr: &Address) -> Option<TokenAmount> {
-        self.validators
+    pub fn get_validator_weight(
+        &self,
+        rt: &impl Runtime,
+        addr: &Address,
+    ) -> anyhow::Result<Option<TokenAmount>> {
+        let weights = self.weights.load(rt.store())?;
+        if let Some(weight) = weights.get(&BytesKey::from(addr.to_bytes()))? {
+            return Ok(Some(weight.clone()));
+        }
+        // only the validators that couldn't be resolved before need to be resolved now
+        Ok(self
+            .validators
             .validators()
             .iter()
-            .find(|x| match rt.resolve_address(&x.addr) {
-                Some(id) => id == *addr,
-                None => false,
-            })
-            .map(|v| v.weight.clone())
+            .filter(|v| self.unresolved.contains(&v.addr))
+            .find(|v| rt.resolve_address(&v.addr) == Some(*addr))
+            .map(|v| v.weight.clone()))
     }
 }
 
@@ -330,13 +448,51 @@ impl<'de> Deserialize<'de> for BatchCrossMsgs {
 
 #[cfg(test)]
 mod tests {
-    use crate::BottomUpCheckpoint;
+    use crate::checkpoint::{
+        checkpoint_epoch, checkpoint_index, migrate_checkpoints, window_epoch,
+    };
+    use crate::upgrade::{BatchCrossMsgsV0, BottomUpCheckpointV0, CheckDataV0, CrossMsgV0};
+    use crate::{BottomUpCheckpoint, CheckpointArray, CHECKPOINT_GENESIS_CID};
     use cid::Cid;
-    use fil_actors_runtime::cbor;
+    use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
+    use fil_actors_runtime::{cbor, make_empty_map};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::METHOD_SEND;
+    use ipc_sdk::address::IPCAddress;
+    use ipc_sdk::cross::StorableMsg;
+    use ipc_sdk::epoch_key;
     use ipc_sdk::subnet_id::SubnetID;
     use primitives::TCid;
     use std::str::FromStr;
 
+    fn checkpoint_v0(subnet: &SubnetID, epoch: i64) -> BottomUpCheckpointV0 {
+        let msg = CrossMsgV0 {
+            msg: StorableMsg {
+                from: IPCAddress::new(subnet, &Address::new_id(100)).unwrap(),
+                to: IPCAddress::new(subnet, &Address::new_id(101)).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: TokenAmount::from_atto(1000),
+                nonce: epoch as u64,
+            },
+            wrapped: false,
+        };
+        BottomUpCheckpointV0 {
+            data: CheckDataV0 {
+                source: subnet.clone(),
+                proof: vec![1, 2, 3],
+                epoch,
+                prev_check: (*CHECKPOINT_GENESIS_CID).into(),
+                children: vec![],
+                cross_msgs: BatchCrossMsgsV0(vec![msg], TokenAmount::from_atto(10)),
+            },
+            sig: vec![4, 5, 6],
+        }
+    }
+
     #[test]
     fn test_serialization() {
         let mut checkpoint = BottomUpCheckpoint::new(SubnetID::from_str("/r123").unwrap(), 10);
@@ -349,4 +505,62 @@ mod tests {
         let de = cbor::deserialize(&raw_bytes, "").unwrap();
         assert_eq!(checkpoint, de);
     }
+
+    #[test]
+    fn test_window_epoch() {
+        let period = 10;
+        assert_eq!(checkpoint_epoch(15, 0, period), 10);
+        assert_eq!(window_epoch(15, 0, period), 20);
+
+        // windows are offset by the genesis epoch
+        assert_eq!(checkpoint_epoch(15, 3, period), 13);
+        assert_eq!(window_epoch(15, 3, period), 23);
+        assert_eq!(checkpoint_epoch(13, 3, period), 13);
+        assert_eq!(window_epoch(12, 3, period), 13);
+        // epochs before genesis are assigned to the window ending at genesis
+        assert_eq!(window_epoch(0, 3, period), 3);
+
+        // windows are still densely indexed
+        assert_eq!(checkpoint_index(window_epoch(15, 3, period), period), 2);
+        assert_eq!(checkpoint_index(window_epoch(25, 3, period), period), 3);
+    }
+
+    #[test]
+    fn test_migrate_checkpoints() {
+        let store = MemoryBlockstore::new();
+        let id = SubnetID::from_str("/r123").unwrap();
+        let period = 10;
+
+        // legacy checkpoints are stored in their version 0 layout
+        let mut legacy = make_empty_map(&store, HAMT_BIT_WIDTH);
+        for epoch in [10, 20, 40] {
+            legacy
+                .set(epoch_key(epoch), checkpoint_v0(&id, epoch))
+                .unwrap();
+        }
+        let root = legacy.flush().unwrap();
+
+        let migrated = migrate_checkpoints(&store, &root, period).unwrap().unwrap();
+        let checkpoints = migrated.load(&store).unwrap();
+        assert_eq!(checkpoints.count(), 3);
+        for epoch in [10, 20, 40] {
+            let ch = checkpoints
+                .get(checkpoint_index(epoch, period))
+                .unwrap()
+                .unwrap();
+            assert_eq!(ch, &BottomUpCheckpoint::from(checkpoint_v0(&id, epoch)));
+            assert_eq!(ch.data.cross_msgs.cross_msgs.as_ref().unwrap().len(), 1);
+            assert_eq!(ch.data.cross_msgs.fee, TokenAmount::from_atto(10));
+        }
+        assert!(checkpoints
+            .get(checkpoint_index(30, period))
+            .unwrap()
+            .is_none());
+
+        // migrating an AMT of checkpoints is a no-op
+        assert!(migrate_checkpoints(&store, &migrated.cid(), period)
+            .unwrap()
+            .is_none());
+        assert!(CheckpointArray::load(&migrated.cid(), &store).is_ok());
+    }
 }
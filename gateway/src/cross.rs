@@ -1,13 +1,22 @@
 use crate::ExecutableMessage;
+use crate::Method;
 use crate::State;
 use crate::SUBNET_ACTOR_REWARD_METHOD;
+use crate::{cross_msg_cid, CrossMsgEvent, GatewayEvent};
 use fil_actors_runtime::runtime::Runtime;
-use fil_actors_runtime::ActorError;
 use fil_actors_runtime::BURNT_FUNDS_ACTOR_ADDR;
+use fil_actors_runtime::{actor_error, cbor, ActorError};
+use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_shared::address::Address;
+use fvm_shared::bigint::Zero;
 use fvm_shared::econ::TokenAmount;
-use fvm_shared::METHOD_SEND;
+use fvm_shared::{MethodNum, METHOD_SEND};
+use ipc_sdk::address::IPCAddress;
 use ipc_sdk::cross::{CrossMsg, StorableMsg};
+use ipc_sdk::route::hop_fee;
+use ipc_sdk::subnet_id::SubnetID;
+use ipc_sdk::ValidatorSet;
+use ipc_treasury::UnlockParams;
 
 impl ExecutableMessage for StorableMsg {
     fn nonce(&self) -> u64 {
@@ -30,9 +39,10 @@ pub(crate) fn cross_msg_side_effects(
     top_down_fee: &TokenAmount,
 ) -> Result<(), ActorError> {
     // if this is a bottom-up message funds of the
-    // cross-message need to be burnt
+    // cross-message, including the fee left for the
+    // hops ahead, need to be burnt
     if do_burn {
-        burn_bu_funds(rt, cross_msg.msg.value.clone())?;
+        burn_bu_funds(rt, cross_msg.msg.value.clone() + &cross_msg.fee)?;
     }
 
     // distribute top-down fee if any
@@ -55,6 +65,32 @@ pub(crate) fn cross_msg_side_effects(
     Ok(())
 }
 
+/// Takes the share of the fee attached to `cross_msg` for the hop from `network_name`.
+/// The fee is split evenly between the hops left in the route of the message, the rest
+/// is left attached to it for the following hops and the last hop takes any remainder.
+pub(crate) fn take_hop_fee(
+    network_name: &SubnetID,
+    cross_msg: &mut CrossMsg,
+) -> Result<TokenAmount, ActorError> {
+    let to = cross_msg
+        .msg
+        .to
+        .subnet()
+        .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
+    let hops = match network_name.hops_to(&to) {
+        Some(hops) if hops > 0 => hops,
+        _ => {
+            return Err(actor_error!(
+                illegal_argument,
+                "no hops left in the route of the cross-message"
+            ))
+        }
+    };
+    let share = hop_fee(&cross_msg.fee, hops);
+    cross_msg.fee -= &share;
+    Ok(share)
+}
+
 pub(crate) fn distribute_crossmsg_fee(
     rt: &mut impl Runtime,
     subnet_actor: &Address,
@@ -70,3 +106,90 @@ pub(crate) fn burn_bu_funds(rt: &mut impl Runtime, value: TokenAmount) -> Result
     rt.send(&BURNT_FUNDS_ACTOR_ADDR, METHOD_SEND, None, value)?;
     Ok(())
 }
+
+/// Unlocks `value` of circulating supply from the treasury to the gateway, so
+/// it can be minted by a top-down message.
+pub(crate) fn unlock_from_treasury(
+    rt: &mut impl Runtime,
+    treasury: &Address,
+    value: &TokenAmount,
+) -> Result<(), ActorError> {
+    rt.send(
+        treasury,
+        ipc_treasury::Method::Unlock as MethodNum,
+        IpldBlock::serialize_cbor(&UnlockParams {
+            amount: value.clone(),
+        })?,
+        TokenAmount::zero(),
+    )?;
+    Ok(())
+}
+
+/// Builds the top-down message setting `validator_set` as the membership of the
+/// gateway of `subnet`, sent on behalf of its subnet actor. Gateways are deployed
+/// at the same address in every subnet, so `gateway` is also the destination
+/// of the message in the child.
+pub(crate) fn membership_msg(
+    subnet: &SubnetID,
+    gateway: &Address,
+    validator_set: &ValidatorSet,
+) -> Result<CrossMsg, ActorError> {
+    let parent = subnet
+        .parent()
+        .ok_or_else(|| actor_error!(illegal_argument, "subnet {} has no parent", subnet))?;
+    let from = IPCAddress::new(&parent, &subnet.subnet_actor())
+        .map_err(|_| actor_error!(illegal_argument, "error setting IPC address in membership"))?;
+    let to = IPCAddress::new(subnet, gateway)
+        .map_err(|_| actor_error!(illegal_argument, "error setting IPC address in membership"))?;
+    Ok(CrossMsg {
+        msg: StorableMsg {
+            from,
+            to,
+            method: Method::SetMembership as MethodNum,
+            params: cbor::serialize(validator_set, "validator set")?,
+            value: TokenAmount::zero(),
+            // the nonce is set when the message is committed.
+            nonce: 0,
+        },
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+    })
+}
+
+/// Checks if `msg` is a membership update for the gateway of `network_name`
+/// committed by the subnet actor of the subnet in its parent.
+pub(crate) fn is_membership_msg(
+    msg: &StorableMsg,
+    network_name: &SubnetID,
+    gateway: &Address,
+) -> bool {
+    let parent = match network_name.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    msg.method == Method::SetMembership as MethodNum
+        && msg.to.raw_addr().ok() == Some(*gateway)
+        && msg.from.subnet().ok() == Some(parent)
+        && msg.from.raw_addr().ok() == Some(network_name.subnet_actor())
+}
+
+/// Builds the payload of the events emitted for a cross-message.
+pub(crate) fn cross_msg_event(
+    cross_msg: &CrossMsg,
+    fee: &TokenAmount,
+) -> Result<CrossMsgEvent, ActorError> {
+    Ok(CrossMsgEvent::new(
+        cross_msg_cid(cross_msg)?,
+        cross_msg,
+        fee.clone(),
+    ))
+}
+
+/// Emits a gateway event following the schema in `ipc_sdk::events`.
+pub(crate) fn emit_event(rt: &impl Runtime, event: GatewayEvent) -> Result<(), ActorError> {
+    let event = event
+        .to_actor_event()
+        .map_err(|e| actor_error!(serialization, "error encoding gateway event: {}", e))?;
+    rt.emit_event(&event)
+}
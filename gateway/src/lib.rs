@@ -6,27 +6,35 @@ pub use self::checkpoint::{BottomUpCheckpoint, CHECKPOINT_GENESIS_CID};
 pub use self::state::*;
 pub use self::subnet::*;
 pub use self::types::*;
+use checkpoint::window_epoch;
 pub use checkpoint::TopDownCheckpoint;
-use cross::{burn_bu_funds, cross_msg_side_effects, distribute_crossmsg_fee};
+use cid::Cid;
+use cross::{
+    burn_bu_funds, cross_msg_event, cross_msg_side_effects, distribute_crossmsg_fee, emit_event,
+    is_membership_msg, membership_msg, take_hop_fee, unlock_from_treasury,
+};
 use fil_actors_runtime::runtime::{ActorCode, Runtime};
 use fil_actors_runtime::{
-    actor_dispatch, actor_error, restrict_internal_api, ActorDowncast, ActorError,
+    actor_dispatch, actor_error, cbor, restrict_internal_api, ActorDowncast, ActorError,
     CALLER_TYPES_SIGNABLE, INIT_ACTOR_ADDR, SYSTEM_ACTOR_ADDR,
 };
 use fvm_ipld_encoding::RawBytes;
-use fvm_shared::address::Address;
+use fvm_shared::address::{Address, Protocol};
 use fvm_shared::bigint::Zero;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::METHOD_SEND;
 use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR};
 pub use ipc_sdk::address::IPCAddress;
-pub use ipc_sdk::cross::{CrossMsg, IPCMsgType, StorableMsg};
+pub use ipc_sdk::cross::{CrossMsg, CrossMsgReceipt, IPCMsgType, StorableMsg};
+pub use ipc_sdk::events::{CheckpointEvent, CrossMsgEvent, GatewayEvent};
 pub use ipc_sdk::subnet_id::SubnetID;
 use ipc_sdk::ValidatorSet;
 use lazy_static::lazy_static;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use recovery::verify_recovery_proof;
+use upgrade::migrate_state;
 
 #[cfg(feature = "fil-gateway-actor")]
 fil_actors_runtime::wasm_trampoline!(Actor);
@@ -34,13 +42,16 @@ fil_actors_runtime::wasm_trampoline!(Actor);
 pub mod checkpoint;
 mod cross;
 mod error;
+pub mod recovery;
 mod state;
 pub mod subnet;
 mod types;
+pub mod upgrade;
 
 lazy_static! {
-    // TODO: make this into constructor!
-    pub static ref CROSS_MSG_FEE: TokenAmount = TokenAmount::from_nano(100);
+    /// Default fee for cross-messages, it can be used to populate the
+    /// `cross_msg_fees` policy when constructing the gateway.
+    pub static ref DEFAULT_CROSS_MSG_FEE: TokenAmount = TokenAmount::from_nano(100);
     pub static ref INITIAL_VALIDATOR_FUNDS: TokenAmount = TokenAmount::from_whole(1);
 }
 
@@ -65,6 +76,27 @@ pub enum Method {
     // and be in the range where only builtin-actors can call them.
     SetMembership = frc42_dispatch::method_hash!("SetMembership"),
     InitGenesisEpoch = frc42_dispatch::method_hash!("InitGenesisEpoch"),
+    GetSubnet = frc42_dispatch::method_hash!("GetSubnet"),
+    ListSubnets = frc42_dispatch::method_hash!("ListSubnets"),
+    GetBottomUpCheckpoint = frc42_dispatch::method_hash!("GetBottomUpCheckpoint"),
+    GetTopDownMsgs = frc42_dispatch::method_hash!("GetTopDownMsgs"),
+    GetPostboxItem = frc42_dispatch::method_hash!("GetPostboxItem"),
+    SetCrossMsgFees = frc42_dispatch::method_hash!("SetCrossMsgFees"),
+    PruneBottomUpCheckpoints = frc42_dispatch::method_hash!("PruneBottomUpCheckpoints"),
+    ReclaimPostbox = frc42_dispatch::method_hash!("ReclaimPostbox"),
+    ListPostboxCids = frc42_dispatch::method_hash!("ListPostboxCids"),
+    RemovePropagator = frc42_dispatch::method_hash!("RemovePropagator"),
+    TransferPostboxOwnership = frc42_dispatch::method_hash!("TransferPostboxOwnership"),
+    FreezeSubnet = frc42_dispatch::method_hash!("FreezeSubnet"),
+    RecoverFunds = frc42_dispatch::method_hash!("RecoverFunds"),
+    SetSubnetLimits = frc42_dispatch::method_hash!("SetSubnetLimits"),
+    ReleaseHeldMsgs = frc42_dispatch::method_hash!("ReleaseHeldMsgs"),
+    Pause = frc42_dispatch::method_hash!("Pause"),
+    Unpause = frc42_dispatch::method_hash!("Unpause"),
+    UpgradeState = frc42_dispatch::method_hash!("UpgradeState"),
+    CommitMembership = frc42_dispatch::method_hash!("CommitMembership"),
+    SetCheckpointLimits = frc42_dispatch::method_hash!("SetCheckpointLimits"),
+    SetExecutedCheckpoint = frc42_dispatch::method_hash!("SetExecutedCheckpoint"),
 }
 
 /// Gateway Actor
@@ -83,7 +115,7 @@ impl Actor {
         })?;
         // the root doesn't need to be explicitly initialized
         if st.network_name.is_root() {
-            st.init_gateway(rt.store(), 0)?;
+            st.init_gateway(rt.store(), 0, 0)?;
         }
         rt.create(&st)?;
         Ok(())
@@ -91,7 +123,7 @@ impl Actor {
 
     /// Register is called by subnet actors to put the required collateral
     /// and register the subnet to the hierarchy.
-    fn register(rt: &mut impl Runtime) -> Result<SubnetID, ActorError> {
+    fn register(rt: &mut impl Runtime, params: RegisterParams) -> Result<SubnetID, ActorError> {
         rt.validate_immediate_caller_accept_any()?;
 
         let subnet_addr = rt.message().caller();
@@ -110,12 +142,13 @@ impl Actor {
                     ));
                 }
                 None => {
-                    st.register_subnet(rt, &shid).map_err(|e| {
-                        e.downcast_default(
-                            ExitCode::USR_ILLEGAL_ARGUMENT,
-                            "Failed to register subnet",
-                        )
-                    })?;
+                    st.register_subnet(rt, &shid, params.bottomup_genesis_epoch)
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_ARGUMENT,
+                                "Failed to register subnet",
+                            )
+                        })?;
                 }
             }
 
@@ -273,6 +306,170 @@ impl Actor {
         Ok(())
     }
 
+    /// Freezes a subnet that hasn't committed a checkpoint for `recovery_deadline` epochs,
+    /// so its users can recover the funds locked in the subnet's circulating supply.
+    ///
+    /// The top-down messages that weren't applied in the subnet before freezing it are
+    /// refunded to their senders, as they are not included in the recovery root. At most
+    /// `MAX_FROZEN_REFUNDS` are refunded per call, so it needs to be called again on the
+    /// frozen subnet until all of them are refunded.
+    fn freeze_subnet(rt: &mut impl Runtime, id: SubnetID) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+        rt.state::<State>()?.require_not_paused()?;
+
+        let (network_name, pending) = rt.transaction(|st: &mut State, rt| {
+            if st.recovery_deadline <= 0 {
+                return Err(actor_error!(illegal_state, "subnet recovery is disabled"));
+            }
+            let mut sub = st
+                .get_subnet(rt, &id)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?
+                .ok_or_else(|| {
+                    actor_error!(illegal_argument, "subnet with id {} not registered", id)
+                })?;
+            if sub.status != Status::Frozen {
+                // checkpoint epochs are counted in the child, inactivity is measured
+                // with the epochs of this chain in which they were committed
+                if rt.curr_epoch() < sub.last_checkpoint_epoch + st.recovery_deadline {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "subnet hasn't been inactive past the recovery deadline"
+                    ));
+                }
+                sub.status = Status::Frozen;
+                sub.refund_nonce = sub.pending_topdown_nonce();
+            } else if sub.refund_nonce >= sub.topdown_nonce {
+                return Err(actor_error!(illegal_state, "subnet already frozen"));
+            }
+
+            let to = std::cmp::min(sub.refund_nonce + MAX_FROZEN_REFUNDS, sub.topdown_nonce);
+            let pending = sub
+                .topdown_msgs(rt.store(), sub.refund_nonce, to)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "error loading pending top-down messages",
+                    )
+                })?;
+            sub.refund_nonce = to;
+            let value = pending
+                .iter()
+                .fold(TokenAmount::zero(), |acc, m| acc + &m.msg.value);
+            sub.release_supply(&value).map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "error releasing circulating supply",
+                )
+            })?;
+
+            st.flush_subnet(rt.store(), &sub).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing subnet")
+            })?;
+            Ok((st.network_name.clone(), pending))
+        })?;
+
+        for cross_msg in pending {
+            let from = &cross_msg.msg.from;
+            let from_addr = from
+                .raw_addr()
+                .map_err(|_| actor_error!(illegal_argument, "invalid address"))?;
+            // receipts are never refunded, as their sender is a gateway.
+            if cross_msg.msg.value.is_zero() || from_addr == rt.message().receiver() {
+                continue;
+            }
+
+            let from_subnet = from
+                .subnet()
+                .map_err(|_| actor_error!(illegal_argument, "invalid subnet in address"))?;
+            if from_subnet == network_name {
+                rt.send(&from_addr, METHOD_SEND, None, cross_msg.msg.value)?;
+            } else {
+                let receipt = CrossMsgReceipt {
+                    msg: cross_msg.msg,
+                    exit_code: SUBNET_FROZEN_EXIT_CODE,
+                    return_data: RawBytes::default(),
+                };
+                Self::send_receipt(rt, &receipt, cross_msg.reply_method)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recovers the funds of an address from a frozen subnet, verifying the balance
+    /// against the recovery root committed in the last checkpoint of the subnet.
+    /// Anyone can submit the proof, the funds are always sent to the address owning them,
+    /// which needs to be a secp256k1 or BLS address to refer to the same owner in both chains.
+    fn recover_funds(rt: &mut impl Runtime, params: RecoverFundsParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+        rt.state::<State>()?.require_not_paused()?;
+
+        let RecoverFundsParams {
+            subnet,
+            address,
+            amount,
+            proof,
+        } = params;
+
+        rt.transaction(|st: &mut State, rt| {
+            let mut sub = st
+                .get_subnet(rt, &subnet)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?
+                .ok_or_else(|| {
+                    actor_error!(illegal_argument, "subnet with id {} not registered", subnet)
+                })?;
+            if sub.status != Status::Frozen {
+                return Err(actor_error!(illegal_state, "subnet is not frozen"));
+            }
+
+            let root = match sub.prev_checkpoint {
+                Some(ref prev_checkpoint) if !prev_checkpoint.proof().is_empty() => {
+                    prev_checkpoint.proof().clone()
+                }
+                _ => {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "subnet didn't commit a recovery root"
+                    ))
+                }
+            };
+            // ID addresses of the child refer to different actors in this chain
+            if !matches!(address.protocol(), Protocol::Secp256k1 | Protocol::BLS) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "funds can only be recovered to secp256k1 or BLS addresses"
+                ));
+            }
+            let valid = verify_recovery_proof(&root, &address, &amount, &proof).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "error verifying proof")
+            })?;
+            if !valid {
+                return Err(actor_error!(illegal_argument, "invalid recovery proof"));
+            }
+
+            st.record_recovered_funds(rt.store(), &subnet, &address, &amount)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "error recovering funds")
+                })?;
+            sub.release_supply(&amount).map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "error releasing circulating supply",
+                )
+            })?;
+            st.flush_subnet(rt.store(), &sub).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing subnet")
+            })?;
+            Ok(())
+        })?;
+
+        rt.send(&address, METHOD_SEND, None, amount)?;
+        Ok(())
+    }
+
     /// CommitChildCheck propagates the commitment of a checkpoint from a child subnet,
     /// process the cross-messages directed to the subnet.
     fn commit_child_check(
@@ -282,6 +479,7 @@ impl Actor {
         // This must be called by a subnet actor, once we have a way to identify subnet actor,
         // we should update here.
         rt.validate_immediate_caller_accept_any()?;
+        rt.state::<State>()?.require_not_paused()?;
 
         commit
             .ensure_cross_msgs_sorted()
@@ -344,9 +542,29 @@ impl Actor {
                         }
                     }
 
+                    // prune the top-down messages already applied in the child.
+                    let pruned_nonce = match sub.prev_checkpoint {
+                        Some(ref prev_checkpoint) => prev_checkpoint.applied_topdown_nonce(),
+                        None => 0,
+                    };
+                    let applied_nonce = commit.applied_topdown_nonce();
+                    if applied_nonce < pruned_nonce || applied_nonce > sub.topdown_nonce {
+                        return Err(actor_error!(
+                            illegal_argument,
+                            "applied top-down nonce in checkpoint not consistent with subnet"
+                        ));
+                    }
+                    sub.prune_topdown_msgs(rt.store(), pruned_nonce, applied_nonce)
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error pruning top-down messages",
+                            )
+                        })?;
+
                     // commit cross-message in checkpoint to execute them.
                     let fee = commit.total_fee().clone();
-                    let value = commit.total_value() + &fee;
+                    let value = commit.total_value() + commit.total_attached_fee() + &fee;
 
                     // release circulating supply
                     sub.release_supply(&value).map_err(|e| {
@@ -369,10 +587,29 @@ impl Actor {
                         e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing checkpoint")
                     })?;
 
-                    let cross_msgs = commit.cross_msgs();
+                    // hold the messages exceeding the release limit of the subnet
+                    let window = window_epoch(
+                        rt.curr_epoch(),
+                        st.genesis_epoch(),
+                        st.bottomup_check_period,
+                    );
+                    let cross_msgs = sub
+                        .release_bottomup_msgs(
+                            rt.store(),
+                            window,
+                            commit.cross_msgs().unwrap_or_default(),
+                            false,
+                        )
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error releasing bottom-up messages",
+                            )
+                        })?;
 
                     // update prev_check for child
                     sub.prev_checkpoint = Some(commit.clone());
+                    sub.last_checkpoint_epoch = rt.curr_epoch();
                     // flush subnet
                     st.flush_subnet(rt.store(), &sub).map_err(|e| {
                         e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing subnet")
@@ -387,10 +624,24 @@ impl Actor {
             }
         })?;
 
-        if let Some(msgs) = cross_msgs {
-            for cross_msg in msgs {
-                Self::apply_msg_inner(rt, commit.source(), cross_msg)?;
-            }
+        emit_event(
+            rt,
+            GatewayEvent::CommitChildCheck(CheckpointEvent {
+                subnet: commit.source().clone(),
+                epoch: commit.epoch(),
+                num_msgs: commit
+                    .data
+                    .cross_msgs
+                    .cross_msgs
+                    .as_ref()
+                    .map_or(0, |msgs| msgs.len() as u64),
+                value: commit.total_value(),
+                fee: fee.clone(),
+            }),
+        )?;
+
+        for cross_msg in cross_msgs {
+            Self::apply_msg_inner(rt, commit.source(), cross_msg)?;
         }
 
         // distribute rewards
@@ -407,6 +658,7 @@ impl Actor {
     fn fund(rt: &mut impl Runtime, params: FundParams) -> Result<(), ActorError> {
         // funds can only be moved between subnets by signable addresses
         rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+        rt.state::<State>()?.require_not_paused()?;
 
         let mut value = rt.message().value_received();
         if value <= TokenAmount::zero() {
@@ -427,9 +679,18 @@ impl Actor {
         let (from_sig_addr, to_sig_addr) =
             resolved_from_to(rt, &rt.message().caller(), &params.to)?;
 
-        let fee = CROSS_MSG_FEE.clone();
-        rt.transaction(|st: &mut State, rt| {
+        let (fee, hop_fee, next_subnet, f_msg) = rt.transaction(|st: &mut State, rt| {
+            let fee = st
+                .cross_msg_fees
+                .fee(CrossMsgKind::Fund, &RawBytes::default());
             st.collect_cross_fee(&mut value, &fee)?;
+            st.ensure_circ_supply_cap(rt, &sub_id, &value)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "fund exceeds circulating supply cap",
+                    )
+                })?;
             // Create fund message
             let mut f_msg = CrossMsg {
                 msg: StorableMsg::new_fund_msg(&sub_id, &from_sig_addr, &to_sig_addr, value)
@@ -440,10 +701,22 @@ impl Actor {
                         )
                     })?,
                 wrapped: false,
+                reply_method: None,
+                fee: fee.clone(),
             };
 
             log::debug!("fund cross msg is: {:?}", f_msg);
 
+            // the fee is split between the hops in the route to the subnet, the share
+            // of the first hop rewards the validators of the next subnet down.
+            let hop_fee = take_hop_fee(&st.network_name, &mut f_msg)?;
+            let next_subnet = sub_id.down(&st.network_name).ok_or_else(|| {
+                actor_error!(
+                    illegal_argument,
+                    "couldn't compute the next subnet in route"
+                )
+            })?;
+
             // Commit top-down message.
             st.commit_topdown_msg(rt, &mut f_msg).map_err(|e| {
                 e.downcast_default(
@@ -451,11 +724,13 @@ impl Actor {
                     "error committing top-down message",
                 )
             })?;
-            Ok(())
+            Ok((fee, hop_fee, next_subnet, f_msg))
         })?;
 
+        emit_event(rt, GatewayEvent::Fund(cross_msg_event(&f_msg, &fee)?))?;
+
         // distribute top-down message fee to validators.
-        distribute_crossmsg_fee(rt, &sub_id.subnet_actor(), fee)
+        distribute_crossmsg_fee(rt, &next_subnet.subnet_actor(), hop_fee)
     }
 
     /// Release creates a new check message to release funds in parent chain
@@ -466,6 +741,10 @@ impl Actor {
     fn release(rt: &mut impl Runtime, params: ReleaseParams) -> Result<(), ActorError> {
         // funds can only be moved between subnets by signable addresses
         rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+        let st: State = rt.state()?;
+        st.require_not_paused()?;
+        // bottom-up messages are only checkpointed once the subnet is initialized
+        st.require_initialized()?;
 
         let mut value = rt.message().value_received();
         if value <= TokenAmount::zero() {
@@ -485,13 +764,15 @@ impl Actor {
         let (from_sig_addr, to_sig_addr) =
             resolved_from_to(rt, &rt.message().caller(), &params.to)?;
 
-        rt.transaction(|st: &mut State, rt| {
-            let fee = &CROSS_MSG_FEE;
+        let (fee, r_msg) = rt.transaction(|st: &mut State, rt| {
+            let fee = st
+                .cross_msg_fees
+                .fee(CrossMsgKind::Release, &RawBytes::default());
             // collect fees
-            st.collect_cross_fee(&mut value, fee)?;
+            st.collect_cross_fee(&mut value, &fee)?;
 
             // Create release message
-            let r_msg = CrossMsg {
+            let mut r_msg = CrossMsg {
                 msg: StorableMsg::new_release_msg(
                     &st.network_name,
                     &from_sig_addr,
@@ -505,19 +786,23 @@ impl Actor {
                     )
                 })?,
                 wrapped: false,
+                reply_method: None,
+                fee: TokenAmount::zero(),
             };
 
             // Commit bottom-up message.
-            st.commit_bottomup_msg(rt.store(), &r_msg, rt.curr_epoch(), fee)
+            st.commit_bottomup_msg(rt.store(), &mut r_msg, rt.curr_epoch(), &fee)
                 .map_err(|e| {
                     e.downcast_default(
                         ExitCode::USR_ILLEGAL_STATE,
                         "error committing top-down message",
                     )
                 })?;
-            Ok(())
+            Ok((fee, r_msg))
         })?;
 
+        emit_event(rt, GatewayEvent::Release(cross_msg_event(&r_msg, &fee)?))?;
+
         // burn funds that are send as bottom-up
         burn_bu_funds(rt, value)
     }
@@ -535,6 +820,9 @@ impl Actor {
     fn send_cross(rt: &mut impl Runtime, params: CrossMsgParams) -> Result<(), ActorError> {
         // only actor are allowed to send cross-message
         rt.validate_immediate_caller_not_type(CALLER_TYPES_SIGNABLE.iter())?;
+        let st: State = rt.state()?;
+        st.require_not_paused()?;
+        st.require_initialized()?;
 
         // FIXME: Should we add an additional check to ensure that the included message
         // has an actor ID as from and thus that the message doesn't come from a
@@ -552,7 +840,7 @@ impl Actor {
         } = params;
         let (mut do_burn, mut top_down_fee) = (false, TokenAmount::zero());
 
-        rt.transaction(|st: &mut State, rt| {
+        let fee = rt.transaction(|st: &mut State, rt| {
             if destination == st.network_name {
                 return Err(actor_error!(
                     illegal_argument,
@@ -595,14 +883,22 @@ impl Actor {
             }
 
             // collect cross-fee
-            let fee = CROSS_MSG_FEE.clone();
+            let fee = st.cross_msg_fees.fee(CrossMsgKind::SendCross, &msg.params);
             st.collect_cross_fee(&mut msg.value, &fee)?;
+            // only the fee collected here is backed by funds
+            cross_msg.fee = TokenAmount::zero();
 
             // commit cross-message for propagation
-            (do_burn, top_down_fee) = Self::commit_cross_message(rt, st, &mut cross_msg, fee)?;
-            Ok(())
+            (do_burn, top_down_fee) =
+                Self::commit_cross_message(rt, st, &mut cross_msg, fee.clone())?;
+            Ok(fee)
         })?;
 
+        emit_event(
+            rt,
+            GatewayEvent::SendCross(cross_msg_event(&cross_msg, &fee)?),
+        )?;
+
         // side-effects sent without any remainders
         cross_msg_side_effects(rt, &cross_msg, do_burn, &top_down_fee)?;
 
@@ -611,82 +907,170 @@ impl Actor {
 
     /// Whitelist a series of addresses as propagator of a cross net message.
     /// This is basically adding this list of addresses to the `PostBoxItem::owners`.
-    /// Only existing owners can perform this operation.
+    /// Only existing owners can perform this operation, if the item is open to anyone
+    /// only the sender of the message can restrict it to the whitelisted addresses.
+    ///
+    /// As the owners are part of the item, its cid changes and the new one is returned.
     fn whitelist_propagator(
         rt: &mut impl Runtime,
         params: WhitelistPropagatorParams,
-    ) -> Result<(), ActorError> {
-        // does not really need check as we are checking against the PostboxItem.owners
-        rt.validate_immediate_caller_accept_any()?;
-
-        let caller = rt.message().caller();
+    ) -> Result<Cid, ActorError> {
         let WhitelistPropagatorParams {
             postbox_cid,
             to_add,
         } = params;
 
-        rt.transaction(|st: &mut State, rt| {
-            let mut postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
-                log::error!("encountered error loading from postbox: {:?}", e);
-                actor_error!(unhandled_message, "cannot load from postbox")
-            })?;
+        Self::update_postbox_owners(rt, postbox_cid, |owners| {
+            let mut owners = owners.unwrap_or_default();
+            for addr in to_add {
+                if !owners.contains(&addr) {
+                    owners.push(addr);
+                }
+            }
+            Ok(owners)
+        })
+    }
+
+    /// Removes a series of addresses from the propagators of a cross net message.
+    /// Only existing owners can perform this operation, and at least one owner
+    /// needs to remain in the item.
+    fn remove_propagator(
+        rt: &mut impl Runtime,
+        params: RemovePropagatorParams,
+    ) -> Result<Cid, ActorError> {
+        let RemovePropagatorParams {
+            postbox_cid,
+            to_remove,
+        } = params;
 
-            // Currently we dont support adding owners if the owners field is None.
-            // This might change in the future.
-            if postbox_item.owners.is_none() {
+        Self::update_postbox_owners(rt, postbox_cid, |owners| {
+            let mut owners = owners.ok_or_else(|| {
+                actor_error!(illegal_state, "postbox item doesn't have propagators")
+            })?;
+            owners.retain(|addr| !to_remove.contains(addr));
+            if owners.is_empty() {
                 return Err(actor_error!(
-                    illegal_state,
-                    "postbox item cannot add owner for now"
+                    illegal_argument,
+                    "cannot remove all the propagators of a postbox item"
                 ));
             }
+            Ok(owners)
+        })
+    }
+
+    /// Transfers the ownership of a cross net message, replacing all of its
+    /// propagators with the new owners. Only existing owners can perform this operation.
+    fn transfer_postbox_ownership(
+        rt: &mut impl Runtime,
+        params: TransferPostboxOwnershipParams,
+    ) -> Result<Cid, ActorError> {
+        let TransferPostboxOwnershipParams {
+            postbox_cid,
+            new_owners,
+        } = params;
 
-            let owners = postbox_item.owners.as_mut().unwrap();
-            if !owners.contains(&caller) {
-                return Err(actor_error!(illegal_state, "not owner"));
+        Self::update_postbox_owners(rt, postbox_cid, |_| {
+            if new_owners.is_empty() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "postbox item needs at least one owner"
+                ));
             }
-            owners.extend(to_add);
+            Ok(new_owners)
+        })
+    }
 
-            st.swap_postbox_item(rt.store(), postbox_cid, postbox_item)
-                .map_err(|e| {
-                    log::error!("encountered error loading from postbox: {:?}", e);
-                    actor_error!(unhandled_message, "cannot load from postbox")
-                })?;
+    /// Checks that the caller is allowed to propagate the postbox item and replaces its
+    /// owners with the ones returned by `f`, returning the new cid of the item. Items
+    /// without owners can only be restricted by the sender of the message.
+    fn update_postbox_owners<F>(
+        rt: &mut impl Runtime,
+        postbox_cid: Cid,
+        f: F,
+    ) -> Result<Cid, ActorError>
+    where
+        F: FnOnce(Option<Vec<Address>>) -> Result<Vec<Address>, ActorError>,
+    {
+        // does not really need check as we are checking against the PostboxItem.owners
+        rt.validate_immediate_caller_accept_any()?;
 
-            Ok(())
-        })?;
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            let mut postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
+                log::error!("encountered error loading from postbox: {:?}", e);
+                actor_error!(unhandled_message, "cannot load from postbox")
+            })?;
 
-        Ok(())
+            let allowed = match postbox_item.owners {
+                Some(_) => postbox_item.is_owner(&caller),
+                // open items can be propagated by anyone, but only the original sender
+                // of the message can restrict them. ID addresses of the source subnet
+                // refer to unrelated actors in this one, so only senders with a key
+                // address, that is the same account in every subnet, are allowed.
+                None => {
+                    let sender = postbox_item
+                        .cross_msg
+                        .msg
+                        .from
+                        .raw_addr()
+                        .map_err(|_| actor_error!(illegal_argument, "invalid address"))?;
+                    matches!(sender.protocol(), Protocol::Secp256k1 | Protocol::BLS)
+                        && rt.resolve_address(&sender) == Some(caller)
+                }
+            };
+            if !allowed {
+                return Err(actor_error!(illegal_state, "owner not match"));
+            }
+            postbox_item.owners = Some(f(postbox_item.owners.take())?);
+
+            st.swap_postbox_item(rt.store(), postbox_cid, postbox_item, |a| {
+                rt.resolve_address(a)
+            })
+            .map_err(|e| {
+                log::error!("encountered error swapping postbox item: {:?}", e);
+                actor_error!(unhandled_message, "cannot swap postbox item")
+            })
+        })
     }
 
     fn propagate(rt: &mut impl Runtime, params: PropagateParams) -> Result<(), ActorError> {
         // does not really need check as we are checking against the PostboxItem.owners
         rt.validate_immediate_caller_accept_any()?;
+        rt.state::<State>()?.require_not_paused()?;
 
         let PropagateParams { postbox_cid } = params;
         let owner = rt.message().caller();
         let mut value = rt.message().value_received();
         let (mut do_burn, mut top_down_fee) = (false, TokenAmount::zero());
 
-        let cross_msg = rt.transaction(|st: &mut State, rt| {
+        let (fee, cross_msg) = rt.transaction(|st: &mut State, rt| {
             let postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
                 log::error!("encountered error loading from postbox: {:?}", e);
                 actor_error!(unhandled_message, "cannot load from postbox")
             })?;
 
-            if let Some(owners) = postbox_item.owners && !owners.contains(&owner) {
+            if !postbox_item.is_owner(&owner) {
                 return Err(actor_error!(illegal_state, "owner not match"));
             }
 
             // collect cross-fee
-            let fee = CROSS_MSG_FEE.clone();
+            let fee = st
+                .cross_msg_fees
+                .fee(CrossMsgKind::Propagate, &postbox_item.cross_msg.msg.params);
             st.collect_cross_fee(&mut value, &fee)?;
 
             let PostBoxItem { mut cross_msg, .. } = postbox_item;
-            (do_burn, top_down_fee) = Self::commit_cross_message(rt, st, &mut cross_msg, fee)?;
-            st.remove_from_postbox(rt.store(), postbox_cid)?;
-            Ok(cross_msg)
+            (do_burn, top_down_fee) =
+                Self::commit_cross_message(rt, st, &mut cross_msg, fee.clone())?;
+            st.remove_from_postbox(rt.store(), postbox_cid, |a| rt.resolve_address(a))?;
+            Ok((fee, cross_msg))
         })?;
 
+        emit_event(
+            rt,
+            GatewayEvent::Propagate(cross_msg_event(&cross_msg, &fee)?),
+        )?;
+
         // trigger cross-message side-effects returning the remainder of the fee
         // to the source.
         cross_msg_side_effects(rt, &cross_msg, do_burn, &top_down_fee)?;
@@ -697,6 +1081,82 @@ impl Actor {
         Ok(())
     }
 
+    /// Removes an expired item from the postbox, refunding the value of the
+    /// cross-message to its sender through a receipt.
+    ///
+    /// Anyone can reclaim an expired item, the refund is always routed to the sender
+    /// of the original cross-message.
+    fn reclaim_postbox(
+        rt: &mut impl Runtime,
+        params: ReclaimPostboxParams,
+    ) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+        rt.state::<State>()?.require_not_paused()?;
+
+        let ReclaimPostboxParams { postbox_cid } = params;
+        let cross_msg = rt.transaction(|st: &mut State, rt| {
+            let postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
+                log::error!("encountered error loading from postbox: {:?}", e);
+                actor_error!(unhandled_message, "cannot load from postbox")
+            })?;
+
+            if !postbox_item.is_expired(rt.curr_epoch()) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "postbox item has not expired yet"
+                ));
+            }
+
+            st.remove_from_postbox(rt.store(), postbox_cid, |a| rt.resolve_address(a))?;
+            Ok(postbox_item.cross_msg)
+        })?;
+
+        // receipts are never refunded, as their sender is a gateway.
+        if cross_msg.msg.from.raw_addr().ok() == Some(rt.message().receiver()) {
+            return Ok(());
+        }
+
+        let receipt = CrossMsgReceipt {
+            msg: cross_msg.msg,
+            exit_code: POSTBOX_EXPIRED_EXIT_CODE,
+            return_data: RawBytes::default(),
+        };
+        Self::send_receipt(rt, &receipt, cross_msg.reply_method)
+    }
+
+    /// Commits the validator set of a child subnet as a top-down message for its
+    /// gateway, so the membership of the child is updated on execution of the
+    /// top-down checkpoint including it.
+    ///
+    /// It is called by the subnet actor of a registered subnet every time its
+    /// validator set changes. Frozen subnets don't run anymore, so their changes
+    /// are ignored and validators can still leave them.
+    fn commit_membership(
+        rt: &mut impl Runtime,
+        validator_set: ValidatorSet,
+    ) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+        let gateway = rt.message().receiver();
+        rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt, &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            if matches!(sub, Some(sub) if sub.status == Status::Frozen) {
+                return Ok(());
+            }
+            let mut msg = membership_msg(&shid, &gateway, &validator_set)?;
+            st.commit_topdown_msg(rt, &mut msg).map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                    "error committing membership message",
+                )
+            })
+        })
+    }
+
     /// Set the memberships of the validators
     ///
     /// This call is expected to be performed implicitly by all validators
@@ -706,8 +1166,10 @@ impl Actor {
         validator_set: ValidatorSet,
     ) -> Result<RawBytes, ActorError> {
         rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])?;
-        let network_name = rt.transaction(|st: &mut State, _| {
-            st.set_membership(validator_set.clone());
+        let network_name = rt.transaction(|st: &mut State, rt| {
+            st.set_membership(rt, validator_set.clone()).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to set membership")
+            })?;
             Ok(st.network_name.clone())
         })?;
 
@@ -729,6 +1191,41 @@ impl Actor {
         Ok(RawBytes::default())
     }
 
+    /// Records the epoch of the latest bottom-up checkpoint executed in the parent,
+    /// bounding the windows that can be pruned.
+    ///
+    /// This call is expected to be performed implicitly by all validators when
+    /// the execution of a checkpoint is observed in the subnet actor of the parent.
+    fn set_executed_checkpoint(
+        rt: &mut impl Runtime,
+        params: ExecutedCheckpointParams,
+    ) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])?;
+        rt.transaction(|st: &mut State, rt| {
+            if params.epoch < st.bottomup_executed_epoch {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "executed checkpoint epoch can't decrease"
+                ));
+            }
+            // the window being populated can't have been executed yet
+            if params.epoch
+                >= window_epoch(
+                    rt.curr_epoch(),
+                    st.genesis_epoch(),
+                    st.bottomup_check_period,
+                )
+            {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "checkpoint window still being populated"
+                ));
+            }
+            st.bottomup_executed_epoch = params.epoch;
+            Ok(())
+        })
+    }
+
     /// Set the memberships of the validators
     fn init_genesis_epoch(
         rt: &mut impl Runtime,
@@ -739,36 +1236,232 @@ impl Actor {
             if st.initialized {
                 return Err(actor_error!(illegal_state, "subnet already initialized"));
             }
-            st.init_gateway(rt.store(), params.genesis_epoch)?;
+            st.init_gateway(
+                rt.store(),
+                params.genesis_epoch,
+                params.bottomup_genesis_epoch,
+            )?;
             Ok(RawBytes::default())
         })
     }
 
-    /// Submit a new topdown checkpoint
-    ///
-    /// It only accepts submission at multiples of `topdown_check_period` since `genesis_epoch`, which are
-    /// set during construction. Each checkpoint will have its number of submissions tracked. The
-    /// same address cannot submit twice. Once the number of submissions is more than or equal to 2/3
-    /// of the total number of validators, the messages will be applied.
-    ///
-    /// Each topdown checkpoint will be checked against each other using blake hashing.
-    fn submit_topdown_check(
-        rt: &mut impl Runtime,
-        checkpoint: TopDownCheckpoint,
-    ) -> Result<RawBytes, ActorError> {
-        // submit topdown can only be performed by signable addresses
-        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+    /// Updates the fee policy for cross-messages. It can only be
+    /// called by the governor of the gateway.
+    fn set_cross_msg_fees(rt: &mut impl Runtime, fees: CrossMsgFees) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.governor])?;
 
-        let to_execute = rt.transaction(|st: &mut State, rt| {
-            st.require_initialized()?;
-            let submitter = rt.message().caller();
-            let submitter_weight = Self::validate_submitter(st, rt, &submitter)?;
-            let store = rt.store();
+        rt.transaction(|st: &mut State, _| {
+            st.cross_msg_fees = fees;
+            Ok(())
+        })
+    }
 
-            let epoch = checkpoint.epoch;
-            let total_weight = st.validators.total_weight.clone();
-            let ch = st
-                .topdown_checkpoint_voting
+    /// Updates the limits on the size of the bottom-up checkpoints. It can only
+    /// be called by the governor of the gateway.
+    fn set_checkpoint_limits(
+        rt: &mut impl Runtime,
+        limits: CheckpointLimits,
+    ) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.governor])?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.bottomup_checkpoint_limits = limits;
+            Ok(())
+        })
+    }
+
+    /// Sets the limits on the funds moving through a child subnet. It can only
+    /// be called by the governor of the gateway.
+    fn set_subnet_limits(
+        rt: &mut impl Runtime,
+        params: SetSubnetLimitsParams,
+    ) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.governor])?;
+
+        rt.transaction(|st: &mut State, rt| {
+            let mut sub = st
+                .get_subnet(rt, &params.subnet)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?
+                .ok_or_else(|| {
+                    actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        params.subnet
+                    )
+                })?;
+            sub.limits = params.limits;
+            st.flush_subnet(rt.store(), &sub).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing subnet")
+            })
+        })
+    }
+
+    /// Applies the bottom-up messages of a subnet held for exceeding its release limit.
+    ///
+    /// Anyone can release the held messages that fit in the limit of the current
+    /// checkpoint window, while the governor can force the release of all of them.
+    fn release_held_msgs(
+        rt: &mut impl Runtime,
+        params: ReleaseHeldMsgsParams,
+    ) -> Result<(), ActorError> {
+        if params.force {
+            let st: State = rt.state()?;
+            rt.validate_immediate_caller_is([&st.governor])?;
+        } else {
+            rt.validate_immediate_caller_accept_any()?;
+        }
+        rt.state::<State>()?.require_not_paused()?;
+
+        let cross_msgs = rt.transaction(|st: &mut State, rt| {
+            let mut sub = st
+                .get_subnet(rt, &params.subnet)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?
+                .ok_or_else(|| {
+                    actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        params.subnet
+                    )
+                })?;
+            let window = window_epoch(
+                rt.curr_epoch(),
+                st.genesis_epoch(),
+                st.bottomup_check_period,
+            );
+            let cross_msgs = sub
+                .release_bottomup_msgs(rt.store(), window, Vec::new(), params.force)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "error releasing bottom-up messages",
+                    )
+                })?;
+            st.flush_subnet(rt.store(), &sub).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing subnet")
+            })?;
+            Ok(cross_msgs)
+        })?;
+
+        for cross_msg in cross_msgs {
+            Self::apply_msg_inner(rt, &params.subnet, cross_msg)?;
+        }
+        Ok(())
+    }
+
+    /// Pauses the gateway, blocking any method moving funds through it until it
+    /// is unpaused. It can only be called by the guardian of the gateway.
+    fn pause(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.guardian])?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.paused = true;
+            Ok(())
+        })
+    }
+
+    /// Unpauses the gateway. It can only be called by the guardian of the gateway.
+    fn unpause(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.guardian])?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.paused = false;
+            Ok(())
+        })
+    }
+
+    /// Migrates the state stored by a previous version of the actor to the layout
+    /// of the current one. It is invoked by the system actor after a code upgrade,
+    /// and it is a no-op if the state is already up to date.
+    fn upgrade_state(rt: &mut impl Runtime, params: UpgradeStateParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])?;
+
+        let root = rt.get_state_root()?;
+        let upgraded = migrate_state(rt.store(), &root, &params, rt.curr_epoch(), |addr| {
+            rt.resolve_address(addr)
+        })
+        .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to upgrade state"))?;
+        if let Some(root) = upgraded {
+            log::info!(
+                "upgraded gateway state to version {}",
+                upgrade::STATE_VERSION
+            );
+            rt.set_state_root(&root)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the bottom-up checkpoint windows up to the epoch provided. It can
+    /// only be called by the governor of the gateway once the checkpoints for
+    /// these windows have been executed in the parent.
+    fn prune_bottomup_checkpoints(
+        rt: &mut impl Runtime,
+        params: PruneCheckpointsParams,
+    ) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.governor])?;
+
+        rt.transaction(|st: &mut State, rt| {
+            // the window currently being populated can't be pruned
+            if params.epoch
+                >= window_epoch(
+                    rt.curr_epoch(),
+                    st.genesis_epoch(),
+                    st.bottomup_check_period,
+                )
+            {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "can't prune the checkpoint window being populated"
+                ));
+            }
+            if params.epoch > st.prunable_epoch() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "can't prune checkpoints not executed in the parent"
+                ));
+            }
+            st.prune_bottomup_checkpoints(rt.store(), params.epoch)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error pruning checkpoints")
+                })
+        })
+    }
+
+    /// Submit a new topdown checkpoint
+    ///
+    /// It only accepts submission at multiples of `topdown_check_period` since `genesis_epoch`, which are
+    /// set during construction. Each checkpoint will have its number of submissions tracked. The
+    /// same address cannot submit twice. Once the number of submissions is more than or equal to 2/3
+    /// of the total number of validators, the messages will be applied.
+    ///
+    /// Each topdown checkpoint will be checked against each other using blake hashing.
+    fn submit_topdown_check(
+        rt: &mut impl Runtime,
+        checkpoint: TopDownCheckpoint,
+    ) -> Result<RawBytes, ActorError> {
+        // submit topdown can only be performed by signable addresses
+        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+        rt.state::<State>()?.require_not_paused()?;
+
+        let to_execute = rt.transaction(|st: &mut State, rt| {
+            st.require_initialized()?;
+            let submitter = rt.message().caller();
+            let submitter_weight = Self::validate_submitter(st, rt, &submitter)?;
+            let store = rt.store();
+
+            let epoch = checkpoint.epoch;
+            let total_weight = st.validators.total_weight.clone();
+            let ch = st
+                .topdown_checkpoint_voting
                 .submit_vote(
                     store,
                     checkpoint,
@@ -799,14 +1492,11 @@ impl Actor {
         // we only `execute_next_topdown_epoch(rt)` if there is no execution for the current submission
         // so that we don't blow up the gas.
         if let Some(checkpoint) = to_execute {
-            if checkpoint.top_down_msgs.is_empty() {
+            let is_empty = checkpoint.top_down_msgs.is_empty();
+            Self::execute_topdown_checkpoint(rt, checkpoint)?;
+            if is_empty {
                 Self::execute_next_topdown_epoch(rt)?;
             }
-            for m in checkpoint.top_down_msgs {
-                // we can use UNDEF here because top-down messages don't need
-                // to track the forwarder for anything when applying messages.
-                Self::apply_msg_inner(rt, &ipc_sdk::subnet_id::UNDEF, m)?;
-            }
         } else {
             Self::execute_next_topdown_epoch(rt)?;
         }
@@ -827,6 +1517,11 @@ impl Actor {
     ) -> Result<(bool, TokenAmount), ActorError> {
         let mut do_burn = false;
 
+        // the fee collected here is split together with the fee already attached
+        // to the message between the hops left in its route.
+        cross_msg.fee += &fee;
+        let fee = take_hop_fee(&st.network_name, cross_msg)?;
+
         let sto = cross_msg
             .msg
             .to
@@ -862,7 +1557,7 @@ impl Actor {
                     top_down_fee = fee;
                     st.commit_topdown_msg(rt, cross_msg)
                 } else {
-                    if cross_msg.msg.value > TokenAmount::zero() {
+                    if cross_msg.msg.value > TokenAmount::zero() || !cross_msg.fee.is_zero() {
                         do_burn = true;
                     }
                     st.commit_bottomup_msg(rt.store(), cross_msg, rt.curr_epoch(), &fee)
@@ -878,7 +1573,6 @@ impl Actor {
                 Ok((do_burn, top_down_fee))
             }
             IPCMsgType::TopDown => {
-                st.applied_topdown_nonce += 1;
                 st.commit_topdown_msg(rt, cross_msg).map_err(|e| {
                     e.downcast_default(
                         ExitCode::USR_ILLEGAL_STATE,
@@ -891,6 +1585,115 @@ impl Actor {
     }
 }
 
+/// Read-only methods to query the state of the gateway
+impl Actor {
+    /// Returns the subnet registered with the given id, if any.
+    fn get_subnet(rt: &mut impl Runtime, id: SubnetID) -> Result<Option<Subnet>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let mut st: State = rt.state()?;
+        st.get_subnet(rt, &id)
+            .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet"))
+    }
+
+    /// Returns a page of the subnets registered in the gateway.
+    fn list_subnets(
+        rt: &mut impl Runtime,
+        params: ListSubnetsParams,
+    ) -> Result<ListSubnetsReturn, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let limit = std::cmp::min(params.limit, MAX_QUERY_LIMIT);
+        let (subnets, next_cursor) =
+            st.list_subnets(rt.store(), params.cursor, limit)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to list subnets")
+                })?;
+        Ok(ListSubnetsReturn {
+            subnets,
+            next_cursor,
+        })
+    }
+
+    /// Returns the bottom-up checkpoint stored for the given epoch, if any.
+    fn get_bottomup_checkpoint(
+        rt: &mut impl Runtime,
+        params: GetCheckpointParams,
+    ) -> Result<Option<BottomUpCheckpoint>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let checkpoints = st.bottomup_checkpoints.load(rt.store()).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load checkpoints")
+        })?;
+        let ch =
+            get_checkpoint(&checkpoints, params.epoch, st.bottomup_check_period).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to get checkpoint")
+            })?;
+        Ok(ch.cloned())
+    }
+
+    /// Returns the top-down messages committed for a child subnet starting
+    /// from the given nonce.
+    fn get_topdown_msgs(
+        rt: &mut impl Runtime,
+        params: GetTopDownMsgsParams,
+    ) -> Result<Vec<CrossMsg>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let mut st: State = rt.state()?;
+        let limit = std::cmp::min(params.limit, MAX_QUERY_LIMIT);
+        st.get_topdown_msgs(rt, &params.subnet, params.from_nonce, limit)
+            .map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "failed to get top-down messages",
+                )
+            })?
+            .ok_or_else(|| {
+                actor_error!(
+                    illegal_argument,
+                    "subnet with id {} not registered",
+                    params.subnet
+                )
+            })
+    }
+
+    /// Returns the postbox item stored for the given cid, if any.
+    fn get_postbox_item(
+        rt: &mut impl Runtime,
+        params: GetPostboxItemParams,
+    ) -> Result<Option<PostBoxItem>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_postbox_item(rt.store(), params.postbox_cid)
+            .map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load postbox item")
+            })
+    }
+
+    /// Lists the cids of the pending postbox items that an owner is allowed to propagate.
+    /// The owner is resolved to its ID address, which is the one items are indexed by.
+    fn list_postbox_cids(
+        rt: &mut impl Runtime,
+        params: ListPostboxCidsParams,
+    ) -> Result<ListPostboxCidsReturn, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let owner = rt.resolve_address(&params.owner).unwrap_or(params.owner);
+        let limit = std::cmp::min(params.limit, MAX_QUERY_LIMIT);
+        let (cids, next_cursor) = st
+            .list_postbox_cids(rt.store(), &owner, params.cursor, limit)
+            .map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to list postbox cids")
+            })?;
+        Ok(ListPostboxCidsReturn { cids, next_cursor })
+    }
+}
+
 /// All the validator code for the actor calls
 impl Actor {
     /// Validate the submitter's submission against the state, also returns the weight of the validator
@@ -901,6 +1704,9 @@ impl Actor {
     ) -> Result<TokenAmount, ActorError> {
         st.validators
             .get_validator_weight(rt, submitter)
+            .map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load validators")
+            })?
             .ok_or_else(|| actor_error!(illegal_argument, "caller not validator"))
     }
 }
@@ -910,11 +1716,151 @@ impl Actor {
     /// Applies a cross-net messages coming from some other subnet.
     /// The `forwarder` argument determines the previous subnet that submitted
     /// the checkpoint triggering the cross-net message execution.
+    ///
+    /// Every message holds the subsequent nonce of the messages received from
+    /// `forwarder`, whether it is executed here or parked in the postbox to be
+    /// propagated, so the nonce is increased in both cases.
     fn apply_msg_inner(
         rt: &mut impl Runtime,
         forwarder: &SubnetID,
         cross_msg: CrossMsg,
     ) -> Result<RawBytes, ActorError> {
+        let st: State = rt.state()?;
+        let msg_type = match cross_msg.msg.apply_type(&st.network_name) {
+            Ok(msg_type) => msg_type,
+            Err(_) => {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "cross-message to apply dosen't have the right type"
+                ))
+            }
+        };
+
+        let apply = match msg_type {
+            IPCMsgType::BottomUp => rt.transaction(|st: &mut State, rt| {
+                // get applied bottom-up nonce from subnet
+                match st.get_subnet(rt, forwarder)
+                    .map_err(|_| actor_error!(illegal_argument, "error getting subnet from store in bottom-up execution"))?{
+                        Some(mut sub) => {
+                            if sub.applied_bottomup_nonce > cross_msg.msg.nonce {
+                                return Err(actor_error!(
+                                    illegal_state,
+                                    "the bottom-up message being applied for subnet has already been applied"
+                                ));
+                            }
+                            // messages ahead of the subsequent nonce are held until the gap is filled
+                            if sub.applied_bottomup_nonce < cross_msg.msg.nonce {
+                                st.insert_pending_bottomup_msg(rt.store(), forwarder, cross_msg.clone())
+                                    .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error buffering bottom-up message"))?;
+                                return Ok(false);
+                            }
+
+                            // increase the nonce after application
+                            sub.increase_applied_bottomup(rt, st)
+                                .map_err(|_| actor_error!(illegal_argument, "error increasing applied_bottomup_nonce in execution"))?;
+                        },
+                        None => {
+                            return Err(actor_error!(
+                                illegal_state,
+                                "we can execute a bottom-up message for a subnet that is not registered"
+                            ));
+                        }
+                };
+
+                Ok(true)
+            })?,
+            IPCMsgType::TopDown => {
+                rt.transaction(|st: &mut State, rt| {
+                    if st.applied_topdown_nonce > cross_msg.msg.nonce {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "the top-down message being applied has already been applied"
+                        ));
+                    }
+                    // messages ahead of the subsequent nonce are held until the gap
+                    // is filled, their funds are minted once they are applied.
+                    if st.applied_topdown_nonce < cross_msg.msg.nonce {
+                        st.insert_pending_topdown_msg(rt.store(), cross_msg.clone())
+                            .map_err(|e| {
+                                e.downcast_default(
+                                    ExitCode::USR_ILLEGAL_STATE,
+                                    "error buffering top-down message",
+                                )
+                            })?;
+                        return Ok(false);
+                    }
+
+                    st.increase_applied_topdown_nonce(rt.store(), rt.curr_epoch())
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error increasing applied_topdown_nonce",
+                            )
+                        })?;
+                    Ok(true)
+                })?
+            }
+        };
+
+        let ret = match (apply, msg_type) {
+            (false, _) => RawBytes::default(),
+            (true, IPCMsgType::BottomUp) => Self::deliver_msg(rt, cross_msg)?,
+            (true, IPCMsgType::TopDown) => Self::deliver_topdown_msg(rt, cross_msg)?,
+        };
+        // buffered messages are drained in batches, keep applying them
+        // even if this message was buffered.
+        match msg_type {
+            IPCMsgType::BottomUp => Self::apply_pending_bottomup_msgs(rt, forwarder)?,
+            IPCMsgType::TopDown => Self::apply_pending_topdown_msgs(rt)?,
+        }
+        Ok(ret)
+    }
+
+    /// Mints the funds of a top-down message holding the subsequent nonce and
+    /// delivers it.
+    fn deliver_topdown_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+    ) -> Result<RawBytes, ActorError> {
+        // Mint funds for the gateway, as any topdown message
+        // including tokens traversing the subnet will use
+        // some balance to increase the circ_supply.
+        // If a treasury is configured, the funds are unlocked from it
+        // within its limits, so exploiting a vulnerability in the gateway
+        // can't drain the whole balance provisioned to mint new circulating
+        // supply. A message over the limits fails and its value is refunded,
+        // so the rest of the checkpoint is still applied. Otherwise, check if
+        // the gateway has enough funds to mint new FIL, if not fail right-away
+        // and do not allow the execution of the message.
+        // The fee left for the hops ahead is minted with the value.
+        let st: State = rt.state()?;
+        let minted = cross_msg.msg.value.clone() + &cross_msg.fee;
+        match st.treasury {
+            Some(treasury) => {
+                if minted > TokenAmount::zero() {
+                    if let Err(e) = unlock_from_treasury(rt, &treasury, &minted) {
+                        log::info!("treasury refused to unlock cross-message funds: {:?}", e);
+                        Self::reject_unminted_msg(rt, cross_msg, e.exit_code())?;
+                        return Ok(RawBytes::default());
+                    }
+                }
+            }
+            None => {
+                if rt.current_balance() < minted {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "not enough balance to mint new tokens as part of the cross-message"
+                    ));
+                }
+            }
+        }
+        Self::deliver_msg(rt, cross_msg)
+    }
+
+    /// Delivers a cross-message holding the subsequent nonce of its sequence.
+    /// Messages directed to the current subnet are executed, the rest are parked
+    /// in the postbox to be propagated, returning the cid of the postbox item.
+    fn deliver_msg(rt: &mut impl Runtime, cross_msg: CrossMsg) -> Result<RawBytes, ActorError> {
         let rto = match cross_msg.msg.to.raw_addr() {
             Ok(to) => to,
             Err(_) => {
@@ -935,86 +1881,18 @@ impl Actor {
         };
 
         let st: State = rt.state()?;
+        let event = GatewayEvent::ApplyMsg(cross_msg_event(&cross_msg, &TokenAmount::zero())?);
 
         log::debug!("sto: {:?}, network: {:?}", sto, st.network_name);
 
-        match cross_msg.msg.apply_type(&st.network_name) {
-            Ok(IPCMsgType::BottomUp) => {
-                // if directed to current network, execute message.
-                if sto == st.network_name {
-                    rt.transaction(|st: &mut State, rt| {
-                        // get applied bottom-up nonce from subnet
-                        match st.get_subnet(rt, forwarder)
-                            .map_err(|_| actor_error!(illegal_argument, "error getting subnet from store in bottom-up execution"))?{
-                                Some(mut sub) => {
-                                    if sub.applied_bottomup_nonce != cross_msg.msg.nonce {
-                                        return Err(actor_error!(
-                                            illegal_state,
-                                            "the bottom-up message being applied for subnet doesn't hold the subsequent nonce"
-                                        ));
-                                    }
-
-                                    // increase the nonce after application
-                                    sub.increase_applied_bottomup(rt, st)
-                                        .map_err(|_| actor_error!(illegal_argument, "error increasing applied_bottomup_nonce in execution"))?;
-                                },
-                                None => {
-                                    return Err(actor_error!(
-                                        illegal_state,
-                                        "we can execute a bottom-up message for a subnet that is not registered"
-                                    ));
-                                }
-                        };
-
-                        Ok(())
-                    })?;
-                    return cross_msg.send(rt, &rto);
-                }
-            }
-            Ok(IPCMsgType::TopDown) => {
-                // Mint funds for the gateway, as any topdown message
-                // including tokens traversing the subnet will use
-                // some balance from the gateway to increase the circ_supply.
-                // check if the gateway has enough funds to mint new FIL,
-                // if not fail right-away and do not allow the execution of
-                // the message.
-                // TODO: It may be a good idea in the future to decouple the
-                // balance provisioned in the gateway to mint new circulating supply
-                // in an independent actor. Minting tokens would require a call to the new actor actor to unlock
-                // additional circulating supply. This prevents an attacker from being able token
-                // vulnerabilities in the gateway
-                // from draining the whole balance.
-                if rt.current_balance() < cross_msg.msg.value {
-                    return Err(actor_error!(
-                        illegal_state,
-                        "not enough balance to mint new tokens as part of the cross-message"
-                    ));
-                }
-
-                if sto == st.network_name {
-                    rt.transaction(|st: &mut State, _| {
-                        if st.applied_topdown_nonce != cross_msg.msg.nonce {
-                            return Err(actor_error!(
-                            illegal_state,
-                            "the top-down message being applied doesn't hold the subsequent nonce"
-                        ));
-                        }
-
-                        st.applied_topdown_nonce += 1;
-                        Ok(())
-                    })?;
-
-                    // We can return the send result
-                    return cross_msg.send(rt, &rto);
-                }
-            }
-            _ => {
-                return Err(actor_error!(
-                    illegal_argument,
-                    "cross-message to apply dosen't have the right type"
-                ))
-            }
-        };
+        // if directed to current network, execute message.
+        if sto == st.network_name {
+            emit_event(rt, event)?;
+            return match cross_msg.msg.apply_type(&st.network_name) {
+                Ok(IPCMsgType::TopDown) => Self::execute_topdown_msg(rt, cross_msg, &rto),
+                _ => Self::execute_msg(rt, cross_msg, &rto),
+            };
+        }
 
         let cid = rt.transaction(|st: &mut State, rt| {
             let owner = cross_msg
@@ -1023,17 +1901,280 @@ impl Actor {
                 .raw_addr()
                 .map_err(|_| actor_error!(illegal_argument, "invalid address"))?;
             let r = st
-                .insert_postbox(rt.store(), Some(vec![owner]), cross_msg)
+                .insert_postbox(
+                    rt.store(),
+                    Some(vec![owner]),
+                    cross_msg,
+                    rt.curr_epoch(),
+                    |a| rt.resolve_address(a),
+                )
                 .map_err(|e| {
                     e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error save topdown messages")
                 })?;
             Ok(r)
         })?;
+        emit_event(rt, event)?;
 
         // it is safe to just unwrap. If `transaction` fails, cid is None and wont reach here.
         Ok(RawBytes::new(cid.to_bytes()))
     }
 
+    /// Executes a top-down message directed to the current subnet.
+    fn execute_topdown_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+        rto: &Address,
+    ) -> Result<RawBytes, ActorError> {
+        let st: State = rt.state()?;
+        // membership updates committed by the subnet actor in the parent
+        // are applied by the gateway itself.
+        if is_membership_msg(&cross_msg.msg, &st.network_name, &rt.message().receiver()) {
+            return Self::apply_membership_msg(rt, &cross_msg);
+        }
+        Self::execute_msg(rt, cross_msg, rto)
+    }
+
+    /// Applies up to `MAX_PENDING_MSGS_APPLIED` pending top-down messages that follow
+    /// the ones already applied.
+    fn apply_pending_topdown_msgs(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        for _ in 0..MAX_PENDING_MSGS_APPLIED {
+            let cross_msg = rt.transaction(|st: &mut State, rt| {
+                let cross_msg = st
+                    .take_pending_topdown_msg(rt.store(), st.applied_topdown_nonce)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error loading pending top-down message",
+                        )
+                    })?;
+                if cross_msg.is_some() {
+                    st.increase_applied_topdown_nonce(rt.store(), rt.curr_epoch())
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error increasing applied_topdown_nonce",
+                            )
+                        })?;
+                }
+                Ok(cross_msg)
+            })?;
+            match cross_msg {
+                Some(cross_msg) => Self::deliver_topdown_msg(rt, cross_msg)?,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Applies up to `MAX_PENDING_MSGS_APPLIED` pending bottom-up messages from
+    /// `subnet` that follow the ones already applied.
+    fn apply_pending_bottomup_msgs(
+        rt: &mut impl Runtime,
+        subnet: &SubnetID,
+    ) -> Result<(), ActorError> {
+        for _ in 0..MAX_PENDING_MSGS_APPLIED {
+            let cross_msg = rt.transaction(|st: &mut State, rt| {
+                let mut sub = st
+                    .get_subnet(rt, subnet)
+                    .map_err(|e| {
+                        e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                    })?
+                    .ok_or_else(|| {
+                        actor_error!(illegal_argument, "subnet with id {} not registered", subnet)
+                    })?;
+                let cross_msg = st
+                    .take_pending_bottomup_msg(rt.store(), subnet, sub.applied_bottomup_nonce)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error loading pending bottom-up message",
+                        )
+                    })?;
+                if cross_msg.is_some() {
+                    sub.increase_applied_bottomup(rt, st).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error increasing applied_bottomup_nonce in execution",
+                        )
+                    })?;
+                }
+                Ok(cross_msg)
+            })?;
+            match cross_msg {
+                Some(cross_msg) => Self::deliver_msg(rt, cross_msg)?,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Applies the validator set committed by the subnet actor in the parent.
+    /// Sets that are not newer than the current one, like the one the
+    /// gateway was initialized with at genesis, are ignored.
+    fn apply_membership_msg(
+        rt: &mut impl Runtime,
+        cross_msg: &CrossMsg,
+    ) -> Result<RawBytes, ActorError> {
+        let validator_set: ValidatorSet =
+            cbor::deserialize(&cross_msg.msg.params, "validator set")?;
+        rt.transaction(|st: &mut State, rt| {
+            if validator_set.config_number() > st.validators.validators.config_number() {
+                st.set_membership(rt, validator_set).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to set membership")
+                })?;
+            }
+            Ok(())
+        })?;
+        Ok(RawBytes::default())
+    }
+
+    /// Executes a cross-message directed to the current subnet.
+    ///
+    /// If the execution fails, the failure is recorded and a receipt cross-message
+    /// refunding the value of the message is routed back to its sender, so a single
+    /// failing message doesn't prevent the checkpoint including it from being executed.
+    /// If the sender requested a reply, the receipt with the result of a successful
+    /// execution is also routed back to it.
+    fn execute_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+        rto: &Address,
+    ) -> Result<RawBytes, ActorError> {
+        // receipts are sent by gateways and never get a receipt back, so they
+        // don't bounce between subnets indefinitely.
+        let is_receipt = cross_msg.msg.from.raw_addr().ok() == Some(rt.message().receiver());
+
+        match cross_msg.clone().send(rt, rto) {
+            Ok(ret) => {
+                if let (Some(method), false) = (cross_msg.reply_method, is_receipt) {
+                    let receipt = CrossMsgReceipt {
+                        msg: cross_msg.msg,
+                        exit_code: ExitCode::OK,
+                        return_data: ret.clone(),
+                    };
+                    Self::send_receipt(rt, &receipt, Some(method))?;
+                }
+                Ok(ret)
+            }
+            Err(e) => {
+                log::info!("cross-message failed to execute: {:?}", e);
+                let receipt = Self::record_failed_msg(rt, &cross_msg, e.exit_code())?;
+                if !is_receipt {
+                    Self::send_receipt(rt, &receipt, cross_msg.reply_method)?;
+                }
+                Ok(RawBytes::default())
+            }
+        }
+    }
+
+    /// Fails a top-down message whose funds the treasury refused to unlock. Its
+    /// value was never minted in this subnet, so it is refunded to the sender
+    /// without burning it.
+    fn reject_unminted_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+        exit_code: ExitCode,
+    ) -> Result<(), ActorError> {
+        let receipt = Self::record_failed_msg(rt, &cross_msg, exit_code)?;
+        // receipts are never refunded, as their sender is a gateway.
+        if cross_msg.msg.from.raw_addr().ok() == Some(rt.message().receiver()) {
+            return Ok(());
+        }
+        if !receipt.msg.value.is_zero() {
+            Self::commit_receipt_msg(
+                rt,
+                |from| StorableMsg::new_refund_msg(from, &receipt),
+                false,
+            )?;
+        }
+        if let Some(method) = cross_msg.reply_method {
+            Self::commit_receipt_msg(
+                rt,
+                |from| StorableMsg::new_receipt_msg(from, &receipt, method),
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records the receipt of a cross-message that failed with `exit_code`.
+    fn record_failed_msg(
+        rt: &mut impl Runtime,
+        cross_msg: &CrossMsg,
+        exit_code: ExitCode,
+    ) -> Result<CrossMsgReceipt, ActorError> {
+        let cid = cross_msg_cid(cross_msg)?;
+        let receipt = CrossMsgReceipt {
+            msg: cross_msg.msg.clone(),
+            exit_code,
+            return_data: RawBytes::default(),
+        };
+        rt.transaction(|st: &mut State, rt| {
+            st.record_failed_msg(rt.store(), cid, receipt.clone())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error recording failed msg")
+                })
+        })?;
+        Ok(receipt)
+    }
+
+    /// Sends back to the sender of the message `receipt` refers to the value of the
+    /// message, if it failed, and the receipt itself, if it requested a reply.
+    ///
+    /// The value is refunded with a plain transfer and the receipt is delivered to
+    /// `reply_method` as a separate zero-value call, so a failing callback can't
+    /// leave the refund stuck in the gateway.
+    fn send_receipt(
+        rt: &mut impl Runtime,
+        receipt: &CrossMsgReceipt,
+        reply_method: Option<MethodNum>,
+    ) -> Result<(), ActorError> {
+        if !receipt.exit_code.is_success() && !receipt.msg.value.is_zero() {
+            Self::commit_receipt_msg(rt, |from| StorableMsg::new_refund_msg(from, receipt), true)?;
+        }
+        if let Some(method) = reply_method {
+            Self::commit_receipt_msg(
+                rt,
+                |from| StorableMsg::new_receipt_msg(from, receipt, method),
+                true,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Commits a cross-message from the gateway built by `new_msg` from the
+    /// address of the gateway. The value of bottom-up messages is burnt if
+    /// `burn` is set, that is, if it was minted in this subnet.
+    fn commit_receipt_msg(
+        rt: &mut impl Runtime,
+        new_msg: impl FnOnce(IPCAddress) -> anyhow::Result<StorableMsg>,
+        burn: bool,
+    ) -> Result<(), ActorError> {
+        let gateway = rt.message().receiver();
+        let (receipt_msg, do_burn) = rt.transaction(|st: &mut State, rt| {
+            let from = IPCAddress::new(&st.network_name, &gateway).map_err(|_| {
+                actor_error!(illegal_argument, "error setting IPC address in receipt")
+            })?;
+            let mut receipt_msg = CrossMsg {
+                msg: new_msg(from).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error creating receipt")
+                })?,
+                wrapped: false,
+                reply_method: None,
+                fee: TokenAmount::zero(),
+            };
+            let do_burn = st.commit_gateway_msg(rt, &mut receipt_msg).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error committing receipt")
+            })?;
+            Ok((receipt_msg, do_burn))
+        })?;
+
+        if do_burn && burn {
+            burn_bu_funds(rt, receipt_msg.msg.value)?;
+        }
+        Ok(())
+    }
+
     /// Execute the next approved topdown checkpoint.
     /// This is an edge case to ensure none of the epoches will be stuck. Consider the following example:
     ///
@@ -1066,11 +2207,36 @@ impl Actor {
         })?;
 
         if let Some(checkpoint) = checkpoint {
-            for m in checkpoint.top_down_msgs {
-                // we can use UNDEF here because top-down messages don't need
-                // to track the forwarder for anything when applying messages.
-                Self::apply_msg_inner(rt, &ipc_sdk::subnet_id::UNDEF, m)?;
-            }
+            Self::execute_topdown_checkpoint(rt, checkpoint)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the top-down messages of a checkpoint that reached consensus.
+    fn execute_topdown_checkpoint(
+        rt: &mut impl Runtime,
+        checkpoint: TopDownCheckpoint,
+    ) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        let value = checkpoint
+            .top_down_msgs
+            .iter()
+            .fold(TokenAmount::zero(), |value, m| value + &m.msg.value);
+        emit_event(
+            rt,
+            GatewayEvent::SubmitTopDownCheck(CheckpointEvent {
+                subnet: st.network_name,
+                epoch: checkpoint.epoch,
+                num_msgs: checkpoint.top_down_msgs.len() as u64,
+                value,
+                fee: TokenAmount::zero(),
+            }),
+        )?;
+
+        for m in checkpoint.top_down_msgs {
+            // we can use UNDEF here because top-down messages don't need
+            // to track the forwarder for anything when applying messages.
+            Self::apply_msg_inner(rt, &ipc_sdk::subnet_id::UNDEF, m)?;
         }
         Ok(())
     }
@@ -1094,5 +2260,26 @@ impl ActorCode for Actor {
         SubmitTopDownCheckpoint => submit_topdown_check,
         SetMembership => set_membership,
         InitGenesisEpoch => init_genesis_epoch,
+        GetSubnet => get_subnet,
+        ListSubnets => list_subnets,
+        GetBottomUpCheckpoint => get_bottomup_checkpoint,
+        GetTopDownMsgs => get_topdown_msgs,
+        GetPostboxItem => get_postbox_item,
+        SetCrossMsgFees => set_cross_msg_fees,
+        PruneBottomUpCheckpoints => prune_bottomup_checkpoints,
+        ReclaimPostbox => reclaim_postbox,
+        ListPostboxCids => list_postbox_cids,
+        RemovePropagator => remove_propagator,
+        TransferPostboxOwnership => transfer_postbox_ownership,
+        FreezeSubnet => freeze_subnet,
+        RecoverFunds => recover_funds,
+        SetSubnetLimits => set_subnet_limits,
+        ReleaseHeldMsgs => release_held_msgs,
+        Pause => pause,
+        Unpause => unpause,
+        UpgradeState => upgrade_state,
+        CommitMembership => commit_membership,
+        SetCheckpointLimits => set_checkpoint_limits,
+        SetExecutedCheckpoint => set_executed_checkpoint,
     }
 }
@@ -0,0 +1,121 @@
+//! Recovery of the user funds locked in subnets that stopped committing checkpoints.
+//!
+//! Subnets commit in the `proof` of their bottom-up checkpoints the root of a binary
+//! merkle tree whose leaves are the balances of their accounts. Once a subnet is frozen,
+//! users can claim the balance committed in the last checkpoint of the subnet by
+//! submitting the merkle path from their leaf to that root.
+use anyhow::anyhow;
+use cid::multihash::Code;
+use cid::multihash::MultihashDigest;
+use fvm_ipld_encoding::{serde_bytes, to_vec};
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+
+/// A node in the merkle path from a balance leaf to the recovery root.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+pub struct RecoveryProofNode {
+    /// Hash of the sibling of the node in the path
+    #[serde(with = "serde_bytes")]
+    pub hash: Vec<u8>,
+    /// Whether the sibling is the left child of their parent
+    pub left: bool,
+}
+
+/// Prefixes of the hashed leaves and inner nodes, so an inner node can't be
+/// presented as the leaf of a balance.
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+fn hash(bytes: &[u8]) -> Vec<u8> {
+    Code::Blake2b256.digest(bytes).digest().to_vec()
+}
+
+fn hash_nodes(left: &[u8], right: &[u8]) -> Vec<u8> {
+    hash(&[&[NODE_PREFIX][..], left, right].concat())
+}
+
+/// Computes the leaf of the recovery tree committing the balance of an address.
+pub fn recovery_leaf(address: &Address, amount: &TokenAmount) -> anyhow::Result<Vec<u8>> {
+    let bytes = to_vec(&(address, amount))?;
+    Ok(hash(&[&[LEAF_PREFIX][..], &bytes].concat()))
+}
+
+/// Checks that `proof` is a valid merkle path from the leaf of `address` and
+/// `amount` to the recovery `root`.
+pub fn verify_recovery_proof(
+    root: &[u8],
+    address: &Address,
+    amount: &TokenAmount,
+    proof: &[RecoveryProofNode],
+) -> anyhow::Result<bool> {
+    let node = proof
+        .iter()
+        .try_fold(recovery_leaf(address, amount)?, |node, sibling| {
+            Ok::<_, anyhow::Error>(match sibling.left {
+                true => hash_nodes(&sibling.hash, &node),
+                false => hash_nodes(&node, &sibling.hash),
+            })
+        })?;
+    Ok(node == root)
+}
+
+/// Computes the recovery root of a list of balances along with the merkle path of
+/// the balance at `index`. When a level has an odd number of nodes, the last one
+/// is paired with itself.
+pub fn recovery_root(
+    balances: &[(Address, TokenAmount)],
+    index: usize,
+) -> anyhow::Result<(Vec<u8>, Vec<RecoveryProofNode>)> {
+    if index >= balances.len() {
+        return Err(anyhow!("balance index out of bounds"));
+    }
+
+    let mut level = balances
+        .iter()
+        .map(|(address, amount)| recovery_leaf(address, amount))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut index = index;
+    let mut proof = Vec::new();
+    while level.len() > 1 {
+        let sibling = match index % 2 {
+            0 => level.get(index + 1).unwrap_or(&level[index]),
+            _ => &level[index - 1],
+        };
+        proof.push(RecoveryProofNode {
+            hash: sibling.clone(),
+            left: index % 2 == 1,
+        });
+
+        level = level
+            .chunks(2)
+            .map(|pair| hash_nodes(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    Ok((level.remove(0), proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recovery::{recovery_root, verify_recovery_proof};
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+
+    #[test]
+    fn test_recovery_proof() {
+        let balances: Vec<_> = (0..5)
+            .map(|i| (Address::new_id(100 + i), TokenAmount::from_atto(1000 * i)))
+            .collect();
+
+        for (i, (address, amount)) in balances.iter().enumerate() {
+            let (root, proof) = recovery_root(&balances, i).unwrap();
+            assert!(verify_recovery_proof(&root, address, amount, &proof).unwrap());
+            // the proof doesn't hold for a different amount
+            let other = amount + TokenAmount::from_atto(1);
+            assert!(!verify_recovery_proof(&root, address, &other, &proof).unwrap());
+        }
+
+        assert!(recovery_root(&balances, 5).is_err());
+    }
+}
@@ -5,22 +5,23 @@ use cid::Cid;
 use fil_actors_runtime::runtime::Runtime;
 use fil_actors_runtime::{actor_error, ActorDowncast, ActorError, Map};
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::to_vec;
 use fvm_ipld_hamt::BytesKey;
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
-use ipc_sdk::epoch_key;
 use lazy_static::lazy_static;
 use num_traits::Zero;
-use primitives::{TCid, THamt};
+use primitives::{TAmt, TCid, THamt};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 use std::str::FromStr;
 
 use crate::checkpoint::Validators;
-use crate::TopDownCheckpoint;
+use crate::upgrade::STATE_VERSION;
+use crate::{TopDownCheckpoint, CROSSMSG_AMT_BITWIDTH};
 use ipc_actor_common::vote::Voting;
-use ipc_sdk::cross::{CrossMsg, StorableMsg};
+use ipc_sdk::cross::{CrossMsg, CrossMsgReceipt, IPCMsgType, StorableMsg};
 use ipc_sdk::subnet_id::SubnetID;
 use ipc_sdk::ValidatorSet;
 
@@ -36,15 +37,24 @@ type PostBox = TCid<THamt<Cid, Vec<u8>>>;
 /// Storage power actor state
 #[derive(Serialize_tuple, Deserialize_tuple)]
 pub struct State {
+    /// Version of the layout of the state, see `upgrade`.
+    pub version: u64,
     pub network_name: SubnetID,
     pub total_subnets: u64,
     pub min_stake: TokenAmount,
     pub subnets: TCid<THamt<SubnetID, Subnet>>,
+    /// Ids of the registered subnets in registration order, indexed by `Subnet::index`.
+    /// The positions of killed subnets are left empty.
+    pub subnet_index: TCid<TAmt<SubnetID, SUBNET_INDEX_AMT_BITWIDTH>>,
+    /// Position in `subnet_index` assigned to the next subnet registered.
+    pub next_subnet_index: u64,
     pub bottomup_check_period: ChainEpoch,
+    /// Epoch from which bottom-up checkpoint windows are counted, set when
+    /// the gateway is initialized.
+    pub bottomup_genesis_epoch: ChainEpoch,
     pub topdown_check_period: ChainEpoch,
-    // FIXME: Consider making checkpoints a HAMT instead of an AMT so we use
-    // the AMT index instead of and epoch k for object indexing.
-    pub bottomup_checkpoints: TCid<THamt<ChainEpoch, BottomUpCheckpoint>>,
+    /// Bottom-up checkpoint windows indexed by `checkpoint_index`.
+    pub bottomup_checkpoints: TCid<TAmt<BottomUpCheckpoint, CHECKPOINT_AMT_BITWIDTH>>,
     /// `postbox` keeps track for an EOA of all the cross-net messages triggered by
     /// an actor that need to be propagated further through the hierarchy.
     pub postbox: PostBox,
@@ -56,6 +66,58 @@ pub struct State {
     /// to start accepting top-down checkpoints and messages. No cross-net messages
     /// and checkpoints can be triggered if the subnet is not initialized yet.
     pub initialized: bool,
+    /// Fee policy applied to cross-messages.
+    pub cross_msg_fees: CrossMsgFees,
+    /// Address allowed to perform governance operations on the gateway.
+    pub governor: Address,
+    /// Number of bottom-up checkpoint windows to retain, older windows are
+    /// pruned as new ones are populated. Zero retains all windows.
+    pub bottomup_checkpoint_retention: u64,
+    /// Epoch of the latest bottom-up checkpoint window pruned from `bottomup_checkpoints`,
+    /// `None` if no window has been pruned yet.
+    pub bottomup_pruned_epoch: Option<ChainEpoch>,
+    /// Epoch of the latest bottom-up checkpoint executed in the parent, as relayed from
+    /// the `previous_executed_checkpoint_cid` of the subnet actor. Only the windows up
+    /// to this epoch can be pruned.
+    pub bottomup_executed_epoch: ChainEpoch,
+    /// Receipts of the cross-messages that failed to execute in this subnet,
+    /// keyed by the cid of the failed message.
+    pub failed_msgs: TCid<THamt<Cid, CrossMsgReceipt>>,
+    /// Number of epochs after which postbox items can be reclaimed. Zero means
+    /// that they never expire.
+    pub postbox_ttl: ChainEpoch,
+    /// Index of the cids of the pending postbox items each owner, by its ID address when
+    /// it can be resolved, is allowed to propagate. Items without owners can be propagated
+    /// by anyone and are not indexed.
+    pub postbox_owners: TCid<THamt<Address, PostboxOwnerIndex>>,
+    /// Number of epochs without committing a checkpoint after which a subnet can be frozen.
+    pub recovery_deadline: ChainEpoch,
+    /// Funds recovered from frozen subnets, keyed by the subnet id and the owner address.
+    pub recovered_funds: TCid<THamt<BytesKey, TokenAmount>>,
+    /// Address allowed to pause and unpause the gateway.
+    pub guardian: Address,
+    /// `paused` determines if the gateway is in emergency pause mode. No funds
+    /// can be moved through the gateway while it is paused.
+    pub paused: bool,
+    /// Treasury actor unlocking the circulating supply minted for top-down
+    /// messages. If `None`, it is minted from the balance of the gateway.
+    pub treasury: Option<Address>,
+    /// Limits on the size of the bottom-up checkpoints.
+    pub bottomup_checkpoint_limits: CheckpointLimits,
+    /// Epoch of the latest checkpoint window bottom-up messages were stored in.
+    /// Messages are never stored in an earlier window, so their nonces are
+    /// contiguous across windows when full windows overflow into the next ones.
+    pub bottomup_msgs_window: ChainEpoch,
+    /// Size in bytes of the encoded cross-messages stored in the checkpoint of
+    /// `bottomup_msgs_window`, checked against `bottomup_checkpoint_limits`.
+    pub bottomup_msgs_window_bytes: u64,
+    /// Top-down messages received ahead of `applied_topdown_nonce`,
+    /// indexed by nonce until the gap is filled.
+    pub pending_topdown_msgs: TCid<TAmt<CrossMsg, CROSSMSG_AMT_BITWIDTH>>,
+    /// Bottom-up messages received ahead of the `applied_bottomup_nonce`
+    /// of their subnet, keyed by the bytes of the subnet id followed by
+    /// the big-endian nonce (see `pending_msg_key`).
+    pub pending_bottomup_msgs: TCid<THamt<BytesKey, CrossMsg>>,
 }
 
 lazy_static! {
@@ -65,19 +127,23 @@ lazy_static! {
 impl State {
     pub fn new<BS: Blockstore>(store: &BS, params: ConstructorParams) -> anyhow::Result<State> {
         Ok(State {
+            version: STATE_VERSION,
             network_name: SubnetID::from_str(&params.network_name)?,
             total_subnets: Default::default(),
             min_stake: MIN_SUBNET_COLLATERAL.clone(),
             subnets: TCid::new_hamt(store)?,
+            subnet_index: TCid::new_amt(store)?,
+            next_subnet_index: 0,
             bottomup_check_period: match params.bottomup_check_period > DEFAULT_CHECKPOINT_PERIOD {
                 true => params.bottomup_check_period,
                 false => DEFAULT_CHECKPOINT_PERIOD,
             },
+            bottomup_genesis_epoch: 0,
             topdown_check_period: match params.topdown_check_period > DEFAULT_CHECKPOINT_PERIOD {
                 true => params.topdown_check_period,
                 false => DEFAULT_CHECKPOINT_PERIOD,
             },
-            bottomup_checkpoints: TCid::new_hamt(store)?,
+            bottomup_checkpoints: TCid::new_amt(store)?,
             postbox: TCid::new_hamt(store)?,
             bottomup_nonce: Default::default(),
             // This way we ensure that the first message to execute has nonce= 0, if not it would expect 1 and fail for the first nonce
@@ -90,25 +156,52 @@ impl State {
                 0,
                 params.topdown_check_period,
             )?,
-            validators: Validators::new(ValidatorSet::default()),
+            validators: Validators::new(store, ValidatorSet::default(), |_| None)?,
             initialized: false,
+            cross_msg_fees: params.cross_msg_fees,
+            governor: params.governor,
+            bottomup_checkpoint_retention: params.checkpoint_retention,
+            bottomup_pruned_epoch: None,
+            bottomup_executed_epoch: 0,
+            failed_msgs: TCid::new_hamt(store)?,
+            postbox_ttl: params.postbox_ttl,
+            postbox_owners: TCid::new_hamt(store)?,
+            recovery_deadline: params.recovery_deadline,
+            recovered_funds: TCid::new_hamt(store)?,
+            guardian: params.guardian,
+            paused: false,
+            treasury: params.treasury,
+            bottomup_checkpoint_limits: params.checkpoint_limits,
+            bottomup_msgs_window: 0,
+            bottomup_msgs_window_bytes: 0,
+            pending_topdown_msgs: TCid::new_amt(store)?,
+            pending_bottomup_msgs: TCid::new_hamt(store)?,
         })
     }
 
     /// Initializes the gateway of the subnet actor and initializates
-    /// the top-down voting
+    /// the top-down voting, and the windows of bottom-up checkpoints
     pub(crate) fn init_gateway<BS: Blockstore>(
         &mut self,
         store: &BS,
         genesis_epoch: ChainEpoch,
+        bottomup_genesis_epoch: ChainEpoch,
     ) -> Result<(), ActorError> {
         self.topdown_checkpoint_voting =
             Voting::<TopDownCheckpoint>::new(store, genesis_epoch, self.topdown_check_period)
                 .map_err(|e| actor_error!(illegal_state, e.to_string()))?;
+        self.bottomup_genesis_epoch = bottomup_genesis_epoch;
+        self.bottomup_executed_epoch = bottomup_genesis_epoch;
         self.initialized = true;
         Ok(())
     }
 
+    /// Epoch from which the bottom-up checkpoint windows of the subnet are counted,
+    /// the same as the one used by the checkpoint voting of its subnet actor.
+    pub fn genesis_epoch(&self) -> ChainEpoch {
+        self.bottomup_genesis_epoch
+    }
+
     /// requires that the subnet is initialized before continuing
     pub(crate) fn require_initialized(&self) -> Result<(), ActorError> {
         if !self.initialized {
@@ -120,6 +213,14 @@ impl State {
         Ok(())
     }
 
+    /// requires that the gateway is not paused before moving funds
+    pub(crate) fn require_not_paused(&self) -> Result<(), ActorError> {
+        if self.paused {
+            return Err(actor_error!(illegal_state, "gateway is paused"));
+        }
+        Ok(())
+    }
+
     /// Get content for a child subnet as mut.
     pub fn get_subnet(
         &mut self,
@@ -131,23 +232,90 @@ impl State {
         Ok(subnet.cloned())
     }
 
+    /// List registered subnets in registration order starting from the position
+    /// `cursor` of the subnet index, returning at most `limit` of them. It also
+    /// returns the cursor of the next page if there are more subnets left.
+    pub fn list_subnets<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<Subnet>, Option<u64>)> {
+        let index = self.subnet_index.load(store)?;
+        let subnets = self.subnets.load(store)?;
+        let mut out = Vec::new();
+        let mut cursor = cursor;
+        while cursor < self.next_subnet_index && (out.len() as u64) < limit {
+            if let Some(id) = index
+                .get(cursor)
+                .map_err(|e| anyhow!("failed to get subnet index: {:?}", e))?
+            {
+                if let Some(sub) = get_subnet(&subnets, id)? {
+                    out.push(sub.clone());
+                }
+            }
+            cursor += 1;
+        }
+
+        Ok((
+            out,
+            if cursor < self.next_subnet_index {
+                Some(cursor)
+            } else {
+                None
+            },
+        ))
+    }
+
+    /// Get the top-down messages committed for a child subnet starting
+    /// from nonce `from_nonce`, returning at most `limit` of them, or
+    /// `None` if the subnet is not registered.
+    pub fn get_topdown_msgs(
+        &mut self,
+        rt: &impl Runtime,
+        id: &SubnetID,
+        from_nonce: u64,
+        limit: u64,
+    ) -> anyhow::Result<Option<Vec<CrossMsg>>> {
+        let sub = match self.get_subnet(rt, id)? {
+            Some(sub) => sub,
+            None => return Ok(None),
+        };
+        let crossmsgs = sub.top_down_msgs.load(rt.store())?;
+
+        let mut msgs = Vec::new();
+        let end = std::cmp::min(sub.topdown_nonce, from_nonce.saturating_add(limit));
+        for nonce in from_nonce..end {
+            if let Some(msg) = crossmsgs
+                .get(nonce)
+                .map_err(|e| anyhow!("failed to get msg by nonce: {:?}", e))?
+            {
+                msgs.push(msg.clone());
+            }
+        }
+        Ok(Some(msgs))
+    }
+
     /// Register a subnet in the map of subnets and flush.
     pub(crate) fn register_subnet(
         &mut self,
         rt: &impl Runtime,
         id: &SubnetID,
+        bottomup_genesis_epoch: ChainEpoch,
     ) -> anyhow::Result<()> {
         let val = rt.message().value_received();
         if val < self.min_stake {
             return Err(anyhow!("call to register doesn't include enough funds"));
         }
 
+        let index = self.next_subnet_index;
         let inserted = self.subnets.modify(rt.store(), |subnets| {
             if get_subnet(subnets, id)?.is_some() {
                 Ok(false)
             } else {
                 let subnet = Subnet {
                     id: id.clone(),
+                    index,
                     stake: val,
                     top_down_msgs: TCid::new_amt(rt.store())?,
                     circ_supply: TokenAmount::zero(),
@@ -156,6 +324,15 @@ impl State {
                     prev_checkpoint: None,
                     applied_bottomup_nonce: 0,
                     genesis_epoch: rt.curr_epoch(),
+                    bottomup_genesis_epoch,
+                    limits: SubnetLimits::default(),
+                    release_window: 0,
+                    released_value: TokenAmount::zero(),
+                    held_msgs: TCid::new_amt(rt.store())?,
+                    held_msgs_head: 0,
+                    held_msgs_tail: 0,
+                    last_checkpoint_epoch: rt.curr_epoch(),
+                    refund_nonce: 0,
                 };
                 set_subnet(subnets, id, subnet)?;
                 Ok(true)
@@ -163,11 +340,28 @@ impl State {
         })?;
 
         if inserted {
+            self.index_subnet(rt.store(), id)?;
             self.total_subnets += 1;
         }
         Ok(())
     }
 
+    /// Append a subnet to the subnet index at position `next_subnet_index`.
+    pub(crate) fn index_subnet<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+    ) -> anyhow::Result<()> {
+        let index = self.next_subnet_index;
+        self.subnet_index.update(store, |subnets| {
+            subnets
+                .set(index, id.clone())
+                .map_err(|e| anyhow!("failed to set subnet index: {:?}", e))
+        })?;
+        self.next_subnet_index += 1;
+        Ok(())
+    }
+
     /// Remove a subnet from the map of subnets and flush.
     pub(crate) fn rm_subnet<BS: Blockstore>(
         &mut self,
@@ -178,9 +372,15 @@ impl State {
             subnets
                 .delete(&id.to_bytes())
                 .map_err(|e| e.downcast_wrap(format!("failed to delete subnet for id {id}")))
-                .map(|x| x.is_some())
+                .map(|x| x.map(|(_, sub)| sub.index))
         })?;
-        if deleted {
+        if let Some(index) = deleted {
+            self.subnet_index.update(store, |subnets| {
+                subnets
+                    .delete(index)
+                    .map_err(|e| anyhow!("failed to delete subnet index: {:?}", e))
+                    .map(|_| ())
+            })?;
             self.total_subnets -= 1;
         }
         Ok(())
@@ -202,8 +402,68 @@ impl State {
         store: &BS,
         ch: &BottomUpCheckpoint,
     ) -> anyhow::Result<()> {
-        self.bottomup_checkpoints
-            .update(store, |checkpoints| set_checkpoint(checkpoints, ch.clone()))
+        self.store_checkpoint(store, ch)?;
+
+        // garbage collect the windows out of the retention period that have
+        // already been executed in the parent
+        if self.bottomup_checkpoint_retention > 0 {
+            let retained = self.bottomup_checkpoint_retention as i64 * self.bottomup_check_period;
+            let epoch = std::cmp::min(ch.epoch() - retained, self.prunable_epoch());
+            self.prune_bottomup_checkpoints(store, epoch)?;
+        }
+        Ok(())
+    }
+
+    /// Latest epoch up to which bottom-up checkpoint windows can be pruned. The
+    /// root has no parent to execute its checkpoints, so all its windows can be pruned.
+    pub(crate) fn prunable_epoch(&self) -> ChainEpoch {
+        if self.network_name.is_root() {
+            ChainEpoch::MAX
+        } else {
+            self.bottomup_executed_epoch
+        }
+    }
+
+    /// store a checkpoint without pruning the windows out of the retention period
+    fn store_checkpoint<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        ch: &BottomUpCheckpoint,
+    ) -> anyhow::Result<()> {
+        let period = self.bottomup_check_period;
+        self.bottomup_checkpoints.update(store, |checkpoints| {
+            set_checkpoint(checkpoints, ch.clone(), period)
+        })
+    }
+
+    /// Remove all the bottom-up checkpoint windows up to `epoch` (inclusive).
+    ///
+    /// Note that caller should have checked that the checkpoints for these windows
+    /// have already been executed in the parent, see `prunable_epoch`.
+    pub(crate) fn prune_bottomup_checkpoints<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let period = self.bottomup_check_period;
+        let to = checkpoint_epoch(epoch, self.genesis_epoch(), period);
+        if to < 0 || self.bottomup_pruned_epoch >= Some(to) {
+            return Ok(());
+        }
+
+        let from = match self.bottomup_pruned_epoch {
+            Some(pruned) => checkpoint_index(pruned, period) + 1,
+            None => 0,
+        };
+        let to_index = checkpoint_index(to, period);
+        self.bottomup_checkpoints.update(store, |checkpoints| {
+            checkpoints
+                .batch_delete(from..=to_index, false)
+                .map_err(|e| anyhow!("failed to prune checkpoints: {:?}", e))?;
+            Ok(())
+        })?;
+        self.bottomup_pruned_epoch = Some(to);
+        Ok(())
     }
 
     /// get checkpoint being populated in the current window.
@@ -215,41 +475,127 @@ impl State {
         if epoch < 0 {
             return Err(anyhow!("epoch can't be negative"));
         }
-        let ch_epoch = window_epoch(epoch, self.bottomup_check_period);
+        let ch_epoch = window_epoch(epoch, self.genesis_epoch(), self.bottomup_check_period);
         let checkpoints = self.bottomup_checkpoints.load(store)?;
 
-        Ok(match get_checkpoint(&checkpoints, ch_epoch)? {
-            Some(ch) => ch.clone(),
-            None => BottomUpCheckpoint::new(self.network_name.clone(), ch_epoch),
-        })
+        Ok(
+            match get_checkpoint(&checkpoints, ch_epoch, self.bottomup_check_period)? {
+                Some(ch) => ch.clone(),
+                None => {
+                    let mut ch = BottomUpCheckpoint::new(self.network_name.clone(), ch_epoch);
+                    // signal the parent the top-down messages already applied so it can prune them.
+                    ch.data.applied_topdown_nonce = self.applied_topdown_nonce;
+                    ch
+                }
+            },
+        )
     }
 
-    /// store a cross-message in a checkpoint
+    /// Increase the applied top-down nonce after an execution, recording it in the
+    /// checkpoint of the current window and of the later windows already holding
+    /// overflowed messages. The nonce signaled by a checkpoint is frozen once its
+    /// window closes, as top-down messages applied later only update later windows.
+    pub(crate) fn increase_applied_topdown_nonce<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        self.applied_topdown_nonce += 1;
+
+        let period = self.bottomup_check_period;
+        let mut ch = self.get_window_checkpoint(store, curr_epoch)?;
+        let last = std::cmp::max(ch.epoch(), self.bottomup_msgs_window);
+        loop {
+            ch.data.applied_topdown_nonce = self.applied_topdown_nonce;
+            self.flush_checkpoint(store, &ch)?;
+            if ch.epoch() + period > last {
+                return Ok(());
+            }
+            ch = self.get_window_checkpoint(store, ch.epoch())?;
+        }
+    }
+
+    /// store a cross-message in a checkpoint. If the checkpoint of the current
+    /// window is full, the message is stored in the first following window with room.
     pub(crate) fn store_msg_in_checkpoint<BS: Blockstore>(
         &mut self,
         store: &BS,
-        cross_msg: &CrossMsg,
+        cross_msg: &mut CrossMsg,
         curr_epoch: ChainEpoch,
         fee: &TokenAmount,
     ) -> anyhow::Result<()> {
-        let mut ch = self.get_window_checkpoint(store, curr_epoch)?;
+        let period = self.bottomup_check_period;
+        let window = window_epoch(curr_epoch, self.genesis_epoch(), period);
+        // start from the latest window holding messages if it is ahead of the current one
+        let mut ch = self.get_window_checkpoint(
+            store,
+            std::cmp::max(curr_epoch, self.bottomup_msgs_window - period),
+        )?;
 
-        let mut cross_msg = cross_msg.clone();
         cross_msg.msg.nonce = self.bottomup_nonce;
-
-        ch.push_cross_msgs(cross_msg, fee);
+        let msg_bytes = to_vec(cross_msg)?.len() as u64;
+        // only the window holding the latest messages can be partially filled,
+        // the ones after it are empty.
+        let mut bytes = if ch.epoch() == self.bottomup_msgs_window {
+            self.bottomup_msgs_window_bytes
+        } else {
+            0
+        };
+        loop {
+            let msgs = ch.data.cross_msgs.cross_msgs.as_ref().map_or(0, Vec::len);
+            if self
+                .bottomup_checkpoint_limits
+                .fits(msgs as u64, bytes, msg_bytes)
+            {
+                break;
+            }
+            ch = self.get_window_checkpoint(store, ch.epoch())?;
+            bytes = 0;
+        }
+        ch.push_cross_msgs(cross_msg.clone(), fee);
 
         // increment nonce
         self.bottomup_nonce += 1;
+        self.bottomup_msgs_window = ch.epoch();
+        self.bottomup_msgs_window_bytes = bytes + msg_bytes;
 
-        // flush checkpoint
-        self.flush_checkpoint(store, &ch).map_err(|e| {
+        // flush checkpoint, windows ahead of the current one don't trigger the
+        // pruning as it is relative to the window being populated.
+        if ch.epoch() == window {
+            self.flush_checkpoint(store, &ch)
+        } else {
+            self.store_checkpoint(store, &ch)
+        }
+        .map_err(|e| {
             e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error flushing checkpoint")
         })?;
 
         Ok(())
     }
 
+    /// Checks that injecting `value` through the child subnet in the route to `id`
+    /// doesn't exceed its circulating supply cap.
+    pub(crate) fn ensure_circ_supply_cap(
+        &mut self,
+        rt: &impl Runtime,
+        id: &SubnetID,
+        value: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let child = id
+            .down(&self.network_name)
+            .ok_or_else(|| anyhow!("couldn't compute the next subnet in route"))?;
+        if let Some(sub) = self.get_subnet(rt, &child)? {
+            let cap = &sub.limits.circ_supply_cap;
+            if !cap.is_zero() && &(sub.circ_supply.clone() + value) > cap {
+                return Err(anyhow!(
+                    "funds exceed the circulating supply cap of subnet {}",
+                    child
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// commit topdown messages for their execution in the subnet
     pub(crate) fn commit_topdown_msg(
         &mut self,
@@ -272,10 +618,14 @@ impl State {
             })?;
         match sub {
             Some(mut sub) => {
+                if sub.status == Status::Frozen {
+                    return Err(anyhow!("can't direct top-down message to a frozen subnet"));
+                }
                 cross_msg.msg.nonce = sub.topdown_nonce;
                 sub.store_topdown_msg(rt.store(), cross_msg)?;
                 sub.topdown_nonce += 1;
                 sub.circ_supply += &cross_msg.msg.value;
+                sub.circ_supply += &cross_msg.fee;
                 self.flush_subnet(rt.store(), &sub)?;
             }
             None => {
@@ -291,7 +641,7 @@ impl State {
     pub(crate) fn commit_bottomup_msg<BS: Blockstore>(
         &mut self,
         store: &BS,
-        msg: &CrossMsg,
+        msg: &mut CrossMsg,
         curr_epoch: ChainEpoch,
         fee: &TokenAmount,
     ) -> anyhow::Result<()> {
@@ -300,6 +650,122 @@ impl State {
         Ok(())
     }
 
+    /// Commit a message originated in the gateway of this subnet for its propagation.
+    /// It returns true if the message is bottom-up and its funds need to be burnt.
+    pub(crate) fn commit_gateway_msg(
+        &mut self,
+        rt: &impl Runtime,
+        cross_msg: &mut CrossMsg,
+    ) -> anyhow::Result<bool> {
+        match cross_msg.msg.ipc_type()? {
+            IPCMsgType::BottomUp => {
+                self.commit_bottomup_msg(
+                    rt.store(),
+                    cross_msg,
+                    rt.curr_epoch(),
+                    &TokenAmount::zero(),
+                )?;
+                Ok(!cross_msg.msg.value.is_zero())
+            }
+            IPCMsgType::TopDown => {
+                self.commit_topdown_msg(rt, cross_msg)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Record the receipt of a cross-message that failed to execute in this subnet.
+    pub(crate) fn record_failed_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: Cid,
+        receipt: CrossMsgReceipt,
+    ) -> anyhow::Result<()> {
+        self.failed_msgs.update(store, |failed_msgs| {
+            failed_msgs
+                .set(BytesKey::from(cid.to_bytes()), receipt)
+                .map_err(|e| e.downcast_wrap(format!("failed to record failed msg {cid}")))?;
+            Ok(())
+        })
+    }
+
+    /// Get the receipt of a cross-message that failed to execute in this subnet.
+    pub fn get_failed_msg<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cid: &Cid,
+    ) -> anyhow::Result<Option<CrossMsgReceipt>> {
+        let failed_msgs = self.failed_msgs.load(store)?;
+        let receipt = failed_msgs.get(&BytesKey::from(cid.to_bytes()))?;
+        Ok(receipt.cloned())
+    }
+
+    /// Buffers a top-down message received ahead of the next nonce to apply.
+    pub(crate) fn insert_pending_topdown_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cross_msg: CrossMsg,
+    ) -> anyhow::Result<()> {
+        let nonce = cross_msg.msg.nonce;
+        self.pending_topdown_msgs.update(store, |pending| {
+            if pending.get(nonce)?.is_some() {
+                return Err(anyhow!("top-down message {} already pending", nonce));
+            }
+            pending
+                .set(nonce, cross_msg)
+                .map_err(|e| anyhow!("failed to set pending top-down msg: {:?}", e))
+        })
+    }
+
+    /// Removes the pending top-down message with `nonce` from the buffer, if any.
+    pub(crate) fn take_pending_topdown_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        nonce: u64,
+    ) -> anyhow::Result<Option<CrossMsg>> {
+        self.pending_topdown_msgs.modify(store, |pending| {
+            pending
+                .delete(nonce)
+                .map_err(|e| anyhow!("failed to delete pending top-down msg: {:?}", e))
+        })
+    }
+
+    /// Buffers a bottom-up message from `subnet` received ahead of the next
+    /// nonce to apply for the subnet.
+    pub(crate) fn insert_pending_bottomup_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        subnet: &SubnetID,
+        cross_msg: CrossMsg,
+    ) -> anyhow::Result<()> {
+        let key = pending_msg_key(subnet, cross_msg.msg.nonce);
+        self.pending_bottomup_msgs.update(store, |pending| {
+            if pending.contains_key(&key)? {
+                return Err(anyhow!(
+                    "bottom-up message {} from {} already pending",
+                    cross_msg.msg.nonce,
+                    subnet
+                ));
+            }
+            pending.set(key, cross_msg)?;
+            Ok(())
+        })
+    }
+
+    /// Removes the pending bottom-up message from `subnet` with `nonce` from the
+    /// buffer, if any.
+    pub(crate) fn take_pending_bottomup_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        subnet: &SubnetID,
+        nonce: u64,
+    ) -> anyhow::Result<Option<CrossMsg>> {
+        let key = pending_msg_key(subnet, nonce);
+        self.pending_bottomup_msgs.modify(store, |pending| {
+            Ok(pending.delete(&key)?.map(|(_, msg)| msg))
+        })
+    }
+
     /// Insert a cross message to the `postbox` before propagate can be called for the
     /// message to be propagated upwards or downwards.
     ///
@@ -309,13 +775,17 @@ impl State {
     ///              anyone can propagate this message. Allows multiple owners.
     /// * `gas` - The gas needed to propagate this message
     /// * `msg` - The actual cross msg to store in `postbox`
+    /// * `epoch` - The current epoch, from which the expiry of the item is counted
+    /// * `resolve` - Resolves the owners to the ID address they are indexed by
     pub fn insert_postbox<BS: Blockstore>(
         &mut self,
         st: &BS,
         owners: Option<Vec<Address>>,
         msg: CrossMsg,
+        epoch: ChainEpoch,
+        resolve: impl Fn(&Address) -> Option<Address>,
     ) -> anyhow::Result<Cid> {
-        let item = PostBoxItem::new(msg, owners);
+        let item = PostBoxItem::new(msg, owners, epoch, self.postbox_ttl);
         let (cid, bytes) = item
             .serialize_with_cid()
             .map_err(|_| anyhow!("cannot serialize postbox item"))?;
@@ -324,6 +794,7 @@ impl State {
             postbox.set(key, bytes)?;
             Ok(())
         })?;
+        self.index_postbox_owners(st, &item.owners, &cid, true, resolve)?;
         Ok(cid)
     }
 
@@ -332,38 +803,54 @@ impl State {
         st: &BS,
         cid: Cid,
     ) -> anyhow::Result<PostBoxItem> {
+        match self.get_postbox_item(st, cid)? {
+            Some(item) => Ok(item),
+            None => Err(anyhow!("cid not found in postbox")),
+        }
+    }
+
+    /// Get an item from the postbox, returning `None` if there is
+    /// no item for the cid.
+    pub fn get_postbox_item<BS: Blockstore>(
+        &self,
+        st: &BS,
+        cid: Cid,
+    ) -> anyhow::Result<Option<PostBoxItem>> {
         let postbox = self.postbox.load(st)?;
         let optional = postbox.get(&BytesKey::from(cid.to_bytes()))?;
-        if optional.is_none() {
-            return Err(anyhow!("cid not found in postbox"));
+        match optional {
+            None => Ok(None),
+            Some(raw_bytes) => PostBoxItem::deserialize(raw_bytes.to_vec())
+                .map(Some)
+                .map_err(|_| anyhow!("cannot parse postbox item")),
         }
-
-        let raw_bytes = optional.unwrap();
-        PostBoxItem::deserialize(raw_bytes.to_vec())
-            .map_err(|_| anyhow!("cannot parse postbox item"))
     }
 
+    /// Replaces the item stored for `cid` in the postbox, returning the cid of the new item.
     pub fn swap_postbox_item<BS: Blockstore>(
         &mut self,
         st: &BS,
         cid: Cid,
         item: PostBoxItem,
-    ) -> anyhow::Result<()> {
-        self.postbox.modify(st, |postbox| {
-            let previous = postbox.delete(&BytesKey::from(cid.to_bytes()))?;
-            if previous.is_none() {
-                return Err(anyhow!("cid not found in postbox"));
-            }
-            let (cid, bytes) = item
-                .serialize_with_cid()
-                .map_err(|_| anyhow!("cannot serialize postbox item"))?;
-            let key = BytesKey::from(cid.to_bytes());
+        resolve: impl Fn(&Address) -> Option<Address>,
+    ) -> anyhow::Result<Cid> {
+        let (new_cid, bytes) = item
+            .serialize_with_cid()
+            .map_err(|_| anyhow!("cannot serialize postbox item"))?;
+        let previous = self.postbox.modify(st, |postbox| {
+            let previous = match postbox.delete(&BytesKey::from(cid.to_bytes()))? {
+                Some((_, previous)) => previous,
+                None => return Err(anyhow!("cid not found in postbox")),
+            };
+            let key = BytesKey::from(new_cid.to_bytes());
             postbox.set(key, bytes)?;
 
-            Ok(())
+            PostBoxItem::deserialize(previous).map_err(|_| anyhow!("cannot parse postbox item"))
         })?;
 
-        Ok(())
+        self.index_postbox_owners(st, &previous.owners, &cid, false, &resolve)?;
+        self.index_postbox_owners(st, &item.owners, &new_cid, true, &resolve)?;
+        Ok(new_cid)
     }
 
     /// Removes the cid for postbox.
@@ -374,16 +861,124 @@ impl State {
         &mut self,
         st: &BS,
         cid: Cid,
+        resolve: impl Fn(&Address) -> Option<Address>,
     ) -> Result<(), ActorError> {
-        self.postbox
+        let previous = self
+            .postbox
             .modify(st, |postbox| {
-                postbox.delete(&BytesKey::from(cid.to_bytes()))?;
-                Ok(())
+                match postbox.delete(&BytesKey::from(cid.to_bytes()))? {
+                    Some((_, bytes)) => PostBoxItem::deserialize(bytes)
+                        .map(Some)
+                        .map_err(|_| anyhow!("cannot parse postbox item")),
+                    None => Ok(None),
+                }
             })
             .map_err(|e| {
                 log::error!("encountered error deleting from postbox: {:?}", e);
                 actor_error!(unhandled_message, "cannot delete from postbox")
             })?;
+
+        if let Some(item) = previous {
+            self.index_postbox_owners(st, &item.owners, &cid, false, resolve)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot update postbox index")
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Records the funds recovered by `address` from the frozen subnet `id`, failing
+    /// if they were already recovered.
+    pub fn record_recovered_funds<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        address: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let key = BytesKey::from([id.to_bytes(), address.to_bytes()].concat());
+        self.recovered_funds.update(store, |recovered| {
+            if recovered.contains_key(&key)? {
+                return Err(anyhow!("funds from {address} already recovered"));
+            }
+            recovered.set(key, amount.clone())?;
+            Ok(())
+        })
+    }
+
+    /// Lists the cids of the pending postbox items that `owner`, resolved to its ID
+    /// address, is allowed to propagate in insertion order, starting from the position
+    /// `cursor` of its index and returning at most `limit` of them. It also returns the
+    /// cursor of the next page if there are more items left.
+    pub fn list_postbox_cids<BS: Blockstore>(
+        &self,
+        st: &BS,
+        owner: &Address,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<Cid>, Option<u64>)> {
+        let index = self.postbox_owners.load(st)?;
+        let owned = match index.get(&BytesKey::from(owner.to_bytes()))? {
+            Some(owned) => owned,
+            None => return Ok((vec![], None)),
+        };
+        let cids = owned.cids.load(st)?;
+
+        let mut out = Vec::new();
+        let mut cursor = cursor;
+        while cursor < owned.next_index && (out.len() as u64) < limit {
+            if let Some(cid) = cids
+                .get(cursor)
+                .map_err(|e| anyhow!("failed to get postbox index: {:?}", e))?
+            {
+                out.push(*cid);
+            }
+            cursor += 1;
+        }
+
+        Ok((
+            out,
+            if cursor < owned.next_index {
+                Some(cursor)
+            } else {
+                None
+            },
+        ))
+    }
+
+    /// Adds (or removes if `insert` is false) `cid` from the postbox items
+    /// indexed for each of the `owners`, resolved with `resolve`.
+    fn index_postbox_owners<BS: Blockstore>(
+        &mut self,
+        st: &BS,
+        owners: &Option<Vec<Address>>,
+        cid: &Cid,
+        insert: bool,
+        resolve: impl Fn(&Address) -> Option<Address>,
+    ) -> anyhow::Result<()> {
+        let owners = match owners {
+            Some(owners) => owners,
+            None => return Ok(()),
+        };
+        self.postbox_owners.update(st, |index| {
+            for owner in owners {
+                let owner = resolve(owner).unwrap_or(*owner);
+                let key = BytesKey::from(owner.to_bytes());
+                let mut owned = match index.get(&key)? {
+                    Some(owned) => owned.clone(),
+                    None if insert => PostboxOwnerIndex::new(st)?,
+                    None => continue,
+                };
+                if insert {
+                    owned.insert(st, cid)?;
+                } else if owned.remove(st, cid)? {
+                    index.delete(&key)?;
+                    continue;
+                }
+                index.set(key, owned)?;
+            }
+            Ok(())
+        })?;
         Ok(())
     }
 
@@ -407,8 +1002,16 @@ impl State {
         Ok(())
     }
 
-    pub fn set_membership(&mut self, validator_set: ValidatorSet) {
-        self.validators = Validators::new(validator_set);
+    /// Sets the validators of the subnet, resolving their addresses to index
+    /// their weights.
+    pub fn set_membership(
+        &mut self,
+        rt: &impl Runtime,
+        validator_set: ValidatorSet,
+    ) -> anyhow::Result<()> {
+        self.validators =
+            Validators::new(rt.store(), validator_set, |addr| rt.resolve_address(addr))?;
+        Ok(())
     }
 }
 
@@ -433,23 +1036,33 @@ fn get_subnet<'m, BS: Blockstore>(
 }
 
 pub fn set_checkpoint<BS: Blockstore>(
-    checkpoints: &mut Map<BS, BottomUpCheckpoint>,
+    checkpoints: &mut CheckpointArray<BS>,
     ch: BottomUpCheckpoint,
+    period: ChainEpoch,
 ) -> anyhow::Result<()> {
     let epoch = ch.epoch();
     checkpoints
-        .set(epoch_key(epoch), ch)
-        .map_err(|e| e.downcast_wrap(format!("failed to set checkpoint for epoch {epoch}")))?;
+        .set(checkpoint_index(epoch, period), ch)
+        .map_err(|e| anyhow!("failed to set checkpoint for epoch {epoch}: {:?}", e))?;
     Ok(())
 }
 
 pub fn get_checkpoint<'m, BS: Blockstore>(
-    checkpoints: &'m Map<BS, BottomUpCheckpoint>,
+    checkpoints: &'m CheckpointArray<BS>,
     epoch: ChainEpoch,
+    period: ChainEpoch,
 ) -> anyhow::Result<Option<&'m BottomUpCheckpoint>> {
-    checkpoints
-        .get(&epoch_key(epoch))
-        .map_err(|e| e.downcast_wrap(format!("failed to get checkpoint for id {epoch}")))
+    let ch = checkpoints
+        .get(checkpoint_index(epoch, period))
+        .map_err(|e| anyhow!("failed to get checkpoint for epoch {epoch}: {:?}", e))?;
+    // the index is shared by all the epochs of the window, so
+    // make sure that the checkpoint is the one requested.
+    Ok(ch.filter(|ch| ch.epoch() == epoch))
+}
+
+/// Key of a pending bottom-up message in `pending_bottomup_msgs`.
+fn pending_msg_key(subnet: &SubnetID, nonce: u64) -> BytesKey {
+    BytesKey::from([subnet.to_bytes(), nonce.to_be_bytes().to_vec()].concat())
 }
 
 pub fn get_topdown_msg<'m, BS: Blockstore>(
@@ -5,6 +5,7 @@ use fvm_ipld_encoding::repr::{Deserialize_repr, Serialize_repr};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use ipc_sdk::cross::CrossMsg;
+use num_traits::Zero;
 use primitives::{TAmt, TCid};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
@@ -19,11 +20,25 @@ pub enum Status {
     Active,
     Inactive,
     Killed,
+    /// The subnet stopped committing checkpoints and its users can recover their funds.
+    Frozen,
+}
+
+/// Limits on the funds moving through a subnet, zero values disable the limit.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SubnetLimits {
+    /// Maximum circulating supply of the subnet that can be reached through `Fund`
+    pub circ_supply_cap: TokenAmount,
+    /// Maximum value of the bottom-up messages of the subnet released in every
+    /// bottom-up checkpoint window
+    pub release_limit: TokenAmount,
 }
 
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct Subnet {
     pub id: SubnetID,
+    /// Position of the subnet in `State::subnet_index`
+    pub index: u64,
     pub stake: TokenAmount,
     pub top_down_msgs: TCid<TAmt<CrossMsg, CROSSMSG_AMT_BITWIDTH>>,
     pub topdown_nonce: u64,
@@ -35,6 +50,25 @@ pub struct Subnet {
     // was registered. This signals the epoch from which
     // the top-down checkpoint can be started.
     pub genesis_epoch: ChainEpoch,
+    /// Epoch of the subnet from which its bottom-up checkpoints are counted, as
+    /// registered by its subnet actor. The gateway of the subnet is initialized
+    /// with it.
+    pub bottomup_genesis_epoch: ChainEpoch,
+    pub limits: SubnetLimits,
+    /// Checkpoint window in which `released_value` was released
+    pub release_window: ChainEpoch,
+    /// Value of the bottom-up messages released in `release_window`
+    pub released_value: TokenAmount,
+    /// Bottom-up messages held because they exceeded the release limit, queued
+    /// in `[held_msgs_head, held_msgs_tail)`
+    pub held_msgs: TCid<TAmt<CrossMsg, CROSSMSG_AMT_BITWIDTH>>,
+    pub held_msgs_head: u64,
+    pub held_msgs_tail: u64,
+    /// Epoch of this chain in which the last checkpoint of the subnet was committed,
+    /// or its genesis epoch if it hasn't committed any
+    pub last_checkpoint_epoch: ChainEpoch,
+    /// Nonce of the next top-down message to refund once the subnet is frozen
+    pub refund_nonce: u64,
 }
 
 impl Subnet {
@@ -45,10 +79,13 @@ impl Subnet {
         value: &TokenAmount,
     ) -> anyhow::Result<()> {
         self.stake += value;
-        if self.stake < st.min_stake {
-            self.status = Status::Inactive;
-        } else {
-            self.status = Status::Active;
+        // frozen subnets can't be reactivated
+        if self.status != Status::Frozen {
+            if self.stake < st.min_stake {
+                self.status = Status::Inactive;
+            } else {
+                self.status = Status::Active;
+            }
         }
         st.flush_subnet(rt.store(), self)?;
         Ok(())
@@ -79,6 +116,120 @@ impl Subnet {
         })
     }
 
+    /// Remove from the subnet the top-down messages with nonce in `[from, to)`,
+    /// i.e. those that have already been applied in the child.
+    pub(crate) fn prune_topdown_msgs<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<()> {
+        if from >= to {
+            return Ok(());
+        }
+        self.top_down_msgs.update(store, |crossmsgs| {
+            crossmsgs
+                .batch_delete(from..to, false)
+                .map_err(|e| anyhow!("failed to prune crossmsg meta array: {:?}", e))?;
+            Ok(())
+        })
+    }
+
+    /// Nonce of the first top-down message that hasn't been applied in the child
+    /// according to its latest checkpoint.
+    pub(crate) fn pending_topdown_nonce(&self) -> u64 {
+        match self.prev_checkpoint {
+            Some(ref prev_checkpoint) => prev_checkpoint.applied_topdown_nonce(),
+            None => 0,
+        }
+    }
+
+    /// Get the top-down messages committed for the subnet with nonce in `[from, to)`.
+    pub(crate) fn topdown_msgs<BS: Blockstore>(
+        &self,
+        store: &BS,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<CrossMsg>> {
+        let crossmsgs = self.top_down_msgs.load(store)?;
+        let mut msgs = Vec::new();
+        for nonce in from..to {
+            if let Some(msg) = crossmsgs
+                .get(nonce)
+                .map_err(|e| anyhow!("failed to get crossmsg: {:?}", e))?
+            {
+                msgs.push(msg.clone());
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Returns the bottom-up messages of the subnet that can be applied in the checkpoint
+    /// `window` without exceeding the release limit, holding the rest in the queue.
+    ///
+    /// Held messages are released before `msgs` so they are applied in order. If `force`
+    /// is set, all the messages are released regardless of the limit.
+    pub(crate) fn release_bottomup_msgs<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        window: ChainEpoch,
+        msgs: Vec<CrossMsg>,
+        force: bool,
+    ) -> anyhow::Result<Vec<CrossMsg>> {
+        if self.release_window != window {
+            self.release_window = window;
+            self.released_value = TokenAmount::zero();
+        }
+        let limit = self.limits.release_limit.clone();
+        let (mut head, mut tail) = (self.held_msgs_head, self.held_msgs_tail);
+        if head == tail && (force || limit.is_zero()) {
+            return Ok(msgs);
+        }
+
+        let mut released_value = self.released_value.clone();
+        let mut fits = |value: &TokenAmount| {
+            let released = released_value.clone() + value;
+            if force || limit.is_zero() || released <= limit {
+                released_value = released;
+                true
+            } else {
+                false
+            }
+        };
+        let mut released = Vec::new();
+        self.held_msgs.update(store, |held| {
+            while head < tail {
+                let msg = held
+                    .get(head)
+                    .map_err(|e| anyhow!("failed to get held crossmsg: {:?}", e))?
+                    .ok_or_else(|| anyhow!("held crossmsg {} not found", head))?
+                    .clone();
+                if !fits(&msg.msg.value) {
+                    break;
+                }
+                held.delete(head)
+                    .map_err(|e| anyhow!("failed to delete held crossmsg: {:?}", e))?;
+                head += 1;
+                released.push(msg);
+            }
+            for msg in msgs.iter() {
+                if head == tail && fits(&msg.msg.value) {
+                    released.push(msg.clone());
+                } else {
+                    held.set(tail, msg.clone())
+                        .map_err(|e| anyhow!("failed to hold crossmsg: {:?}", e))?;
+                    tail += 1;
+                }
+            }
+            Ok(())
+        })?;
+
+        self.held_msgs_head = head;
+        self.held_msgs_tail = tail;
+        self.released_value = released_value;
+        Ok(released)
+    }
+
     pub(crate) fn release_supply(&mut self, value: &TokenAmount) -> anyhow::Result<()> {
         if &self.circ_supply < value {
             return Err(anyhow!(
@@ -1,30 +1,53 @@
+use crate::recovery::RecoveryProofNode;
+use crate::{BottomUpCheckpoint, Subnet, SubnetLimits};
 use anyhow::anyhow;
 use cid::multihash::Code;
 use cid::{multihash, Cid};
 use fil_actors_runtime::runtime::fvm::{equal_account_id, resolve_secp_bls};
 use fil_actors_runtime::runtime::Runtime;
 use fil_actors_runtime::{cbor, ActorError, Array};
+use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
 use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
+use fvm_ipld_hamt::BytesKey;
 use fvm_shared::address::{Address, Protocol};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
 use ipc_sdk::cross::CrossMsg;
 use ipc_sdk::subnet_id::SubnetID;
 use multihash::MultihashDigest;
-use primitives::CodeType;
+use num_traits::Zero;
+use primitives::{CodeType, TAmt, TCid, THamt};
 use std::cmp::Ordering;
 
 /// ID used in the builtin-actors bundle manifest
 pub const MANIFEST_ID: &str = "ipc_gateway";
 
 pub const CROSSMSG_AMT_BITWIDTH: u32 = 3;
+pub const CHECKPOINT_AMT_BITWIDTH: u32 = 3;
+pub const SUBNET_INDEX_AMT_BITWIDTH: u32 = 3;
+pub const POSTBOX_INDEX_AMT_BITWIDTH: u32 = 3;
 pub const DEFAULT_CHECKPOINT_PERIOD: ChainEpoch = 10;
 pub const MIN_COLLATERAL_AMOUNT: u64 = 10_u64.pow(18);
 
 pub const SUBNET_ACTOR_REWARD_METHOD: u64 = frc42_dispatch::method_hash!("Reward");
 
+/// Maximum number of items returned by paginated query methods.
+pub const MAX_QUERY_LIMIT: u64 = 100;
+/// Maximum number of pending top-down messages refunded in a single call to `FreezeSubnet`.
+pub const MAX_FROZEN_REFUNDS: u64 = 100;
+/// Maximum number of buffered cross-messages applied in a single call once the nonce
+/// gap before them is filled. The rest are applied with the next incoming messages.
+pub const MAX_PENDING_MSGS_APPLIED: u64 = 100;
+
+/// Exit code of the receipts refunding postbox items that expired before being propagated.
+pub const POSTBOX_EXPIRED_EXIT_CODE: ExitCode = ExitCode::new(32);
+/// Exit code of the receipts refunding top-down messages not applied in a frozen subnet.
+pub const SUBNET_FROZEN_EXIT_CODE: ExitCode = ExitCode::new(33);
+
 pub type CrossMsgArray<'bs, BS> = Array<'bs, CrossMsg, BS>;
+pub type CheckpointArray<'bs, BS> = Array<'bs, BottomUpCheckpoint, BS>;
 
 /// The executable message trait
 pub trait ExecutableMessage {
@@ -37,6 +60,122 @@ pub struct ConstructorParams {
     pub network_name: String,
     pub bottomup_check_period: ChainEpoch,
     pub topdown_check_period: ChainEpoch,
+    /// Fee policy applied to cross-messages
+    pub cross_msg_fees: CrossMsgFees,
+    /// Address allowed to perform governance operations on the gateway
+    pub governor: Address,
+    /// Number of bottom-up checkpoint windows retained in the gateway,
+    /// zero means that all of them are retained
+    pub checkpoint_retention: u64,
+    /// Number of epochs after which postbox items can be reclaimed,
+    /// zero means that they never expire
+    pub postbox_ttl: ChainEpoch,
+    /// Number of epochs without committing a checkpoint after which a subnet
+    /// can be frozen to recover its funds, zero disables recovery
+    pub recovery_deadline: ChainEpoch,
+    /// Address, usually a multisig, allowed to pause and unpause the gateway
+    pub guardian: Address,
+    /// Treasury actor unlocking the circulating supply minted for top-down messages,
+    /// if `None` it is minted from the balance of the gateway
+    pub treasury: Option<Address>,
+    /// Limits on the size of the bottom-up checkpoints
+    pub checkpoint_limits: CheckpointLimits,
+}
+
+/// Values of the fields introduced in the gateway state since version 0, used
+/// when upgrading the state stored by a previous version of the actor. Only the
+/// fields introduced after the version of the upgraded state are used.
+#[derive(Serialize_tuple, Deserialize_tuple)]
+pub struct UpgradeStateParams {
+    pub cross_msg_fees: CrossMsgFees,
+    pub governor: Address,
+    pub checkpoint_retention: u64,
+    pub postbox_ttl: ChainEpoch,
+    pub recovery_deadline: ChainEpoch,
+    pub guardian: Address,
+    pub treasury: Option<Address>,
+    pub checkpoint_limits: CheckpointLimits,
+}
+
+/// Type of operation a cross-message fee is charged for.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CrossMsgKind {
+    Fund,
+    Release,
+    SendCross,
+    Propagate,
+}
+
+/// Fee policy for cross-messages. The fee charged for a message is
+/// the base fee for its kind plus `per_byte` for every byte in the
+/// params of the message.
+///
+/// The fee is charged by the gateway the message is sent from and attached to the
+/// message, every hop in its route taking an even share of what is left. Forwarded
+/// messages are therefore rewarded with a fraction of the fee of the source gateway.
+/// The policy of an intermediate gateway only applies through the `propagate` fee it
+/// charges for propagating a message from its postbox, which is added to the fee
+/// split between the hops left.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct CrossMsgFees {
+    pub fund: TokenAmount,
+    pub release: TokenAmount,
+    pub send_cross: TokenAmount,
+    pub propagate: TokenAmount,
+    pub per_byte: TokenAmount,
+}
+
+impl CrossMsgFees {
+    /// Creates a policy charging the same fee for every message kind
+    /// regardless of its size.
+    pub fn uniform(fee: TokenAmount) -> Self {
+        Self {
+            fund: fee.clone(),
+            release: fee.clone(),
+            send_cross: fee.clone(),
+            propagate: fee,
+            per_byte: TokenAmount::zero(),
+        }
+    }
+
+    /// Returns the fee to charge for a cross-message of the given kind
+    /// including the `params` payload.
+    pub fn fee(&self, kind: CrossMsgKind, params: &RawBytes) -> TokenAmount {
+        let base = match kind {
+            CrossMsgKind::Fund => &self.fund,
+            CrossMsgKind::Release => &self.release,
+            CrossMsgKind::SendCross => &self.send_cross,
+            CrossMsgKind::Propagate => &self.propagate,
+        };
+        base.clone() + self.per_byte.clone() * params.len() as u64
+    }
+}
+
+/// Limits on the size of the bottom-up checkpoints. Cross-messages that don't
+/// fit in the checkpoint of the current window are stored in the following
+/// windows. Zero disables a limit.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug, Default)]
+pub struct CheckpointLimits {
+    /// Maximum number of cross-messages in a checkpoint.
+    pub max_msgs: u64,
+    /// Maximum size in bytes of the cross-messages in a checkpoint.
+    pub max_bytes: u64,
+}
+
+impl CheckpointLimits {
+    /// Returns true if a message of `msg_bytes` encoded bytes can be added to a
+    /// checkpoint holding `msgs` messages of `bytes` encoded bytes in total without
+    /// exceeding the limits. An empty checkpoint accepts any message, so messages
+    /// larger than `max_bytes` can still be propagated in a checkpoint of their own.
+    pub fn fits(&self, msgs: u64, bytes: u64, msg_bytes: u64) -> bool {
+        if msgs == 0 {
+            return true;
+        }
+        if self.max_msgs > 0 && msgs >= self.max_msgs {
+            return false;
+        }
+        !(self.max_bytes > 0 && bytes + msg_bytes > self.max_bytes)
+    }
 }
 
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
@@ -75,6 +214,18 @@ pub struct PropagateParams {
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
 pub struct InitGenesisEpoch {
     pub genesis_epoch: ChainEpoch,
+    /// Epoch of the subnet from which bottom-up checkpoint windows are counted,
+    /// the `bottomup_genesis_epoch` registered for the subnet in its parent.
+    pub bottomup_genesis_epoch: ChainEpoch,
+}
+
+/// Parameters of `Register`, sent by the subnet actor of the subnet.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct RegisterParams {
+    /// Epoch of the subnet from which the checkpoint voting of the subnet actor
+    /// counts bottom-up checkpoints. The gateway of the subnet is initialized
+    /// with it, so its windows are aligned with the voting.
+    pub bottomup_genesis_epoch: ChainEpoch,
 }
 
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
@@ -85,11 +236,129 @@ pub struct WhitelistPropagatorParams {
     pub to_add: Vec<Address>,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct RemovePropagatorParams {
+    /// The postbox message cid
+    pub postbox_cid: Cid,
+    /// The owners to remove
+    pub to_remove: Vec<Address>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct TransferPostboxOwnershipParams {
+    /// The postbox message cid
+    pub postbox_cid: Cid,
+    /// The owners replacing the current ones
+    pub new_owners: Vec<Address>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ListSubnetsParams {
+    /// Position in the subnet index of the first subnet to return, zero for the
+    /// first page and `ListSubnetsReturn::next_cursor` for the following ones
+    pub cursor: u64,
+    /// Maximum number of subnets to return (capped to `MAX_QUERY_LIMIT`)
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ListSubnetsReturn {
+    pub subnets: Vec<Subnet>,
+    /// Cursor to use for the next page, `None` if there are no more subnets.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct GetCheckpointParams {
+    pub epoch: ChainEpoch,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct GetTopDownMsgsParams {
+    pub subnet: SubnetID,
+    /// Nonce of the first top-down message to return
+    pub from_nonce: u64,
+    /// Maximum number of messages to return (capped to `MAX_QUERY_LIMIT`)
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct GetPostboxItemParams {
+    /// The postbox message cid
+    pub postbox_cid: Cid,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ListPostboxCidsParams {
+    /// Owner of the postbox items
+    pub owner: Address,
+    /// Position in the index of the owner of the first cid to return, zero for the
+    /// first page and `ListPostboxCidsReturn::next_cursor` for the following ones
+    pub cursor: u64,
+    /// Maximum number of cids to return (capped to `MAX_QUERY_LIMIT`)
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ListPostboxCidsReturn {
+    pub cids: Vec<Cid>,
+    /// Cursor to use for the next page, `None` if there are no more cids.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ReclaimPostboxParams {
+    /// The postbox message cid
+    pub postbox_cid: Cid,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct RecoverFundsParams {
+    /// The frozen subnet holding the funds
+    pub subnet: SubnetID,
+    /// The address owning the funds, both in the subnet and in this subnet
+    pub address: Address,
+    /// The balance of the address committed in the recovery root
+    pub amount: TokenAmount,
+    /// The merkle path from the balance to the recovery root
+    pub proof: Vec<RecoveryProofNode>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct SetSubnetLimitsParams {
+    pub subnet: SubnetID,
+    pub limits: SubnetLimits,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ReleaseHeldMsgsParams {
+    pub subnet: SubnetID,
+    /// Release all the held messages regardless of the release limit,
+    /// only the governor can force the release
+    pub force: bool,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct PruneCheckpointsParams {
+    /// Epoch up to which (inclusive) bottom-up checkpoint windows are removed
+    pub epoch: ChainEpoch,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ExecutedCheckpointParams {
+    /// Epoch of the latest bottom-up checkpoint executed in the parent
+    pub epoch: ChainEpoch,
+}
+
 /// The item to store in the `State::postbox`
 #[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
 pub struct PostBoxItem {
     pub cross_msg: CrossMsg,
     pub owners: Option<Vec<Address>>,
+    /// Epoch in which the item was inserted in the postbox
+    pub inserted_epoch: ChainEpoch,
+    /// Number of epochs after which the item can be reclaimed, zero means that it never expires
+    pub ttl: ChainEpoch,
 }
 
 // The implementation does not matter, we just need to extract the cid
@@ -132,8 +401,32 @@ pub(crate) fn resolved_from_to(
 const POSTBOX_ITEM_DESCRIPTION: &str = "postbox";
 
 impl PostBoxItem {
-    pub fn new(cross_msg: CrossMsg, owners: Option<Vec<Address>>) -> Self {
-        Self { cross_msg, owners }
+    pub fn new(
+        cross_msg: CrossMsg,
+        owners: Option<Vec<Address>>,
+        inserted_epoch: ChainEpoch,
+        ttl: ChainEpoch,
+    ) -> Self {
+        Self {
+            cross_msg,
+            owners,
+            inserted_epoch,
+            ttl,
+        }
+    }
+
+    /// Checks if `addr` is allowed to propagate the item, items without
+    /// owners can be propagated by anyone.
+    pub fn is_owner(&self, addr: &Address) -> bool {
+        match &self.owners {
+            Some(owners) => owners.contains(addr),
+            None => true,
+        }
+    }
+
+    /// Checks if the item has expired and can be reclaimed at `epoch`.
+    pub fn is_expired(&self, epoch: ChainEpoch) -> bool {
+        self.ttl > 0 && epoch >= self.inserted_epoch + self.ttl
     }
 
     pub fn serialize_with_cid(&self) -> Result<(Cid, Vec<u8>), ActorError> {
@@ -147,6 +440,76 @@ impl PostBoxItem {
     }
 }
 
+/// The postbox items an owner is allowed to propagate, in `State::postbox_owners`.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct PostboxOwnerIndex {
+    /// Cids of the items in insertion order, keyed by their position. The
+    /// positions of removed items are left empty.
+    pub cids: TCid<TAmt<Cid, POSTBOX_INDEX_AMT_BITWIDTH>>,
+    /// Position of each indexed cid in `cids`.
+    pub positions: TCid<THamt<Cid, u64>>,
+    /// Position in `cids` assigned to the next item indexed.
+    pub next_index: u64,
+}
+
+impl PostboxOwnerIndex {
+    pub fn new<BS: Blockstore>(store: &BS) -> anyhow::Result<Self> {
+        Ok(Self {
+            cids: TCid::new_amt(store)?,
+            positions: TCid::new_hamt(store)?,
+            next_index: 0,
+        })
+    }
+
+    /// Appends `cid` at position `next_index`, if it is not indexed yet.
+    pub fn insert<BS: Blockstore>(&mut self, store: &BS, cid: &Cid) -> anyhow::Result<()> {
+        let index = self.next_index;
+        let inserted = self.positions.modify(store, |positions| {
+            let key = BytesKey::from(cid.to_bytes());
+            if positions.contains_key(&key)? {
+                return Ok(false);
+            }
+            positions.set(key, index)?;
+            Ok(true)
+        })?;
+        if inserted {
+            self.cids.update(store, |cids| {
+                cids.set(index, *cid)
+                    .map_err(|e| anyhow!("failed to set postbox index: {:?}", e))
+            })?;
+            self.next_index += 1;
+        }
+        Ok(())
+    }
+
+    /// Removes `cid` from the index, returning whether there are no items left.
+    pub fn remove<BS: Blockstore>(&mut self, store: &BS, cid: &Cid) -> anyhow::Result<bool> {
+        let (removed, is_empty) = self.positions.modify(store, |positions| {
+            let removed = positions
+                .delete(&BytesKey::from(cid.to_bytes()))?
+                .map(|(_, index)| index);
+            Ok((removed, positions.is_empty()))
+        })?;
+        if let Some(index) = removed {
+            self.cids.update(store, |cids| {
+                cids.delete(index)
+                    .map_err(|e| anyhow!("failed to delete postbox index: {:?}", e))
+                    .map(|_| ())
+            })?;
+        }
+        Ok(is_empty)
+    }
+}
+
+/// Computes the cid of a cross-message
+pub fn cross_msg_cid(cross_msg: &CrossMsg) -> Result<Cid, ActorError> {
+    let bytes = cbor::serialize(cross_msg, "cross-message")?;
+    Ok(Cid::new_v1(
+        DAG_CBOR,
+        Code::Blake2b256.digest(bytes.bytes()),
+    ))
+}
+
 pub(crate) fn ensure_message_sorted<E: ExecutableMessage>(messages: &[E]) -> anyhow::Result<()> {
     // check top down msgs
     for i in 1..messages.len() {
@@ -161,8 +524,15 @@ pub(crate) fn ensure_message_sorted<E: ExecutableMessage>(messages: &[E]) -> any
 
 #[cfg(test)]
 mod tests {
-    use crate::ConstructorParams;
-    use fvm_ipld_encoding::RawBytes;
+    use crate::{CheckpointLimits, ConstructorParams, CrossMsgFees, CrossMsgKind};
+    use fvm_ipld_encoding::{to_vec, RawBytes};
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::METHOD_SEND;
+    use ipc_sdk::address::IPCAddress;
+    use ipc_sdk::cross::{CrossMsg, StorableMsg};
+    use ipc_sdk::subnet_id::SubnetID;
+    use std::str::FromStr;
 
     #[test]
     fn serialize_params() {
@@ -170,6 +540,17 @@ mod tests {
             network_name: "/root".to_string(),
             bottomup_check_period: 100,
             topdown_check_period: 20,
+            cross_msg_fees: CrossMsgFees::uniform(TokenAmount::from_nano(100)),
+            governor: Address::new_id(100),
+            checkpoint_retention: 10,
+            postbox_ttl: 1000,
+            recovery_deadline: 5000,
+            guardian: Address::new_id(101),
+            treasury: Some(Address::new_id(102)),
+            checkpoint_limits: CheckpointLimits {
+                max_msgs: 100,
+                max_bytes: 10000,
+            },
         };
         let bytes = fil_actors_runtime::util::cbor::serialize(&p, "").unwrap();
         let serialized = base64::encode(bytes.bytes());
@@ -182,5 +563,74 @@ mod tests {
         assert_eq!(p.network_name, deserialized.network_name);
         assert_eq!(p.bottomup_check_period, deserialized.bottomup_check_period);
         assert_eq!(p.topdown_check_period, deserialized.topdown_check_period);
+        assert_eq!(p.cross_msg_fees, deserialized.cross_msg_fees);
+        assert_eq!(p.governor, deserialized.governor);
+        assert_eq!(p.checkpoint_retention, deserialized.checkpoint_retention);
+        assert_eq!(p.postbox_ttl, deserialized.postbox_ttl);
+        assert_eq!(p.recovery_deadline, deserialized.recovery_deadline);
+        assert_eq!(p.guardian, deserialized.guardian);
+        assert_eq!(p.treasury, deserialized.treasury);
+        assert_eq!(p.checkpoint_limits, deserialized.checkpoint_limits);
+    }
+
+    #[test]
+    fn cross_msg_fee_by_kind_and_size() {
+        let mut fees = CrossMsgFees::uniform(TokenAmount::from_atto(100));
+        fees.send_cross = TokenAmount::from_atto(200);
+        fees.per_byte = TokenAmount::from_atto(2);
+
+        let empty = RawBytes::default();
+        assert_eq!(
+            fees.fee(CrossMsgKind::Fund, &empty),
+            TokenAmount::from_atto(100)
+        );
+        assert_eq!(
+            fees.fee(CrossMsgKind::SendCross, &empty),
+            TokenAmount::from_atto(200)
+        );
+        let params = RawBytes::new(vec![0; 10]);
+        assert_eq!(
+            fees.fee(CrossMsgKind::SendCross, &params),
+            TokenAmount::from_atto(220)
+        );
+    }
+
+    #[test]
+    fn checkpoint_limits_fit() {
+        let subnet = SubnetID::from_str("/r123").unwrap();
+        let cross_msg = CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(&subnet, &Address::new_id(100)).unwrap(),
+                to: IPCAddress::new(&subnet, &Address::new_id(101)).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: TokenAmount::from_atto(1000),
+                nonce: 0,
+            },
+            wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
+        };
+        let size = to_vec(&cross_msg).unwrap().len() as u64;
+
+        // an empty checkpoint accepts any message
+        let limits = CheckpointLimits {
+            max_msgs: 1,
+            max_bytes: 1,
+        };
+        assert!(limits.fits(0, 0, size));
+
+        assert!(!limits.fits(1, size, size));
+        assert!(CheckpointLimits::default().fits(1, size, size));
+        let limits = CheckpointLimits {
+            max_msgs: 2,
+            max_bytes: 2 * size,
+        };
+        assert!(limits.fits(1, size, size));
+        let limits = CheckpointLimits {
+            max_msgs: 2,
+            max_bytes: 2 * size - 1,
+        };
+        assert!(!limits.fits(1, size, size));
     }
 }
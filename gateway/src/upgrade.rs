@@ -0,0 +1,814 @@
+//! Versioning of the gateway state.
+//!
+//! The state of the gateway is tagged with the version of its layout. Every time
+//! the layout changes, `STATE_VERSION` is increased and the previous layout is kept
+//! in this module together with the migration to the next version, so `UpgradeState`
+//! can bring the state stored by any previous version of the actor to the current
+//! layout after a code upgrade.
+//!
+//! Version 0 is the layout used before the state was versioned.
+
+use std::fmt;
+
+use anyhow::anyhow;
+use cid::multihash::Code;
+use cid::Cid;
+use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
+use fil_actors_runtime::{cbor, make_map_with_root_and_bitwidth, Array, Map};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{serde_bytes, CborStore, RawBytes};
+use fvm_ipld_hamt::BytesKey;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use ipc_actor_common::vote::{EpochVoteSubmissions, UniqueVote, Voting};
+use ipc_sdk::cross::{CrossMsg, StorableMsg};
+use ipc_sdk::subnet_id::SubnetID;
+use ipc_sdk::ValidatorSet;
+use num_traits::Zero;
+use primitives::{TCid, TLink};
+use serde::de::{self, DeserializeOwned, IgnoredAny, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+
+use crate::checkpoint::{migrate_checkpoints, BatchCrossMsgs, CheckData, ChildCheck, Validators};
+use crate::{
+    BottomUpCheckpoint, ConstructorParams, State, Status, Subnet, SubnetLimits, TopDownCheckpoint,
+    UpgradeStateParams,
+};
+
+/// Version of the layout of the gateway state.
+pub const STATE_VERSION: u64 = 1;
+
+/// Returns the version of the layout of the gateway state stored in `root`.
+pub fn state_version<BS: Blockstore>(store: &BS, root: &Cid) -> anyhow::Result<u64> {
+    // since version 1 the version is the first field of the state
+    if let Ok(Some(header)) = store.get_cbor::<VersionHeader>(root) {
+        return Ok(header.0);
+    }
+    match store.get_cbor::<StateV0>(root) {
+        Ok(Some(_)) => Ok(0),
+        _ => Err(anyhow!("unknown gateway state layout")),
+    }
+}
+
+/// Migrates the gateway state stored in `root` to the current version, returning
+/// the root of the migrated state, or `None` if it is already up to date.
+/// `resolve` resolves the addresses of the validators and postbox owners to their ID address.
+pub fn migrate_state<BS: Blockstore>(
+    store: &BS,
+    root: &Cid,
+    params: &UpgradeStateParams,
+    epoch: ChainEpoch,
+    resolve: impl Fn(&Address) -> Option<Address>,
+) -> anyhow::Result<Option<Cid>> {
+    let mut version = state_version(store, root)?;
+    if version > STATE_VERSION {
+        return Err(anyhow!(
+            "state version {} is newer than the actor version {}",
+            version,
+            STATE_VERSION
+        ));
+    }
+    if version == STATE_VERSION {
+        return Ok(None);
+    }
+
+    let mut root = *root;
+    while version < STATE_VERSION {
+        (root, version) = match version {
+            0 => (migrate_v0(store, &root, params, epoch, &resolve)?, 1),
+            _ => return Err(anyhow!("no migration from state version {}", version)),
+        };
+    }
+    Ok(Some(root))
+}
+
+/// First field of the state since version 1, the rest of the fields are skipped.
+struct VersionHeader(u64);
+
+impl<'de> Deserialize<'de> for VersionHeader {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HeaderVisitor;
+
+        impl<'de> Visitor<'de> for HeaderVisitor {
+            type Value = VersionHeader;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a versioned gateway state")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let version = seq
+                    .next_element::<u64>()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(VersionHeader(version))
+            }
+        }
+
+        deserializer.deserialize_seq(HeaderVisitor)
+    }
+}
+
+/// Layout of the gateway state in version 0.
+#[derive(Serialize_tuple, Deserialize_tuple)]
+pub struct StateV0 {
+    pub network_name: SubnetID,
+    pub total_subnets: u64,
+    pub min_stake: TokenAmount,
+    /// HAMT of `SubnetV0` keyed by subnet id
+    pub subnets: Cid,
+    pub bottomup_check_period: ChainEpoch,
+    pub topdown_check_period: ChainEpoch,
+    /// HAMT of `BottomUpCheckpointV0` keyed by epoch
+    pub bottomup_checkpoints: Cid,
+    /// HAMT of serialized `PostBoxItemV0` keyed by their cid
+    pub postbox: Cid,
+    pub bottomup_nonce: u64,
+    pub applied_topdown_nonce: u64,
+    pub topdown_checkpoint_voting: Voting<TopDownCheckpointV0>,
+    pub validators: ValidatorsV0,
+    pub initialized: bool,
+}
+
+/// Layout of the validators in version 0, before their weights were indexed.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct ValidatorsV0 {
+    pub validators: ValidatorSet,
+    pub total_weight: TokenAmount,
+}
+
+/// Layout of a subnet in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct SubnetV0 {
+    pub id: SubnetID,
+    pub stake: TokenAmount,
+    /// AMT of `CrossMsgV0` indexed by nonce
+    pub top_down_msgs: Cid,
+    pub topdown_nonce: u64,
+    pub circ_supply: TokenAmount,
+    pub status: Status,
+    pub prev_checkpoint: Option<BottomUpCheckpointV0>,
+    pub applied_bottomup_nonce: u64,
+    pub genesis_epoch: ChainEpoch,
+}
+
+/// Layout of a bottom-up checkpoint in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct BottomUpCheckpointV0 {
+    pub data: CheckDataV0,
+    #[serde(with = "serde_bytes")]
+    pub sig: Vec<u8>,
+}
+
+/// Layout of the data of a bottom-up checkpoint in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct CheckDataV0 {
+    pub source: SubnetID,
+    #[serde(with = "serde_bytes")]
+    pub proof: Vec<u8>,
+    pub epoch: ChainEpoch,
+    pub prev_check: TCid<TLink<BottomUpCheckpointV0>>,
+    pub children: Vec<ChildCheckV0>,
+    pub cross_msgs: BatchCrossMsgsV0,
+}
+
+/// Layout of the checkpoints of a child in a bottom-up checkpoint in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct ChildCheckV0 {
+    pub source: SubnetID,
+    pub checks: Vec<TCid<TLink<BottomUpCheckpointV0>>>,
+}
+
+/// Layout of the cross-messages of a bottom-up checkpoint in version 0.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BatchCrossMsgsV0(pub Vec<CrossMsgV0>, pub TokenAmount);
+
+/// Layout of a cross-message in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct CrossMsgV0 {
+    pub msg: StorableMsg,
+    pub wrapped: bool,
+}
+
+/// Layout of a top-down checkpoint in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct TopDownCheckpointV0 {
+    pub epoch: ChainEpoch,
+    pub top_down_msgs: Vec<CrossMsgV0>,
+}
+
+/// Layout of a postbox item in version 0.
+#[derive(Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct PostBoxItemV0 {
+    pub cross_msg: CrossMsgV0,
+    pub owners: Option<Vec<Address>>,
+}
+
+impl From<CrossMsgV0> for CrossMsg {
+    fn from(msg: CrossMsgV0) -> Self {
+        CrossMsg {
+            msg: msg.msg,
+            wrapped: msg.wrapped,
+            reply_method: None,
+            fee: TokenAmount::zero(),
+        }
+    }
+}
+
+impl From<ChildCheckV0> for ChildCheck {
+    fn from(check: ChildCheckV0) -> Self {
+        ChildCheck {
+            source: check.source,
+            checks: check
+                .checks
+                .into_iter()
+                .map(|ch| TCid::from(ch.cid()))
+                .collect(),
+        }
+    }
+}
+
+impl From<TopDownCheckpointV0> for TopDownCheckpoint {
+    fn from(ch: TopDownCheckpointV0) -> Self {
+        TopDownCheckpoint {
+            epoch: ch.epoch,
+            top_down_msgs: ch.top_down_msgs.into_iter().map(CrossMsg::from).collect(),
+        }
+    }
+}
+
+impl From<BottomUpCheckpointV0> for BottomUpCheckpoint {
+    fn from(ch: BottomUpCheckpointV0) -> Self {
+        let BatchCrossMsgsV0(cross_msgs, fee) = ch.data.cross_msgs;
+        BottomUpCheckpoint {
+            data: CheckData {
+                source: ch.data.source,
+                proof: ch.data.proof,
+                epoch: ch.data.epoch,
+                prev_check: TCid::from(ch.data.prev_check.cid()),
+                children: ch.data.children.into_iter().map(ChildCheck::from).collect(),
+                cross_msgs: BatchCrossMsgs {
+                    cross_msgs: if cross_msgs.is_empty() {
+                        None
+                    } else {
+                        Some(cross_msgs.into_iter().map(CrossMsg::from).collect())
+                    },
+                    fee,
+                },
+                // nothing is known about the top-down messages applied in the source,
+                // so none of them can be pruned until its next checkpoint.
+                applied_topdown_nonce: 0,
+            },
+            sig: ch.sig,
+        }
+    }
+}
+
+/// Migrates the state from version 0 to the current version.
+///
+/// The fields introduced since version 0 are initialized from `params`, and the
+/// subnets, checkpoints and postbox items are rewritten in their new layout. Postbox
+/// items are re-inserted at `epoch`, so their cids change, and the pending top-down
+/// checkpoint votes are re-keyed by the unique key of the checkpoints in their new layout.
+fn migrate_v0<BS: Blockstore>(
+    store: &BS,
+    root: &Cid,
+    params: &UpgradeStateParams,
+    epoch: ChainEpoch,
+    resolve: impl Fn(&Address) -> Option<Address>,
+) -> anyhow::Result<Cid> {
+    let old: StateV0 = store
+        .get_cbor(root)?
+        .ok_or_else(|| anyhow!("state not found"))?;
+
+    let mut st = State::new(
+        store,
+        ConstructorParams {
+            network_name: old.network_name.to_string(),
+            bottomup_check_period: old.bottomup_check_period,
+            topdown_check_period: old.topdown_check_period,
+            cross_msg_fees: params.cross_msg_fees.clone(),
+            governor: params.governor,
+            checkpoint_retention: params.checkpoint_retention,
+            postbox_ttl: params.postbox_ttl,
+            recovery_deadline: params.recovery_deadline,
+            guardian: params.guardian,
+            treasury: params.treasury,
+            checkpoint_limits: params.checkpoint_limits.clone(),
+        },
+    )?;
+    st.network_name = old.network_name;
+    st.total_subnets = old.total_subnets;
+    st.min_stake = old.min_stake;
+    st.bottomup_check_period = old.bottomup_check_period;
+    st.topdown_check_period = old.topdown_check_period;
+    st.bottomup_nonce = old.bottomup_nonce;
+    st.applied_topdown_nonce = old.applied_topdown_nonce;
+    st.validators = Validators::new(store, old.validators.validators, &resolve)?;
+    // windows were aligned to epoch zero before the genesis offset, which is
+    // the default of an initialized state.
+    st.initialized = old.initialized;
+
+    st.topdown_checkpoint_voting = migrate_voting_v0(store, old.topdown_checkpoint_voting)?;
+
+    let subnets: Map<BS, SubnetV0> =
+        make_map_with_root_and_bitwidth(&old.subnets, store, HAMT_BIT_WIDTH)
+            .map_err(|e| anyhow!("failed to load subnets: {:?}", e))?;
+    let mut old_subnets = Vec::new();
+    subnets.for_each(|_, sub| {
+        old_subnets.push(sub.clone());
+        Ok(())
+    })?;
+    for sub in old_subnets {
+        let sub = migrate_subnet_v0(store, sub, st.next_subnet_index, epoch)?;
+        st.index_subnet(store, &sub.id)?;
+        st.flush_subnet(store, &sub)?;
+    }
+
+    st.bottomup_checkpoints =
+        migrate_checkpoints(store, &old.bottomup_checkpoints, st.bottomup_check_period)?
+            .ok_or_else(|| anyhow!("checkpoints already migrated"))?;
+
+    let postbox: Map<BS, Vec<u8>> =
+        make_map_with_root_and_bitwidth(&old.postbox, store, HAMT_BIT_WIDTH)
+            .map_err(|e| anyhow!("failed to load postbox: {:?}", e))?;
+    let mut old_items = Vec::new();
+    postbox.for_each(|_, bytes| {
+        let item: PostBoxItemV0 = cbor::deserialize(&RawBytes::from(bytes.clone()), "postbox")?;
+        old_items.push(item);
+        Ok(())
+    })?;
+    for item in old_items {
+        st.insert_postbox(store, item.owners, item.cross_msg.into(), epoch, &resolve)?;
+    }
+
+    Ok(store.put_cbor(&st, Code::Blake2b256)?)
+}
+
+/// Migrates a voting on checkpoints in their version 0 layout, `V0`, to the
+/// current layout `V`, re-keying the pending votes by their new unique key.
+pub fn migrate_voting_v0<BS, V0, V>(store: &BS, old: Voting<V0>) -> anyhow::Result<Voting<V>>
+where
+    BS: Blockstore,
+    V0: Clone + DeserializeOwned + Serialize,
+    V: From<V0> + UniqueVote + DeserializeOwned + Serialize,
+{
+    let mut voting = Voting::new_with_ratio(
+        store,
+        old.genesis_epoch,
+        old.submission_period,
+        old.threshold_ratio.0,
+        old.threshold_ratio.1,
+    )?;
+    voting.last_voting_executed_epoch = old.last_voting_executed_epoch;
+    voting.executable_epoch_queue = old.executable_epoch_queue;
+
+    let old_submissions = old.epoch_vote_submissions.load(store)?;
+    let mut epochs = Vec::new();
+    old_submissions.for_each(|epoch, submissions| {
+        epochs.push((epoch.clone(), submissions.clone()));
+        Ok(())
+    })?;
+    voting.epoch_vote_submissions.update(store, |hamt| {
+        for (epoch, submissions) in epochs {
+            hamt.set(epoch, migrate_submissions_v0(store, submissions)?)?;
+        }
+        Ok(())
+    })?;
+    Ok(voting)
+}
+
+fn migrate_submissions_v0<BS, V0, V>(
+    store: &BS,
+    old: EpochVoteSubmissions<V0>,
+) -> anyhow::Result<EpochVoteSubmissions<V>>
+where
+    BS: Blockstore,
+    V0: Clone + DeserializeOwned + Serialize,
+    V: From<V0> + UniqueVote + DeserializeOwned + Serialize,
+{
+    let old_votes = old.submissions.load(store)?;
+    let old_weights = old.submission_weights.load(store)?;
+    let mut votes = Vec::new();
+    old_votes.for_each(|key, vote| {
+        let weight = old_weights.get(key)?.cloned();
+        votes.push((key.clone(), V::from(vote.clone()), weight));
+        Ok(())
+    })?;
+
+    let mut submissions = EpochVoteSubmissions::<V>::new(store)?;
+    submissions.total_submission_weight = old.total_submission_weight;
+    submissions.submitters = old.submitters;
+    for (old_key, vote, weight) in votes {
+        let key = vote.unique_key()?;
+        if old.most_voted_key.as_ref().map(|k| k.as_slice()) == Some(old_key.0.as_slice()) {
+            submissions.most_voted_key = Some(key.clone());
+        }
+        if let Some(weight) = weight {
+            submissions.submission_weights.update(store, |hamt| {
+                hamt.set(BytesKey::from(key.as_slice()), weight)?;
+                Ok(())
+            })?;
+        }
+        submissions.submissions.update(store, |hamt| {
+            hamt.set(BytesKey::from(key.as_slice()), vote)?;
+            Ok(())
+        })?;
+    }
+    Ok(submissions)
+}
+
+fn migrate_subnet_v0<BS: Blockstore>(
+    store: &BS,
+    sub: SubnetV0,
+    index: u64,
+    epoch: ChainEpoch,
+) -> anyhow::Result<Subnet> {
+    let old_msgs: Array<CrossMsgV0, BS> = Array::load(&sub.top_down_msgs, store)
+        .map_err(|e| anyhow!("failed to load top-down messages: {:?}", e))?;
+    let mut top_down_msgs = TCid::new_amt(store)?;
+    top_down_msgs.update(store, |msgs| {
+        old_msgs.for_each(|nonce, msg| {
+            msgs.set(nonce, msg.clone().into())
+                .map_err(|e| anyhow!("failed to set top-down message: {:?}", e))
+        })?;
+        Ok(())
+    })?;
+
+    Ok(Subnet {
+        id: sub.id,
+        index,
+        stake: sub.stake,
+        top_down_msgs,
+        topdown_nonce: sub.topdown_nonce,
+        circ_supply: sub.circ_supply,
+        status: sub.status,
+        prev_checkpoint: sub.prev_checkpoint.map(BottomUpCheckpoint::from),
+        applied_bottomup_nonce: sub.applied_bottomup_nonce,
+        genesis_epoch: sub.genesis_epoch,
+        // legacy subnet actors counted checkpoints from epoch zero
+        bottomup_genesis_epoch: 0,
+        limits: SubnetLimits::default(),
+        release_window: 0,
+        released_value: TokenAmount::zero(),
+        held_msgs: TCid::new_amt(store)?,
+        held_msgs_head: 0,
+        held_msgs_tail: 0,
+        // the epoch of the last commit wasn't recorded, the recovery deadline of
+        // the subnet is counted from the upgrade
+        last_checkpoint_epoch: epoch,
+        refund_nonce: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::upgrade::{
+        migrate_state, state_version, BatchCrossMsgsV0, BottomUpCheckpointV0, CheckDataV0,
+        ChildCheckV0, CrossMsgV0, PostBoxItemV0, StateV0, SubnetV0, TopDownCheckpointV0,
+        ValidatorsV0, STATE_VERSION,
+    };
+    use crate::{
+        get_checkpoint, BottomUpCheckpoint, CheckpointLimits, CrossMsgFees, State, Status,
+        TopDownCheckpoint, UpgradeStateParams, CHECKPOINT_GENESIS_CID, CROSSMSG_AMT_BITWIDTH,
+    };
+    use cid::multihash::Code;
+    use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
+    use fil_actors_runtime::{cbor, make_empty_map, Array};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::{CborStore, RawBytes};
+    use fvm_ipld_hamt::BytesKey;
+    use fvm_shared::address::Address;
+    use fvm_shared::clock::ChainEpoch;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::METHOD_SEND;
+    use ipc_actor_common::vote::{EpochVoteSubmissions, UniqueBytesKey, Voting};
+    use ipc_sdk::address::IPCAddress;
+    use ipc_sdk::cross::{CrossMsg, StorableMsg};
+    use ipc_sdk::epoch_key;
+    use ipc_sdk::subnet_id::SubnetID;
+    use ipc_sdk::{Validator, ValidatorSet};
+    use std::str::FromStr;
+
+    fn cross_msg_v0(subnet: &SubnetID, nonce: u64) -> CrossMsgV0 {
+        CrossMsgV0 {
+            msg: StorableMsg {
+                from: IPCAddress::new(subnet, &Address::new_id(100)).unwrap(),
+                to: IPCAddress::new(subnet, &Address::new_id(101)).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: TokenAmount::from_atto(1000),
+                nonce,
+            },
+            wrapped: false,
+        }
+    }
+
+    fn checkpoint_v0(subnet: &SubnetID, epoch: ChainEpoch) -> BottomUpCheckpointV0 {
+        BottomUpCheckpointV0 {
+            data: CheckDataV0 {
+                source: subnet.clone(),
+                proof: vec![1, 2, 3],
+                epoch,
+                prev_check: (*CHECKPOINT_GENESIS_CID).into(),
+                children: vec![ChildCheckV0 {
+                    source: subnet.clone(),
+                    checks: vec![(*CHECKPOINT_GENESIS_CID).into()],
+                }],
+                cross_msgs: BatchCrossMsgsV0(
+                    vec![cross_msg_v0(subnet, 0)],
+                    TokenAmount::from_atto(10),
+                ),
+            },
+            sig: vec![4, 5, 6],
+        }
+    }
+
+    /// Top-down checkpoint voting of version 0 with a pending vote of `validator` for `vote`.
+    fn voting_v0(
+        store: &MemoryBlockstore,
+        period: ChainEpoch,
+        validator: &Address,
+        vote: &TopDownCheckpointV0,
+    ) -> Voting<TopDownCheckpointV0> {
+        // the key is rebuilt in the migration, so any key identifies the vote
+        let key = UniqueBytesKey(vec![1, 2, 3]);
+        let mut submitters = make_empty_map(store, HAMT_BIT_WIDTH);
+        submitters
+            .set(BytesKey::from(validator.to_bytes()), ())
+            .unwrap();
+        let mut weights = make_empty_map(store, HAMT_BIT_WIDTH);
+        weights
+            .set(BytesKey::from(key.as_slice()), TokenAmount::from_atto(100))
+            .unwrap();
+        let mut votes = make_empty_map(store, HAMT_BIT_WIDTH);
+        votes
+            .set(BytesKey::from(key.as_slice()), vote.clone())
+            .unwrap();
+
+        let mut epochs = make_empty_map(store, HAMT_BIT_WIDTH);
+        epochs
+            .set(
+                epoch_key(vote.epoch),
+                EpochVoteSubmissions {
+                    total_submission_weight: TokenAmount::from_atto(100),
+                    most_voted_key: Some(key),
+                    submitters: submitters.flush().unwrap().into(),
+                    submission_weights: weights.flush().unwrap().into(),
+                    submissions: votes.flush().unwrap().into(),
+                },
+            )
+            .unwrap();
+        Voting {
+            genesis_epoch: 5,
+            submission_period: period,
+            last_voting_executed_epoch: 5,
+            executable_epoch_queue: None,
+            epoch_vote_submissions: epochs.flush().unwrap().into(),
+            threshold_ratio: (2, 3),
+        }
+    }
+
+    fn validators_v0() -> ValidatorsV0 {
+        ValidatorsV0 {
+            validators: ValidatorSet::new(
+                vec![Validator {
+                    addr: Address::new_id(10),
+                    net_addr: "10".to_string(),
+                    weight: TokenAmount::from_atto(100),
+                }],
+                1,
+            ),
+            total_weight: TokenAmount::from_atto(100),
+        }
+    }
+
+    fn assert_validators_indexed(store: &MemoryBlockstore, st: &State) {
+        let weights = st.validators.weights.load(store).unwrap();
+        assert_eq!(
+            weights
+                .get(&BytesKey::from(Address::new_id(10).to_bytes()))
+                .unwrap(),
+            Some(&TokenAmount::from_atto(100))
+        );
+        assert_eq!(st.validators.total_weight, TokenAmount::from_atto(100));
+        assert!(st.validators.unresolved.is_empty());
+    }
+
+    fn upgrade_params() -> UpgradeStateParams {
+        UpgradeStateParams {
+            cross_msg_fees: CrossMsgFees::uniform(TokenAmount::from_atto(100)),
+            governor: Address::new_id(200),
+            checkpoint_retention: 0,
+            postbox_ttl: 100,
+            recovery_deadline: 1000,
+            guardian: Address::new_id(201),
+            treasury: Some(Address::new_id(202)),
+            checkpoint_limits: CheckpointLimits {
+                max_msgs: 10,
+                max_bytes: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        let store = MemoryBlockstore::new();
+        let network_name = SubnetID::from_str("/r123").unwrap();
+        let shid = SubnetID::from_str("/r123/f0101").unwrap();
+        let period = 10;
+
+        let mut msgs = Array::new_with_bit_width(&store, CROSSMSG_AMT_BITWIDTH);
+        msgs.set(0, cross_msg_v0(&shid, 0)).unwrap();
+        let mut subnets = make_empty_map(&store, HAMT_BIT_WIDTH);
+        subnets
+            .set(
+                shid.to_bytes().into(),
+                SubnetV0 {
+                    id: shid.clone(),
+                    stake: TokenAmount::from_whole(10),
+                    top_down_msgs: msgs.flush().unwrap(),
+                    topdown_nonce: 1,
+                    circ_supply: TokenAmount::from_atto(1000),
+                    status: Status::Active,
+                    prev_checkpoint: Some(checkpoint_v0(&shid, 10)),
+                    applied_bottomup_nonce: 1,
+                    genesis_epoch: 5,
+                },
+            )
+            .unwrap();
+
+        let mut checkpoints = make_empty_map(&store, HAMT_BIT_WIDTH);
+        for epoch in [10, 20] {
+            checkpoints
+                .set(epoch_key(epoch), checkpoint_v0(&network_name, epoch))
+                .unwrap();
+        }
+
+        let owner = Address::new_id(100);
+        let item = PostBoxItemV0 {
+            cross_msg: cross_msg_v0(&shid, 1),
+            owners: Some(vec![owner]),
+        };
+        let mut postbox = make_empty_map(&store, HAMT_BIT_WIDTH);
+        postbox
+            .set(
+                BytesKey::from(CHECKPOINT_GENESIS_CID.to_bytes()),
+                cbor::serialize(&item, "").unwrap().to_vec(),
+            )
+            .unwrap();
+
+        let validator = Address::new_id(10);
+        let vote = TopDownCheckpointV0 {
+            epoch: 15,
+            top_down_msgs: vec![cross_msg_v0(&shid, 2)],
+        };
+
+        let old = StateV0 {
+            network_name: network_name.clone(),
+            total_subnets: 1,
+            min_stake: TokenAmount::from_whole(1),
+            subnets: subnets.flush().unwrap(),
+            bottomup_check_period: period,
+            topdown_check_period: period,
+            bottomup_checkpoints: checkpoints.flush().unwrap(),
+            postbox: postbox.flush().unwrap(),
+            bottomup_nonce: 3,
+            applied_topdown_nonce: 2,
+            topdown_checkpoint_voting: voting_v0(&store, period, &validator, &vote),
+            validators: validators_v0(),
+            initialized: true,
+        };
+        let root = store.put_cbor(&old, Code::Blake2b256).unwrap();
+        assert_eq!(state_version(&store, &root).unwrap(), 0);
+
+        let epoch = 50;
+        let root = migrate_state(&store, &root, &upgrade_params(), epoch, |addr| Some(*addr))
+            .unwrap()
+            .unwrap();
+        assert_eq!(state_version(&store, &root).unwrap(), STATE_VERSION);
+        // migrating an up to date state is a no-op
+        assert!(
+            migrate_state(&store, &root, &upgrade_params(), epoch, |addr| Some(*addr))
+                .unwrap()
+                .is_none()
+        );
+
+        let st: State = store.get_cbor(&root).unwrap().unwrap();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.network_name, network_name);
+        assert_eq!(st.total_subnets, 1);
+        assert_eq!(st.bottomup_nonce, 3);
+        assert_eq!(st.applied_topdown_nonce, 2);
+        assert!(st.initialized);
+        assert_eq!(st.governor, Address::new_id(200));
+        assert_eq!(st.guardian, Address::new_id(201));
+        assert_eq!(st.postbox_ttl, 100);
+        assert!(!st.paused);
+        assert_eq!(st.treasury, Some(Address::new_id(202)));
+        assert_eq!(
+            st.bottomup_checkpoint_limits,
+            upgrade_params().checkpoint_limits
+        );
+        assert_eq!(st.topdown_checkpoint_voting.genesis_epoch, 5);
+        assert_validators_indexed(&store, &st);
+
+        // the pending votes are kept, keyed by the checkpoint in its new layout
+        let votes = st
+            .topdown_checkpoint_voting
+            .epoch_vote_submissions
+            .load(&store)
+            .unwrap();
+        let submissions = votes.get(&epoch_key(vote.epoch)).unwrap().unwrap();
+        assert_eq!(
+            submissions.total_submission_weight,
+            TokenAmount::from_atto(100)
+        );
+        assert!(submissions.has_submitted(&store, &validator).unwrap());
+        assert_eq!(
+            submissions.load_most_voted_submission(&store).unwrap(),
+            Some(TopDownCheckpoint::from(vote))
+        );
+        assert_eq!(
+            submissions.load_most_voted_weight(&store).unwrap(),
+            Some(TokenAmount::from_atto(100))
+        );
+
+        let subnets = st.subnets.load(&store).unwrap();
+        let sub = subnets.get(&shid.to_bytes()).unwrap().unwrap();
+        assert_eq!(sub.topdown_nonce, 1);
+        let (listed, _) = st.list_subnets(&store, 0, 10).unwrap();
+        assert_eq!(listed, vec![sub.clone()]);
+        assert_eq!(sub.circ_supply, TokenAmount::from_atto(1000));
+        assert_eq!(sub.genesis_epoch, 5);
+        assert_eq!(
+            sub.prev_checkpoint,
+            Some(BottomUpCheckpoint::from(checkpoint_v0(&shid, 10)))
+        );
+        let msgs = sub.top_down_msgs.load(&store).unwrap();
+        assert_eq!(
+            msgs.get(0).unwrap().unwrap(),
+            &CrossMsg::from(cross_msg_v0(&shid, 0))
+        );
+
+        let checkpoints = st.bottomup_checkpoints.load(&store).unwrap();
+        for epoch in [10, 20] {
+            let ch = get_checkpoint(&checkpoints, epoch, period)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                ch,
+                &BottomUpCheckpoint::from(checkpoint_v0(&network_name, epoch))
+            );
+            assert_eq!(ch.data.cross_msgs.cross_msgs.as_ref().unwrap().len(), 1);
+            assert_eq!(ch.data.applied_topdown_nonce, 0);
+        }
+
+        let (cids, _) = st.list_postbox_cids(&store, &owner, 0, 10).unwrap();
+        assert_eq!(cids.len(), 1);
+        let migrated = st.load_from_postbox(&store, cids[0]).unwrap();
+        assert_eq!(migrated.cross_msg, CrossMsg::from(item.cross_msg));
+        assert_eq!(migrated.owners, Some(vec![owner]));
+        assert_eq!(migrated.inserted_epoch, epoch);
+    }
+
+    #[test]
+    fn test_current_version_roundtrip() {
+        let store = MemoryBlockstore::new();
+        let st = State::new(
+            &store,
+            crate::ConstructorParams {
+                network_name: "/r123".to_string(),
+                bottomup_check_period: 10,
+                topdown_check_period: 10,
+                cross_msg_fees: CrossMsgFees::uniform(TokenAmount::from_atto(100)),
+                governor: Address::new_id(200),
+                checkpoint_retention: 0,
+                postbox_ttl: 0,
+                recovery_deadline: 0,
+                guardian: Address::new_id(201),
+                treasury: None,
+                checkpoint_limits: CheckpointLimits::default(),
+            },
+        )
+        .unwrap();
+        let root = store.put_cbor(&st, Code::Blake2b256).unwrap();
+        assert_eq!(state_version(&store, &root).unwrap(), STATE_VERSION);
+
+        let decoded: State = store.get_cbor(&root).unwrap().unwrap();
+        assert_eq!(store.put_cbor(&decoded, Code::Blake2b256).unwrap(), root);
+        assert!(
+            migrate_state(&store, &root, &upgrade_params(), 0, |addr| Some(*addr))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -1,23 +1,39 @@
+use cid::multihash::Code;
 use cid::Cid;
+use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
+use fil_actors_runtime::deserialize_block;
 use fil_actors_runtime::runtime::Runtime;
-use fil_actors_runtime::test_utils::MockRuntime;
-use fil_actors_runtime::BURNT_FUNDS_ACTOR_ADDR;
-use fvm_ipld_encoding::RawBytes;
-use fvm_shared::address::Address;
+use fil_actors_runtime::test_utils::{
+    expect_abort, MockRuntime, ACCOUNT_ACTOR_CODE_ID, SUBNET_ACTOR_CODE_ID, SYSTEM_ACTOR_CODE_ID,
+};
+use fil_actors_runtime::{make_empty_map, BURNT_FUNDS_ACTOR_ADDR, SYSTEM_ACTOR_ADDR};
+use fvm_ipld_encoding::ipld_block::IpldBlock;
+use fvm_ipld_encoding::{to_vec, CborStore, RawBytes};
+use fvm_shared::address::{Address, BLS_PUB_LEN};
 use fvm_shared::bigint::Zero;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
-use fvm_shared::METHOD_SEND;
-use ipc_actor_common::vote::{EpochVoteSubmissions, UniqueVote};
+use fvm_shared::{MethodNum, METHOD_SEND};
+use ipc_actor_common::vote::{EpochVoteSubmissions, UniqueVote, Voting};
 use ipc_gateway::checkpoint::{window_epoch, BatchCrossMsgs, ChildCheck};
-use ipc_gateway::Status::{Active, Inactive};
+use ipc_gateway::recovery::recovery_root;
+use ipc_gateway::upgrade::{StateV0, ValidatorsV0};
+use ipc_gateway::Status::{Active, Frozen, Inactive};
 use ipc_gateway::{
-    get_topdown_msg, BottomUpCheckpoint, CrossMsg, IPCAddress, PostBoxItem, State, StorableMsg,
-    TopDownCheckpoint, CROSS_MSG_FEE, INITIAL_VALIDATOR_FUNDS, SUBNET_ACTOR_REWARD_METHOD,
+    cross_msg_cid, get_checkpoint, get_topdown_msg, Actor, BottomUpCheckpoint, CheckpointEvent,
+    CheckpointLimits, CrossMsg, CrossMsgFees, CrossMsgReceipt, GatewayEvent, GetCheckpointParams,
+    GetTopDownMsgsParams, IPCAddress, InitGenesisEpoch, ListSubnetsParams, ListSubnetsReturn,
+    Method, PostBoxItem, RecoverFundsParams, RegisterParams, ReleaseHeldMsgsParams,
+    RemovePropagatorParams, SetSubnetLimitsParams, State, StorableMsg, Subnet, SubnetLimits,
+    TopDownCheckpoint, TransferPostboxOwnershipParams, UpgradeStateParams,
+    WhitelistPropagatorParams, DEFAULT_CHECKPOINT_PERIOD, DEFAULT_CROSS_MSG_FEE,
+    INITIAL_VALIDATOR_FUNDS, MAX_FROZEN_REFUNDS, MIN_COLLATERAL_AMOUNT, POSTBOX_EXPIRED_EXIT_CODE,
+    SUBNET_ACTOR_REWARD_METHOD,
 };
 use ipc_sdk::subnet_id::SubnetID;
 use ipc_sdk::{epoch_key, Validator, ValidatorSet};
+use ipc_treasury::UnlockParams;
 use primitives::TCid;
 use std::collections::BTreeSet;
 use std::ops::Mul;
@@ -84,6 +100,52 @@ fn register_subnet() {
     h.check_state();
 }
 
+#[test]
+fn test_bottomup_genesis_epoch() {
+    let (h, mut rt) = setup_root();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // the subnet actor registers the epoch from which its voting counts checkpoints
+    let genesis_epoch = 5;
+    let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+    rt.set_caller(*SUBNET_ACTOR_CODE_ID, *SUBNET_ONE);
+    rt.set_value(value.clone());
+    rt.set_balance(value);
+    rt.expect_validate_caller_any();
+    rt.call::<Actor>(
+        Method::Register as MethodNum,
+        IpldBlock::serialize_cbor(&RegisterParams {
+            bottomup_genesis_epoch: genesis_epoch,
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.bottomup_genesis_epoch, genesis_epoch);
+
+    // and the gateway of the subnet is initialized with it
+    let mut child_rt = new_runtime();
+    let child = new_harness(shid);
+    child.construct(&mut child_rt);
+    child_rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+    child_rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    child_rt
+        .call::<Actor>(
+            Method::InitGenesisEpoch as MethodNum,
+            IpldBlock::serialize_cbor(&InitGenesisEpoch {
+                genesis_epoch: sub.genesis_epoch,
+                bottomup_genesis_epoch: sub.bottomup_genesis_epoch,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    child_rt.verify();
+    let st: State = child_rt.get_state();
+    assert_eq!(st.genesis_epoch(), genesis_epoch);
+    assert_eq!(st.bottomup_executed_epoch, genesis_epoch);
+}
+
 #[test]
 fn add_stake() {
     let (h, mut rt) = setup_root();
@@ -273,7 +335,7 @@ fn checkpoint_commit() {
     let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
     assert_eq!(
         commit.epoch(),
-        window_epoch(epoch, st.bottomup_check_period)
+        window_epoch(epoch, st.genesis_epoch(), st.bottomup_check_period)
     );
     let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
     assert_eq!(&child_check.checks.len(), &1);
@@ -305,7 +367,7 @@ fn checkpoint_commit() {
     let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
     assert_eq!(
         commit.epoch(),
-        window_epoch(epoch, st.bottomup_check_period)
+        window_epoch(epoch, st.genesis_epoch(), st.bottomup_check_period)
     );
     let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
     assert_eq!(&child_check.checks.len(), &2);
@@ -338,7 +400,7 @@ fn checkpoint_commit() {
     let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
     assert_eq!(
         commit.epoch(),
-        window_epoch(epoch, st.bottomup_check_period)
+        window_epoch(epoch, st.genesis_epoch(), st.bottomup_check_period)
     );
     let child_check = has_childcheck_source(&commit.data.children, &shid_two).unwrap();
     assert_eq!(&child_check.checks.len(), &1);
@@ -407,7 +469,7 @@ fn checkpoint_crossmsgs() {
     let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
     assert_eq!(
         commit.epoch(),
-        window_epoch(epoch, st.bottomup_check_period)
+        window_epoch(epoch, st.genesis_epoch(), st.bottomup_check_period)
     );
     let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
     assert_eq!(&child_check.checks.len(), &1);
@@ -534,6 +596,309 @@ fn test_fund() {
     .unwrap();
 }
 
+#[test]
+fn test_prune_applied_topdown_msgs() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    let mut exp_cs = TokenAmount::zero();
+    for nonce in 1..=3 {
+        exp_cs += amount.clone();
+        h.fund(
+            &mut rt,
+            &funder,
+            &funder,
+            &shid,
+            ExitCode::OK,
+            amount.clone(),
+            nonce,
+            &exp_cs,
+        )
+        .unwrap();
+    }
+
+    // the child can't have applied more messages than the ones committed
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.applied_topdown_nonce = 4;
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // messages already applied in the child are pruned
+    ch.data.applied_topdown_nonce = 2;
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    assert!(get_topdown_msg(&crossmsgs, 0).unwrap().is_none());
+    assert!(get_topdown_msg(&crossmsgs, 1).unwrap().is_none());
+    assert!(get_topdown_msg(&crossmsgs, 2).unwrap().is_some());
+    assert_eq!(sub.topdown_nonce, 3);
+
+    // the applied nonce can't go backwards
+    let mut next = BottomUpCheckpoint::new(shid.clone(), epoch + 19);
+    next.data.prev_check = ch.cid().into();
+    next.data.applied_topdown_nonce = 1;
+    h.commit_child_check(&mut rt, &shid, &next, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    next.data.applied_topdown_nonce = 3;
+    h.commit_child_check(&mut rt, &shid, &next, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    assert_eq!(crossmsgs.count(), 0);
+}
+
+#[test]
+fn test_recover_funds_from_frozen_subnet() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    let mut exp_cs = TokenAmount::zero();
+    for nonce in 1..=2 {
+        exp_cs += amount.clone();
+        h.fund(
+            &mut rt,
+            &funder,
+            &funder,
+            &shid,
+            ExitCode::OK,
+            amount.clone(),
+            nonce,
+            &exp_cs,
+        )
+        .unwrap();
+    }
+
+    // the child commits the balances after applying the first top-down message
+    let balances = vec![
+        (
+            Address::new_secp256k1(&[1; fvm_shared::address::SECP_PUB_LEN]).unwrap(),
+            TokenAmount::from_atto(6 * 10_u64.pow(17)),
+        ),
+        (
+            Address::new_bls(&[2; fvm_shared::address::BLS_PUB_LEN]).unwrap(),
+            TokenAmount::from_atto(3 * 10_u64.pow(17)),
+        ),
+        (
+            Address::new_id(2003),
+            TokenAmount::from_atto(10_u64.pow(17)),
+        ),
+    ];
+    let (root, proof) = recovery_root(&balances, 0).unwrap();
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.applied_topdown_nonce = 1;
+    ch.data.proof = root;
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+
+    let (address, balance) = balances[0].clone();
+    let params = RecoverFundsParams {
+        subnet: shid.clone(),
+        address,
+        amount: balance.clone(),
+        proof,
+    };
+
+    // funds can't be recovered from an active subnet
+    let caller = Address::new_id(1002);
+    h.recover_funds(
+        &mut rt,
+        &caller,
+        params.clone(),
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
+
+    // the subnet can't be frozen before the deadline, counted from the epoch in which
+    // the checkpoint was committed regardless of the epoch of the checkpoint
+    let deadline = epoch + *DEFAULT_RECOVERY_DEADLINE;
+    rt.set_epoch(deadline - 1);
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // freezing refunds the top-down message not applied in the child
+    rt.set_epoch(deadline);
+    rt.set_balance(value.clone() + amount.clone() * 2);
+    rt.expect_send(
+        *TEST_BLS,
+        METHOD_SEND,
+        None,
+        amount.clone(),
+        None,
+        ExitCode::OK,
+    );
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.status, Frozen);
+    assert_eq!(sub.circ_supply, amount);
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+
+    // the balance needs to match the one committed
+    let mut invalid = params.clone();
+    invalid.amount = amount.clone();
+    h.recover_funds(&mut rt, &caller, invalid, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    h.recover_funds(&mut rt, &caller, params.clone(), ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.circ_supply, amount.clone() - balance);
+
+    // funds can only be recovered once
+    h.recover_funds(&mut rt, &caller, params, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    let (address, balance) = balances[1].clone();
+    let (_, proof) = recovery_root(&balances, 1).unwrap();
+    h.recover_funds(
+        &mut rt,
+        &caller,
+        RecoverFundsParams {
+            subnet: shid.clone(),
+            address,
+            amount: balance,
+            proof,
+        },
+        ExitCode::OK,
+    )
+    .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.circ_supply, TokenAmount::from_atto(10_u64.pow(17)));
+
+    // ID addresses of the child can't recover funds
+    let (address, balance) = balances[2].clone();
+    let (_, proof) = recovery_root(&balances, 2).unwrap();
+    h.recover_funds(
+        &mut rt,
+        &caller,
+        RecoverFundsParams {
+            subnet: shid.clone(),
+            address,
+            amount: balance,
+            proof,
+        },
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_freeze_subnet_refunds_in_batches() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(1000);
+    let mut exp_cs = TokenAmount::zero();
+    let count = MAX_FROZEN_REFUNDS + 1;
+    for nonce in 1..=count {
+        exp_cs += amount.clone();
+        h.fund(
+            &mut rt,
+            &funder,
+            &funder,
+            &shid,
+            ExitCode::OK,
+            amount.clone(),
+            nonce,
+            &exp_cs,
+        )
+        .unwrap();
+    }
+
+    // the subnet never committed a checkpoint, the deadline counts from its genesis
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    rt.set_epoch(sub.genesis_epoch + *DEFAULT_RECOVERY_DEADLINE);
+    rt.set_balance(value + amount.clone() * count);
+
+    let caller = Address::new_id(1002);
+    for _ in 0..MAX_FROZEN_REFUNDS {
+        rt.expect_send(
+            *TEST_BLS,
+            METHOD_SEND,
+            None,
+            amount.clone(),
+            None,
+            ExitCode::OK,
+        );
+    }
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.status, Frozen);
+    assert_eq!(sub.refund_nonce, MAX_FROZEN_REFUNDS);
+    assert_eq!(sub.circ_supply, amount);
+
+    // the remaining messages are refunded freezing it again
+    rt.expect_send(
+        *TEST_BLS,
+        METHOD_SEND,
+        None,
+        amount.clone(),
+        None,
+        ExitCode::OK,
+    );
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.refund_nonce, count);
+    assert!(sub.circ_supply.is_zero());
+
+    h.freeze_subnet(&mut rt, &caller, &shid, ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+}
+
+#[test]
+fn test_leave_frozen_subnet() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    rt.set_epoch(sub.genesis_epoch + *DEFAULT_RECOVERY_DEADLINE);
+    h.freeze_subnet(&mut rt, &Address::new_id(1002), &shid, ExitCode::OK)
+        .unwrap();
+
+    // validators leaving the subnet change its membership, which is ignored
+    // as the subnet doesn't run anymore, and release their stake.
+    let validator_set = ValidatorSet::new(vec![], 1);
+    h.commit_membership(&mut rt, &SUBNET_ONE, &validator_set, ExitCode::OK)
+        .unwrap();
+    rt.set_balance(value.clone());
+    h.release_stake(&mut rt, &shid, &value, ExitCode::OK)
+        .unwrap();
+
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.topdown_nonce, 0);
+    assert!(sub.stake.is_zero());
+    assert_eq!(sub.status, Frozen);
+}
+
 #[test]
 fn test_release() {
     let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
@@ -616,6 +981,154 @@ fn test_release() {
     .unwrap();
 }
 
+#[test]
+fn test_release_not_initialized() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let mut rt = new_runtime();
+    let h = new_harness(shid);
+    h.construct(&mut rt);
+
+    let releaser = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
+    rt.set_balance(r_amount.clone());
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::USR_ILLEGAL_STATE,
+        r_amount,
+        2,
+        0,
+        0,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_nonce, 0);
+}
+
+#[test]
+fn test_checkpoint_limits() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let limits = CheckpointLimits {
+        max_msgs: 2,
+        max_bytes: 0,
+    };
+    // only the governor can set the limits
+    h.set_checkpoint_limits(&mut rt, &GUARDIAN, limits.clone(), ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_checkpoint_limits(&mut rt, &GOVERNOR, limits, ExitCode::OK)
+        .unwrap();
+
+    let releaser = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
+    rt.set_balance(7 * r_amount.clone());
+
+    // the window ending at epoch 10 is full after two messages
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        2,
+        0,
+        0,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        3,
+        1,
+        1,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        4,
+        2,
+        0,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_msgs_window, 20);
+    let mut ch = st.get_window_checkpoint(rt.store(), 4).unwrap();
+    assert_eq!(ch.cross_msgs().unwrap().len(), 2);
+    // the size of the messages in the latest window is tracked as they are stored
+    let msgs = st
+        .get_window_checkpoint(rt.store(), 12)
+        .unwrap()
+        .cross_msgs()
+        .unwrap();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(
+        st.bottomup_msgs_window_bytes,
+        to_vec(&msgs[0]).unwrap().len() as u64
+    );
+
+    // new messages follow the ones that overflowed into the window
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        12,
+        3,
+        1,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        13,
+        4,
+        0,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_msgs_window, 30);
+
+    // messages are never stored in a window earlier than the latest one
+    // holding messages, even once the limits are lifted
+    h.set_checkpoint_limits(
+        &mut rt,
+        &GOVERNOR,
+        CheckpointLimits::default(),
+        ExitCode::OK,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &releaser,
+        &to,
+        ExitCode::OK,
+        r_amount.clone(),
+        14,
+        5,
+        1,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_msgs_window, 30);
+    let mut ch = st.get_window_checkpoint(rt.store(), 14).unwrap();
+    assert_eq!(ch.cross_msgs().unwrap().len(), 2);
+}
+
 #[test]
 fn test_send_cross() {
     let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
@@ -659,8 +1172,9 @@ fn test_send_cross() {
         &circ_sup,
     )
     .unwrap();
+    // two hops away, the fee left for the next hop is included in the supply
     let sub = SubnetID::from_str("/r123/f0101/f0101/f01002").unwrap();
-    let circ_sup = circ_sup.clone() + &value;
+    let circ_sup = circ_sup.clone() + &value + split_hop_fee(&shid, &sub).1;
     h.send_cross(
         &mut rt,
         &from,
@@ -675,7 +1189,7 @@ fn test_send_cross() {
     .unwrap();
 
     // bottom-up
-    rt.set_balance(3 * &value);
+    rt.set_balance(3 * (value.clone() + &*DEFAULT_CROSS_MSG_FEE));
     let sub = SubnetID::from_str("/r123/f0102/f0101").unwrap();
     let zero = TokenAmount::zero();
     h.send_cross(
@@ -777,6 +1291,8 @@ fn test_commit_child_check_bu_target_subnet() {
         cross_msgs: Some(vec![CrossMsg {
             msg: msg.clone(),
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         }]),
         fee: fee.clone(),
     };
@@ -803,10 +1319,675 @@ fn test_commit_child_check_bu_target_subnet() {
         .unwrap();
 }
 
-/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
-/// the gateway. It will save it in the postbox.
+/// Bottom-up messages received ahead of the next nonce to apply are held until
+/// the gap is filled, and then applied in order.
 #[test]
-fn test_commit_child_check_bu_not_target_subnet() {
+fn test_apply_out_of_order_bottomup_msgs() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.fund(
+        &mut rt,
+        &Address::new_id(1001),
+        &Address::new_id(1001),
+        &shid,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+
+    let msg_value = TokenAmount::from_atto(10_u64.pow(17));
+    rt.set_balance(3 * msg_value.clone());
+    let msgs: Vec<CrossMsg> = (0..3)
+        .map(|nonce| CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(&shid, &Address::new_id(2000)).unwrap(),
+                to: IPCAddress::new(&ROOTNET_ID, &Address::new_id(2001 + nonce)).unwrap(),
+                method: METHOD_SEND,
+                value: msg_value.clone(),
+                params: RawBytes::default(),
+                nonce,
+            },
+            wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
+        })
+        .collect();
+    let commit = |rt: &mut MockRuntime, ch: &BottomUpCheckpoint, applied: &[CrossMsg]| {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, shid.subnet_actor());
+        rt.expect_validate_caller_any();
+        expect_event(
+            rt,
+            GatewayEvent::CommitChildCheck(CheckpointEvent {
+                subnet: shid.clone(),
+                epoch: ch.epoch(),
+                num_msgs: ch.data.cross_msgs.cross_msgs.as_ref().unwrap().len() as u64,
+                value: ch.total_value(),
+                fee: TokenAmount::zero(),
+            }),
+        );
+        for m in applied {
+            expect_event(
+                rt,
+                GatewayEvent::ApplyMsg(msg_event(m, &TokenAmount::zero())),
+            );
+            rt.expect_send(
+                m.msg.to.raw_addr().unwrap(),
+                METHOD_SEND,
+                None,
+                m.msg.value.clone(),
+                None,
+                ExitCode::OK,
+            );
+        }
+        rt.call::<Actor>(
+            Method::CommitChildCheckpoint as MethodNum,
+            IpldBlock::serialize_cbor(ch).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+    };
+
+    // the messages ahead of the gap are held
+    rt.set_epoch(10);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), 19);
+    ch.data.cross_msgs.cross_msgs = Some(vec![msgs[2].clone(), msgs[1].clone()]);
+    commit(&mut rt, &ch, &[]);
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().applied_bottomup_nonce, 0);
+
+    // filling the gap applies the held messages in order
+    let prev = ch.cid();
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), 29);
+    ch.data.prev_check = TCid::from(prev);
+    ch.data.cross_msgs.cross_msgs = Some(vec![msgs[0].clone()]);
+    commit(&mut rt, &ch, &msgs);
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().applied_bottomup_nonce, 3);
+    let st: State = rt.get_state();
+    verify_empty_map(&rt, st.pending_bottomup_msgs.cid());
+
+    // messages already applied are rejected
+    let prev = ch.cid();
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), 39);
+    ch.data.prev_check = TCid::from(prev);
+    ch.data.cross_msgs.cross_msgs = Some(vec![msgs[1].clone()]);
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+}
+
+#[test]
+fn test_subnet_limits() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let funder = Address::new_id(1001);
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+
+    // only the governor can set the limits of a subnet
+    let limit = TokenAmount::from_atto(10_u64.pow(17));
+    let params = SetSubnetLimitsParams {
+        subnet: shid.clone(),
+        limits: SubnetLimits {
+            circ_supply_cap: value.clone() + limit.clone(),
+            release_limit: limit.clone(),
+        },
+    };
+    h.set_subnet_limits(&mut rt, &funder, params.clone(), ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_subnet_limits(&mut rt, &GOVERNOR, params, ExitCode::OK)
+        .unwrap();
+
+    // funds over the circulating supply cap are rejected
+    let funder = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        limit.clone() + TokenAmount::from_atto(1),
+        2,
+        &value,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        limit.clone(),
+        2,
+        &(value.clone() + limit.clone()),
+    )
+    .unwrap();
+
+    // the messages exceeding the release limit of the window are held
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let msgs: Vec<CrossMsg> = (0..2)
+        .map(|nonce| CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(&shid, &funder).unwrap(),
+                to: IPCAddress::new(&ROOTNET_ID, &to).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: limit.clone(),
+                nonce,
+            },
+            wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
+        })
+        .collect();
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    rt.set_balance(value.clone() * 3);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.cross_msgs = BatchCrossMsgs {
+        cross_msgs: Some(msgs.clone()),
+        fee: TokenAmount::zero(),
+    };
+
+    rt.set_caller(*SUBNET_ACTOR_CODE_ID, shid.subnet_actor());
+    rt.expect_validate_caller_any();
+    expect_event(
+        &mut rt,
+        GatewayEvent::CommitChildCheck(CheckpointEvent {
+            subnet: shid.clone(),
+            epoch: ch.epoch(),
+            num_msgs: 2,
+            value: ch.total_value(),
+            fee: TokenAmount::zero(),
+        }),
+    );
+    expect_event(
+        &mut rt,
+        GatewayEvent::ApplyMsg(msg_event(&msgs[0], &TokenAmount::zero())),
+    );
+    rt.expect_send(to, METHOD_SEND, None, limit.clone(), None, ExitCode::OK);
+    rt.call::<Actor>(
+        Method::CommitChildCheckpoint as MethodNum,
+        IpldBlock::serialize_cbor(&ch).unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.applied_bottomup_nonce, 1);
+    assert_eq!(sub.released_value, limit);
+    assert_eq!((sub.held_msgs_head, sub.held_msgs_tail), (0, 1));
+
+    // held messages can't be released over the limit of the window
+    let caller = Address::new_id(1002);
+    let params = ReleaseHeldMsgsParams {
+        subnet: shid.clone(),
+        force: false,
+    };
+    h.release_held_msgs(&mut rt, &caller, params.clone(), ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!((sub.held_msgs_head, sub.held_msgs_tail), (0, 1));
+
+    // only the governor can force their release
+    let forced = ReleaseHeldMsgsParams {
+        subnet: shid.clone(),
+        force: true,
+    };
+    h.release_held_msgs(&mut rt, &caller, forced, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+
+    // they are released in the next window
+    let st: State = rt.get_state();
+    rt.set_epoch(epoch + st.bottomup_check_period);
+    expect_event(
+        &mut rt,
+        GatewayEvent::ApplyMsg(msg_event(&msgs[1], &TokenAmount::zero())),
+    );
+    rt.expect_send(to, METHOD_SEND, None, limit.clone(), None, ExitCode::OK);
+    h.release_held_msgs(&mut rt, &caller, params, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.applied_bottomup_nonce, 2);
+    assert_eq!((sub.held_msgs_head, sub.held_msgs_tail), (1, 1));
+}
+
+#[test]
+fn test_pause() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let funder = Address::new_id(1001);
+
+    // only the guardian can pause the gateway
+    h.set_paused(&mut rt, &GOVERNOR, true, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_paused(&mut rt, &GUARDIAN, true, ExitCode::OK)
+        .unwrap();
+
+    // funds can't be moved while the gateway is paused
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_STATE,
+        value.clone(),
+        1,
+        &TokenAmount::zero(),
+    )
+    .unwrap();
+
+    // nor can subnets be frozen, as they can't commit checkpoints either
+    rt.set_epoch(*DEFAULT_RECOVERY_DEADLINE);
+    h.freeze_subnet(&mut rt, &funder, &shid, ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+
+    // queries and membership updates keep working
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, funder);
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::GetSubnet as MethodNum,
+            IpldBlock::serialize_cbor(&shid).unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let sub: Option<Subnet> = deserialize_block(ret).unwrap();
+    assert_eq!(sub.unwrap().circ_supply, TokenAmount::zero());
+
+    let validator_set = ValidatorSet::new(
+        vec![Validator {
+            addr: Address::new_id(1),
+            net_addr: "1".to_string(),
+            weight: TokenAmount::from_atto(1),
+        }],
+        1,
+    );
+    h.set_membership(&mut rt, validator_set.clone()).unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.validators.validators, validator_set);
+
+    // funds can be moved again once unpaused
+    h.set_paused(&mut rt, &GOVERNOR, false, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_paused(&mut rt, &GUARDIAN, false, ExitCode::OK)
+        .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_upgrade_state() {
+    let mut rt = new_runtime();
+    let h = new_harness(ROOTNET_ID.clone());
+
+    // store the state of a gateway deployed before the state was versioned
+    let store = rt.store.clone();
+    let empty_map = || {
+        make_empty_map::<_, ()>(&*store, HAMT_BIT_WIDTH)
+            .flush()
+            .unwrap()
+    };
+    let old = StateV0 {
+        network_name: ROOTNET_ID.clone(),
+        total_subnets: 0,
+        min_stake: TokenAmount::from_whole(1),
+        subnets: empty_map(),
+        bottomup_check_period: DEFAULT_CHECKPOINT_PERIOD,
+        topdown_check_period: *DEFAULT_TOPDOWN_PERIOD,
+        bottomup_checkpoints: empty_map(),
+        postbox: empty_map(),
+        bottomup_nonce: 0,
+        applied_topdown_nonce: 0,
+        topdown_checkpoint_voting: Voting {
+            genesis_epoch: 0,
+            submission_period: *DEFAULT_TOPDOWN_PERIOD,
+            last_voting_executed_epoch: 0,
+            executable_epoch_queue: None,
+            epoch_vote_submissions: empty_map().into(),
+            threshold_ratio: (2, 3),
+        },
+        validators: ValidatorsV0 {
+            validators: ValidatorSet::default(),
+            total_weight: TokenAmount::zero(),
+        },
+        initialized: true,
+    };
+    rt.state = Some(store.put_cbor(&old, Code::Blake2b256).unwrap());
+
+    let params = || UpgradeStateParams {
+        cross_msg_fees: CrossMsgFees::uniform(DEFAULT_CROSS_MSG_FEE.clone()),
+        governor: *GOVERNOR,
+        checkpoint_retention: 0,
+        postbox_ttl: *DEFAULT_POSTBOX_TTL,
+        recovery_deadline: *DEFAULT_RECOVERY_DEADLINE,
+        guardian: *GUARDIAN,
+        treasury: None,
+        checkpoint_limits: CheckpointLimits::default(),
+    };
+
+    // only the system actor can upgrade the state
+    h.upgrade_state(&mut rt, &GOVERNOR, params(), ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.upgrade_state(&mut rt, &SYSTEM_ACTOR_ADDR, params(), ExitCode::OK)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.network_name, *ROOTNET_ID);
+    assert_eq!(st.governor, *GOVERNOR);
+    assert_eq!(st.guardian, *GUARDIAN);
+    assert!(st.initialized);
+    h.check_state();
+
+    // upgrading an up to date state is a no-op
+    let root = rt.state.unwrap();
+    h.upgrade_state(&mut rt, &SYSTEM_ACTOR_ADDR, params(), ExitCode::OK)
+        .unwrap();
+    assert_eq!(rt.state.unwrap(), root);
+}
+
+/// This test covers the case where a bottom up cross_msg fails to execute in its target
+/// subnet. The failure is recorded and a receipt refunding the value is sent back to the sender.
+#[test]
+fn test_commit_child_check_bu_failed_msg() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    h.register(
+        &mut rt,
+        &SUBNET_ONE,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+        ExitCode::OK,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &Address::new_id(1001),
+        &Address::new_id(1001),
+        &shid,
+        ExitCode::OK,
+        TokenAmount::from_atto(10_u64.pow(18)),
+        1,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+    )
+    .unwrap();
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let msg = StorableMsg {
+        to: IPCAddress::new(&ROOTNET_ID, &to).unwrap(),
+        from: IPCAddress::new(&shid, &from).unwrap(),
+        method: 2,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: 0,
+    };
+    let cross_msg = CrossMsg {
+        msg: msg.clone(),
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+    };
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.cross_msgs = BatchCrossMsgs {
+        cross_msgs: Some(vec![cross_msg.clone()]),
+        fee: TokenAmount::zero(),
+    };
+
+    // the execution fails but the checkpoint is still committed
+    rt.expect_send(
+        to,
+        msg.method,
+        None,
+        msg.value.clone(),
+        None,
+        ExitCode::USR_UNHANDLED_MESSAGE,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+
+    // the failure is recorded
+    let receipt = CrossMsgReceipt {
+        msg: msg.clone(),
+        exit_code: ExitCode::USR_UNHANDLED_MESSAGE,
+        return_data: RawBytes::default(),
+    };
+    let st: State = rt.get_state();
+    assert_eq!(
+        st.get_failed_msg(rt.store(), &cross_msg_cid(&cross_msg).unwrap())
+            .unwrap(),
+        Some(receipt.clone())
+    );
+
+    // and a receipt refunding the value is sent back to the sender
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.topdown_nonce, 2);
+    assert_eq!(sub.applied_bottomup_nonce, 1);
+    assert_eq!(sub.circ_supply, TokenAmount::from_atto(10_u64.pow(18)));
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let receipt_msg = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(
+        receipt_msg.from,
+        IPCAddress::new(&ROOTNET_ID, &ACTOR).unwrap()
+    );
+    assert_eq!(receipt_msg.to, msg.from);
+    assert_eq!(receipt_msg.method, METHOD_SEND);
+    assert_eq!(receipt_msg.value, value);
+    assert_eq!(
+        receipt_msg.params.deserialize::<CrossMsgReceipt>().unwrap(),
+        receipt
+    );
+}
+
+/// This test covers the case where the sender of a bottom up cross_msg requested a reply. The
+/// result of the execution is sent back to the callback method of the sender.
+#[test]
+fn test_commit_child_check_bu_reply() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    h.register(
+        &mut rt,
+        &SUBNET_ONE,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+        ExitCode::OK,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &Address::new_id(1001),
+        &Address::new_id(1001),
+        &shid,
+        ExitCode::OK,
+        TokenAmount::from_atto(10_u64.pow(18)),
+        1,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+    )
+    .unwrap();
+
+    let from = Address::new_id(1002);
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let reply_method = frc42_dispatch::method_hash!("Callback");
+
+    let msg = StorableMsg {
+        to: IPCAddress::new(&ROOTNET_ID, &to).unwrap(),
+        from: IPCAddress::new(&shid, &from).unwrap(),
+        method: 2,
+        value: TokenAmount::from_atto(10_u64.pow(17)),
+        params: RawBytes::default(),
+        nonce: 0,
+    };
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.cross_msgs = BatchCrossMsgs {
+        cross_msgs: Some(vec![CrossMsg {
+            msg: msg.clone(),
+            wrapped: false,
+            reply_method: Some(reply_method),
+            fee: TokenAmount::zero(),
+        }]),
+        fee: TokenAmount::zero(),
+    };
+
+    let ret = IpldBlock::serialize_cbor(&42_u64).unwrap();
+    rt.expect_send(
+        to,
+        msg.method,
+        None,
+        msg.value.clone(),
+        ret.clone(),
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+
+    // the result is sent back to the callback of the sender without any value
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.topdown_nonce, 2);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let reply = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(reply.to, msg.from);
+    assert_eq!(reply.method, reply_method);
+    assert_eq!(reply.value, TokenAmount::zero());
+    assert_eq!(
+        reply.params.deserialize::<CrossMsgReceipt>().unwrap(),
+        CrossMsgReceipt {
+            msg,
+            exit_code: ExitCode::OK,
+            return_data: RawBytes::new(ret.unwrap().data),
+        }
+    );
+}
+
+/// This test covers the case where a bottom up cross_msg requesting a reply fails. The value is
+/// refunded with a plain transfer and the receipt is sent to the callback without any value.
+#[test]
+fn test_commit_child_check_bu_reply_failed() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    h.register(
+        &mut rt,
+        &SUBNET_ONE,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+        ExitCode::OK,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &Address::new_id(1001),
+        &Address::new_id(1001),
+        &shid,
+        ExitCode::OK,
+        TokenAmount::from_atto(10_u64.pow(18)),
+        1,
+        &TokenAmount::from_atto(10_u64.pow(18)),
+    )
+    .unwrap();
+
+    let from = Address::new_id(1002);
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let reply_method = frc42_dispatch::method_hash!("Callback");
+
+    let msg = StorableMsg {
+        to: IPCAddress::new(&ROOTNET_ID, &to).unwrap(),
+        from: IPCAddress::new(&shid, &from).unwrap(),
+        method: 2,
+        value: TokenAmount::from_atto(10_u64.pow(17)),
+        params: RawBytes::default(),
+        nonce: 0,
+    };
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = BottomUpCheckpoint::new(shid.clone(), epoch + 9);
+    ch.data.cross_msgs = BatchCrossMsgs {
+        cross_msgs: Some(vec![CrossMsg {
+            msg: msg.clone(),
+            wrapped: false,
+            reply_method: Some(reply_method),
+            fee: TokenAmount::zero(),
+        }]),
+        fee: TokenAmount::zero(),
+    };
+
+    rt.expect_send(
+        to,
+        msg.method,
+        None,
+        msg.value.clone(),
+        None,
+        ExitCode::USR_UNHANDLED_MESSAGE,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+
+    let receipt = CrossMsgReceipt {
+        msg: msg.clone(),
+        exit_code: ExitCode::USR_UNHANDLED_MESSAGE,
+        return_data: RawBytes::default(),
+    };
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.topdown_nonce, 3);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+
+    // the value is refunded with a plain transfer
+    let refund = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(refund.to, msg.from);
+    assert_eq!(refund.method, METHOD_SEND);
+    assert_eq!(refund.value, msg.value);
+
+    // and the receipt is sent to the callback without any value
+    let reply = get_topdown_msg(&crossmsgs, 2).unwrap().unwrap();
+    assert_eq!(reply.to, msg.from);
+    assert_eq!(reply.method, reply_method);
+    assert_eq!(reply.value, TokenAmount::zero());
+    assert_eq!(
+        reply.params.deserialize::<CrossMsgReceipt>().unwrap(),
+        receipt
+    );
+}
+
+/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
+/// the gateway. It will save it in the postbox.
+#[test]
+fn test_commit_child_check_bu_not_target_subnet() {
     // ============== Register subnet ==============
     let parent = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
     let shid = SubnetID::new_from_parent(&parent, *SUBNET_TWO);
@@ -861,6 +2042,8 @@ fn test_commit_child_check_bu_not_target_subnet() {
         cross_msgs: Some(vec![CrossMsg {
             msg: msg.clone(),
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         }]),
         fee: fee.clone(),
     };
@@ -992,6 +2175,8 @@ fn test_propagate_with_remainder() {
         cross_msgs: Some(vec![CrossMsg {
             msg: params.clone(),
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         }]),
         fee: fee.clone(),
     };
@@ -1116,8 +2301,12 @@ fn test_commit_child_check_bu_switch_td() {
                     Some(vec![caller.clone()]),
                     CrossMsg {
                         wrapped: false,
+                        reply_method: None,
+                        fee: TokenAmount::zero(),
                         msg: params.clone(),
                     },
+                    r.curr_epoch(),
+                    |a| r.resolve_address(a),
                 )
                 .unwrap())
         })
@@ -1136,7 +2325,7 @@ fn test_commit_child_check_bu_switch_td() {
             .subnet_actor(),
         SUBNET_ACTOR_REWARD_METHOD,
         None,
-        CROSS_MSG_FEE.clone(),
+        DEFAULT_CROSS_MSG_FEE.clone(),
         None,
         ExitCode::OK,
     );
@@ -1173,6 +2362,379 @@ fn test_commit_child_check_bu_switch_td() {
     assert_eq!(msg.value, value);
 }
 
+#[test]
+fn test_reclaim_postbox() {
+    let parent_sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(parent_sub.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ff: /root/f101/f102
+    // tt: /root/f101/f103
+    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
+    let ff_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_TWO);
+    let tt_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_THR);
+    h.register(&mut rt, &SUBNET_TWO, &reg_value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_THR, &reg_value, ExitCode::OK)
+        .unwrap();
+
+    let ff = IPCAddress::new(&ff_sub, &from).unwrap();
+    let tt = IPCAddress::new(&tt_sub, &to).unwrap();
+    let msg = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: 0,
+    };
+    let owner = ff.raw_addr().unwrap();
+
+    let insert_epoch = 10;
+    rt.set_epoch(insert_epoch);
+    let cid = rt
+        .transaction(|st: &mut State, r| {
+            Ok(st
+                .insert_postbox(
+                    r.store(),
+                    Some(vec![owner]),
+                    CrossMsg {
+                        wrapped: false,
+                        reply_method: None,
+                        fee: TokenAmount::zero(),
+                        msg: msg.clone(),
+                    },
+                    r.curr_epoch(),
+                    |a| r.resolve_address(a),
+                )
+                .unwrap())
+        })
+        .unwrap();
+
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.inserted_epoch, insert_epoch);
+    assert_eq!(item.ttl, *DEFAULT_POSTBOX_TTL);
+
+    // the item can't be reclaimed before it expires
+    let anyone = Address::new_id(1001);
+    rt.set_epoch(insert_epoch + *DEFAULT_POSTBOX_TTL - 1);
+    h.reclaim_postbox(&mut rt, &anyone, cid, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert!(st.load_from_postbox(rt.store(), cid).is_ok());
+
+    // once expired anyone can reclaim it, the value is refunded to the sender
+    let starting_nonce = get_subnet(&rt, &ff_sub).unwrap().topdown_nonce;
+    rt.set_epoch(insert_epoch + *DEFAULT_POSTBOX_TTL);
+    h.reclaim_postbox(&mut rt, &anyone, cid, ExitCode::OK)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    let r = st.load_from_postbox(rt.store(), cid);
+    assert_eq!(r.unwrap_err().to_string(), "cid not found in postbox");
+
+    let sub = get_subnet(&rt, &ff_sub).unwrap();
+    assert_eq!(sub.topdown_nonce, starting_nonce + 1);
+    assert_eq!(sub.circ_supply, value);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let receipt_msg = get_topdown_msg(&crossmsgs, starting_nonce)
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt_msg.to, ff);
+    assert_eq!(
+        receipt_msg.from,
+        IPCAddress::new(&parent_sub, &ACTOR).unwrap()
+    );
+    assert_eq!(receipt_msg.method, METHOD_SEND);
+    assert_eq!(receipt_msg.value, value);
+    let receipt: CrossMsgReceipt = receipt_msg.params.deserialize().unwrap();
+    assert_eq!(receipt.exit_code, POSTBOX_EXPIRED_EXIT_CODE);
+    assert_eq!(receipt.msg, msg);
+
+    // the item can't be reclaimed twice
+    h.reclaim_postbox(&mut rt, &anyone, cid, ExitCode::USR_UNHANDLED_MESSAGE)
+        .unwrap();
+}
+
+#[test]
+fn test_postbox_owner_index() {
+    let (h, mut rt) = setup_root();
+
+    let owner = Address::new_id(1001);
+    let other = Address::new_id(1002);
+    let propagator = Address::new_id(1003);
+    // owners listed by their key address are indexed by their ID address
+    let other_key = Address::new_secp256k1(&[2; fvm_shared::address::SECP_PUB_LEN]).unwrap();
+    rt.add_id_address(other_key, other);
+
+    let sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let new_msg = |nonce: u64| CrossMsg {
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+        msg: StorableMsg {
+            to: IPCAddress::new(&sub, &Address::new_id(2000)).unwrap(),
+            from: IPCAddress::new(&ROOTNET_ID, &owner).unwrap(),
+            method: METHOD_SEND,
+            value: TokenAmount::zero(),
+            params: RawBytes::default(),
+            nonce,
+        },
+    };
+
+    let (cid1, cid2) = rt
+        .transaction(|st: &mut State, r| {
+            let resolve = |a: &Address| r.resolve_address(a);
+            let cid1 = st
+                .insert_postbox(
+                    r.store(),
+                    Some(vec![owner]),
+                    new_msg(0),
+                    r.curr_epoch(),
+                    resolve,
+                )
+                .unwrap();
+            let cid2 = st
+                .insert_postbox(
+                    r.store(),
+                    Some(vec![owner, other_key]),
+                    new_msg(1),
+                    r.curr_epoch(),
+                    resolve,
+                )
+                .unwrap();
+            // items without owners are not indexed
+            st.insert_postbox(r.store(), None, new_msg(2), r.curr_epoch(), resolve)
+                .unwrap();
+            Ok((cid1, cid2))
+        })
+        .unwrap();
+
+    // cids are listed in insertion order
+    let first = h.list_postbox_cids(&mut rt, &owner, 0, 1);
+    assert_eq!(first.cids, vec![cid1]);
+    assert_eq!(first.next_cursor, Some(1));
+    let second = h.list_postbox_cids(&mut rt, &owner, 1, 10);
+    assert_eq!(second.cids, vec![cid2]);
+    assert_eq!(second.next_cursor, None);
+
+    // the owner is found either by its ID or its key address
+    let page = h.list_postbox_cids(&mut rt, &other, 0, 10);
+    assert_eq!(page.cids, vec![cid2]);
+    let page = h.list_postbox_cids(&mut rt, &other_key, 0, 10);
+    assert_eq!(page.cids, vec![cid2]);
+    let page = h.list_postbox_cids(&mut rt, &propagator, 0, 10);
+    assert!(page.cids.is_empty());
+    assert_eq!(page.next_cursor, None);
+
+    // whitelisting a propagator changes the cid of the item, the index follows it
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, owner);
+    rt.expect_validate_caller_any();
+    rt.call::<Actor>(
+        Method::WhiteListPropagator as MethodNum,
+        IpldBlock::serialize_cbor(&WhitelistPropagatorParams {
+            postbox_cid: cid1,
+            to_add: vec![propagator],
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+
+    let page = h.list_postbox_cids(&mut rt, &propagator, 0, 10);
+    assert_eq!(page.cids.len(), 1);
+    let new_cid1 = page.cids[0];
+    assert_ne!(new_cid1, cid1);
+    let page = h.list_postbox_cids(&mut rt, &owner, 0, 10);
+    assert_eq!(page.cids, vec![cid2, new_cid1]);
+
+    // removing an item drops it from the index of all its owners
+    rt.transaction(|st: &mut State, r| {
+        st.remove_from_postbox(r.store(), cid2, |a| r.resolve_address(a))
+    })
+    .unwrap();
+    let page = h.list_postbox_cids(&mut rt, &owner, 0, 10);
+    assert_eq!(page.cids, vec![new_cid1]);
+    // and the cursors of the remaining items are kept
+    let page = h.list_postbox_cids(&mut rt, &owner, 2, 10);
+    assert_eq!(page.cids, vec![new_cid1]);
+    assert_eq!(page.next_cursor, None);
+    let page = h.list_postbox_cids(&mut rt, &other, 0, 10);
+    assert!(page.cids.is_empty());
+}
+
+#[test]
+fn test_postbox_propagators() {
+    let (h, mut rt) = setup_root();
+
+    let owner = Address::new_id(1001);
+    let owner_key = Address::new_secp256k1(&[1; fvm_shared::address::SECP_PUB_LEN]).unwrap();
+    rt.add_id_address(owner_key, owner);
+    let propagator = Address::new_id(1002);
+    let new_owner = Address::new_id(1003);
+    let sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let new_msg = |from: &Address| CrossMsg {
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+        msg: StorableMsg {
+            to: IPCAddress::new(&sub, &Address::new_id(2000)).unwrap(),
+            from: IPCAddress::new(&ROOTNET_ID, from).unwrap(),
+            method: METHOD_SEND,
+            value: TokenAmount::zero(),
+            params: RawBytes::default(),
+            nonce: 0,
+        },
+    };
+    let mut insert_open = |msg: CrossMsg| {
+        rt.transaction(|st: &mut State, r| {
+            Ok(st
+                .insert_postbox(r.store(), None, msg, r.curr_epoch(), |a| {
+                    r.resolve_address(a)
+                })
+                .unwrap())
+        })
+        .unwrap()
+    };
+
+    // open items sent from an ID address can't be restricted, as the address
+    // refers to an unrelated actor in this subnet
+    let id_cid = insert_open(new_msg(&owner));
+    // open items are restricted to the whitelisted propagators
+    let cid = insert_open(new_msg(&owner_key));
+
+    let err = h
+        .update_postbox_owners(
+            &mut rt,
+            &owner,
+            Method::WhiteListPropagator,
+            IpldBlock::serialize_cbor(&WhitelistPropagatorParams {
+                postbox_cid: id_cid,
+                to_add: vec![owner],
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    // but only by the sender of the message
+    let err = h
+        .update_postbox_owners(
+            &mut rt,
+            &new_owner,
+            Method::WhiteListPropagator,
+            IpldBlock::serialize_cbor(&WhitelistPropagatorParams {
+                postbox_cid: cid,
+                to_add: vec![new_owner],
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+    let err = h
+        .update_postbox_owners(
+            &mut rt,
+            &new_owner,
+            Method::TransferPostboxOwnership,
+            IpldBlock::serialize_cbor(&TransferPostboxOwnershipParams {
+                postbox_cid: cid,
+                new_owners: vec![new_owner],
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    let cid = h
+        .update_postbox_owners(
+            &mut rt,
+            &owner,
+            Method::WhiteListPropagator,
+            IpldBlock::serialize_cbor(&WhitelistPropagatorParams {
+                postbox_cid: cid,
+                to_add: vec![owner, propagator],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, Some(vec![owner, propagator]));
+
+    // only owners can manage the propagators
+    let err = h
+        .update_postbox_owners(
+            &mut rt,
+            &new_owner,
+            Method::RemovePropagator,
+            IpldBlock::serialize_cbor(&RemovePropagatorParams {
+                postbox_cid: cid,
+                to_remove: vec![propagator],
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    let cid = h
+        .update_postbox_owners(
+            &mut rt,
+            &owner,
+            Method::RemovePropagator,
+            IpldBlock::serialize_cbor(&RemovePropagatorParams {
+                postbox_cid: cid,
+                to_remove: vec![propagator],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, Some(vec![owner]));
+
+    // the last owner can't be removed
+    let err = h
+        .update_postbox_owners(
+            &mut rt,
+            &owner,
+            Method::RemovePropagator,
+            IpldBlock::serialize_cbor(&RemovePropagatorParams {
+                postbox_cid: cid,
+                to_remove: vec![owner],
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_ARGUMENT);
+
+    // transfer the ownership to a new owner
+    let cid = h
+        .update_postbox_owners(
+            &mut rt,
+            &owner,
+            Method::TransferPostboxOwnership,
+            IpldBlock::serialize_cbor(&TransferPostboxOwnershipParams {
+                postbox_cid: cid,
+                new_owners: vec![new_owner],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, Some(vec![new_owner]));
+    assert!(!item.is_owner(&owner));
+    assert_eq!(
+        h.list_postbox_cids(&mut rt, &new_owner, 0, 10).cids,
+        vec![cid]
+    );
+    assert!(h.list_postbox_cids(&mut rt, &owner, 0, 10).cids.is_empty());
+}
+
 /// This test covers the case where the cross_msg's target subnet is the SAME as that of
 /// the gateway. It would directly commit the message and will not save in postbox.
 #[test]
@@ -1229,6 +2791,8 @@ fn test_commit_child_check_tp_target_subnet() {
         cross_msgs: Some(vec![CrossMsg {
             msg: params.clone(),
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         }]),
         fee: fee.clone(),
     };
@@ -1304,6 +2868,8 @@ fn test_commit_child_check_tp_not_target_subnet() {
         cross_msgs: Some(vec![CrossMsg {
             msg: params.clone(),
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         }]),
         fee: fee.clone(),
     };
@@ -1358,7 +2924,7 @@ fn test_commit_child_check_tp_not_target_subnet() {
             .subnet_actor(),
         SUBNET_ACTOR_REWARD_METHOD,
         None,
-        CROSS_MSG_FEE.clone(),
+        DEFAULT_CROSS_MSG_FEE.clone(),
         None,
         ExitCode::OK,
     );
@@ -1430,30 +2996,313 @@ fn test_set_membership() {
 
     let st: State = rt.get_state();
 
-    assert_eq!(st.validators.validators, validator_set);
+    assert_eq!(st.validators.validators, validator_set);
+    assert_eq!(
+        st.validators.total_weight,
+        TokenAmount::from_atto(weights.iter().sum::<u64>())
+    );
+}
+
+#[test]
+fn test_validator_weight_lookup() {
+    let (h, mut rt) = setup_root();
+
+    let unresolved = Address::new_bls(&[1; BLS_PUB_LEN]).unwrap();
+    let validator_set = ValidatorSet::new(
+        vec![
+            Validator {
+                addr: Address::new_id(10),
+                net_addr: "10".to_string(),
+                weight: TokenAmount::from_atto(1000),
+            },
+            Validator {
+                addr: unresolved,
+                net_addr: "11".to_string(),
+                weight: TokenAmount::from_atto(2000),
+            },
+        ],
+        1,
+    );
+    h.set_membership(&mut rt, validator_set).unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.validators.unresolved, vec![unresolved]);
+    assert_eq!(
+        st.validators
+            .get_validator_weight(&rt, &Address::new_id(10))
+            .unwrap(),
+        Some(TokenAmount::from_atto(1000))
+    );
+    assert_eq!(
+        st.validators
+            .get_validator_weight(&rt, &Address::new_id(11))
+            .unwrap(),
+        None
+    );
+
+    // validators whose actor is created after the validator set was set can vote
+    rt.add_id_address(unresolved, Address::new_id(11));
+    assert_eq!(
+        st.validators
+            .get_validator_weight(&rt, &Address::new_id(11))
+            .unwrap(),
+        Some(TokenAmount::from_atto(2000))
+    );
+}
+
+fn setup_membership(h: &Harness, rt: &mut MockRuntime) {
+    let weights = vec![1000; 5];
+    let mut index = 0;
+    let validators = weights
+        .iter()
+        .map(|weight| {
+            let v = Validator {
+                addr: Address::new_id(index),
+                net_addr: index.to_string(),
+                weight: TokenAmount::from_atto(*weight),
+            };
+            index += 1;
+            v
+        })
+        .collect();
+    let validator_set = ValidatorSet::new(validators, 10);
+    h.set_membership(rt, validator_set.clone()).unwrap();
+}
+
+#[test]
+fn test_commit_membership() {
+    let (h, mut rt) = setup_root();
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let validator_set = ValidatorSet::new(
+        vec![Validator {
+            addr: Address::new_id(10),
+            net_addr: "10".to_string(),
+            weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        }],
+        1,
+    );
+
+    // only registered subnets can commit their membership
+    h.commit_membership(
+        &mut rt,
+        &SUBNET_ONE,
+        &validator_set,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    h.register(
+        &mut rt,
+        &SUBNET_ONE,
+        &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        ExitCode::OK,
+    )
+    .unwrap();
+    h.commit_membership(&mut rt, &SUBNET_ONE, &validator_set, ExitCode::OK)
+        .unwrap();
+
+    // the membership is committed as a top-down message for the child gateway
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.topdown_nonce, 1);
+    assert_eq!(sub.circ_supply, TokenAmount::zero());
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, 0).unwrap().unwrap();
+    assert_eq!(msg.from, IPCAddress::new(&ROOTNET_ID, &SUBNET_ONE).unwrap());
+    assert_eq!(msg.to, IPCAddress::new(&shid, &ACTOR).unwrap());
+    assert_eq!(msg.method, Method::SetMembership as MethodNum);
+    assert_eq!(msg.value, TokenAmount::zero());
+    assert_eq!(
+        msg.params.deserialize::<ValidatorSet>().unwrap(),
+        validator_set
+    );
+}
+
+#[test]
+fn test_apply_membership() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    setup_membership(&h, &mut rt);
+    let st: State = rt.get_state();
+    let genesis_epoch = st.topdown_checkpoint_voting.genesis_epoch;
+
+    let membership_msg = |validator_set: &ValidatorSet, nonce: u64| CrossMsg {
+        msg: StorableMsg {
+            from: IPCAddress::new(&ROOTNET_ID, &SUBNET_ONE).unwrap(),
+            to: IPCAddress::new(&shid, &ACTOR).unwrap(),
+            method: Method::SetMembership as MethodNum,
+            params: RawBytes::serialize(validator_set).unwrap(),
+            value: TokenAmount::zero(),
+            nonce,
+        },
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+    };
+    let mut validators = st.validators.validators.validators().clone();
+    validators.pop();
+    let new_set = ValidatorSet::new(validators, 11);
+    // sets that are not newer than the current one are ignored
+    let stale_set = ValidatorSet::new(vec![], 5);
+
+    let epoch = genesis_epoch + *DEFAULT_TOPDOWN_PERIOD;
+    let checkpoint = TopDownCheckpoint {
+        epoch,
+        top_down_msgs: vec![membership_msg(&new_set, 0), membership_msg(&stale_set, 1)],
+    };
+    for i in 0..3 {
+        h.submit_topdown_check(&mut rt, Address::new_id(i), checkpoint.clone())
+            .unwrap();
+    }
+    // the membership is applied by the gateway without sending any message
+    expect_topdown_execution(&h, &mut rt, &checkpoint);
+    h.submit_topdown_check(&mut rt, Address::new_id(3), checkpoint.clone())
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 2);
+    assert_eq!(st.validators.validators, new_set);
+    assert_eq!(st.validators.total_weight, TokenAmount::from_atto(4000));
+}
+
+#[test]
+fn test_applied_topdown_nonce_frozen_in_closed_windows() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    setup_membership(&h, &mut rt);
+    let st: State = rt.get_state();
+    let genesis_epoch = st.topdown_checkpoint_voting.genesis_epoch;
+    let period = st.bottomup_check_period;
+
+    // stale membership messages are applied without side effects
+    let membership_msg = |nonce: u64| CrossMsg {
+        msg: StorableMsg {
+            from: IPCAddress::new(&ROOTNET_ID, &SUBNET_ONE).unwrap(),
+            to: IPCAddress::new(&shid, &ACTOR).unwrap(),
+            method: Method::SetMembership as MethodNum,
+            params: RawBytes::serialize(ValidatorSet::new(vec![], 5)).unwrap(),
+            value: TokenAmount::zero(),
+            nonce,
+        },
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+    };
+    let apply_topdown_check = |rt: &mut MockRuntime, checkpoint: TopDownCheckpoint| {
+        rt.set_epoch(checkpoint.epoch);
+        for i in 0..3 {
+            h.submit_topdown_check(rt, Address::new_id(i), checkpoint.clone())
+                .unwrap();
+        }
+        expect_topdown_execution(&h, rt, &checkpoint);
+        h.submit_topdown_check(rt, Address::new_id(3), checkpoint)
+            .unwrap();
+    };
+    let window_nonce = |rt: &MockRuntime, epoch: ChainEpoch| {
+        let st: State = rt.get_state();
+        let window = window_epoch(epoch, st.genesis_epoch(), period);
+        let checkpoints = st.bottomup_checkpoints.load(rt.store()).unwrap();
+        get_checkpoint(&checkpoints, window, period)
+            .unwrap()
+            .unwrap()
+            .applied_topdown_nonce()
+    };
+
+    let first = genesis_epoch + *DEFAULT_TOPDOWN_PERIOD;
+    apply_topdown_check(
+        &mut rt,
+        TopDownCheckpoint {
+            epoch: first,
+            top_down_msgs: vec![membership_msg(0)],
+        },
+    );
+    assert_eq!(window_nonce(&rt, first), 1);
+
+    // top-down messages applied after the window closes, and before its checkpoint
+    // is signed, are signaled in the following windows only.
+    let second = first + *DEFAULT_TOPDOWN_PERIOD;
+    apply_topdown_check(
+        &mut rt,
+        TopDownCheckpoint {
+            epoch: second,
+            top_down_msgs: vec![membership_msg(1)],
+        },
+    );
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 2);
+    assert_eq!(window_nonce(&rt, first), 1);
     assert_eq!(
-        st.validators.total_weight,
-        TokenAmount::from_atto(weights.iter().sum::<u64>())
+        st.get_window_checkpoint(rt.store(), first)
+            .unwrap()
+            .applied_topdown_nonce(),
+        1
     );
+    assert_eq!(window_nonce(&rt, second), 2);
 }
 
-fn setup_membership(h: &Harness, rt: &mut MockRuntime) {
-    let weights = vec![1000; 5];
-    let mut index = 0;
-    let validators = weights
-        .iter()
-        .map(|weight| {
-            let v = Validator {
-                addr: Address::new_id(index),
-                net_addr: index.to_string(),
-                weight: TokenAmount::from_atto(*weight),
-            };
-            index += 1;
-            v
-        })
-        .collect();
-    let validator_set = ValidatorSet::new(validators, 10);
-    h.set_membership(rt, validator_set.clone()).unwrap();
+#[test]
+fn test_reclaimed_topdown_msg_does_not_hold_next_nonce() {
+    let (h, mut rt) = setup_root();
+    setup_membership(&h, &mut rt);
+    let st: State = rt.get_state();
+    let genesis_epoch = st.topdown_checkpoint_voting.genesis_epoch;
+
+    let apply_topdown_check = |rt: &mut MockRuntime, checkpoint: TopDownCheckpoint| {
+        for i in 0..3 {
+            h.submit_topdown_check(rt, Address::new_id(i), checkpoint.clone())
+                .unwrap();
+        }
+        expect_topdown_execution(&h, rt, &checkpoint);
+        h.submit_topdown_check(rt, Address::new_id(3), checkpoint)
+            .unwrap();
+    };
+
+    // a message passing through the subnet is parked in the postbox and
+    // holds the subsequent nonce.
+    let sender = Address::new_id(10);
+    let mut pass_through = cross_msg(0);
+    pass_through.msg.to = IPCAddress::new(
+        &SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE),
+        &Address::new_id(20),
+    )
+    .unwrap();
+    let first = genesis_epoch + *DEFAULT_TOPDOWN_PERIOD;
+    rt.set_epoch(first);
+    apply_topdown_check(
+        &mut rt,
+        TopDownCheckpoint {
+            epoch: first,
+            top_down_msgs: vec![pass_through],
+        },
+    );
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+    let cids = h.list_postbox_cids(&mut rt, &sender, 0, 10).cids;
+    assert_eq!(cids.len(), 1);
+
+    // the item expires without being propagated
+    rt.set_epoch(first + *DEFAULT_POSTBOX_TTL);
+    h.reclaim_postbox(&mut rt, &sender, cids[0], ExitCode::OK)
+        .unwrap();
+
+    // the next message is applied right away instead of being buffered
+    let msg = cross_msg(1);
+    let checkpoint = TopDownCheckpoint {
+        epoch: first + *DEFAULT_TOPDOWN_PERIOD,
+        top_down_msgs: vec![msg.clone()],
+    };
+    rt.expect_send(
+        msg.msg.to.raw_addr().unwrap(),
+        msg.msg.method,
+        None,
+        msg.msg.value,
+        None,
+        ExitCode::OK,
+    );
+    apply_topdown_check(&mut rt, checkpoint);
+
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 2);
+    assert_eq!(st.pending_topdown_msgs.load(rt.store()).unwrap().count(), 0);
 }
 
 #[test]
@@ -1591,6 +3440,7 @@ fn test_submit_topdown_check_works_with_execution() {
         None,
         ExitCode::OK,
     );
+    expect_topdown_execution(&h, &mut rt, &checkpoint);
     let r = h.submit_topdown_check(&mut rt, submitter, checkpoint.clone());
     assert!(r.is_ok());
     let submission = get_epoch_submissions(&mut rt, epoch);
@@ -1602,6 +3452,181 @@ fn test_submit_topdown_check_works_with_execution() {
     );
 }
 
+#[test]
+fn test_submit_topdown_check_unlocks_from_treasury() {
+    let (h, mut rt) = setup_root();
+    let treasury = Address::new_id(1002);
+    let mut st: State = rt.get_state();
+    st.treasury = Some(treasury);
+    rt.replace_state(&st);
+    let genesis_epoch = st.topdown_checkpoint_voting.genesis_epoch;
+
+    setup_membership(&h, &mut rt);
+
+    let epoch = genesis_epoch + *DEFAULT_TOPDOWN_PERIOD;
+    let value = TokenAmount::from_atto(1000);
+    let mut msg = cross_msg(0);
+    msg.msg.value = value.clone();
+    let checkpoint = TopDownCheckpoint {
+        epoch,
+        top_down_msgs: vec![msg.clone()],
+    };
+
+    for i in 0..3 {
+        h.submit_topdown_check(&mut rt, Address::new_id(i), checkpoint.clone())
+            .unwrap();
+    }
+
+    // the value is unlocked from the treasury instead of the balance of the gateway,
+    // the mock runtime doesn't run the treasury, so its transfer is set by hand.
+    rt.set_balance(value.clone());
+    rt.expect_send(
+        treasury,
+        ipc_treasury::Method::Unlock as MethodNum,
+        IpldBlock::serialize_cbor(&UnlockParams {
+            amount: value.clone(),
+        })
+        .unwrap(),
+        TokenAmount::zero(),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        msg.msg.to.raw_addr().unwrap(),
+        msg.msg.method,
+        None,
+        value,
+        None,
+        ExitCode::OK,
+    );
+    expect_topdown_execution(&h, &mut rt, &checkpoint);
+    h.submit_topdown_check(&mut rt, Address::new_id(3), checkpoint.clone())
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(
+        st.topdown_checkpoint_voting.last_voting_executed_epoch(),
+        epoch
+    );
+    assert_eq!(st.applied_topdown_nonce, 1);
+}
+
+#[test]
+fn test_submit_topdown_check_over_treasury_limit() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    let treasury = Address::new_id(1002);
+    let mut st: State = rt.get_state();
+    st.treasury = Some(treasury);
+    rt.replace_state(&st);
+    let genesis_epoch = st.topdown_checkpoint_voting.genesis_epoch;
+
+    setup_membership(&h, &mut rt);
+
+    let new_msg = |nonce: u64, value: u64| CrossMsg {
+        msg: StorableMsg {
+            from: IPCAddress::new(&ROOTNET_ID, &Address::new_id(10)).unwrap(),
+            to: IPCAddress::new(&shid, &Address::new_id(20)).unwrap(),
+            method: METHOD_SEND,
+            params: RawBytes::default(),
+            value: TokenAmount::from_atto(value),
+            nonce,
+        },
+        wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
+    };
+    let over_limit = new_msg(0, 1000);
+    let msg = new_msg(1, 10);
+    let checkpoint = TopDownCheckpoint {
+        epoch: genesis_epoch + *DEFAULT_TOPDOWN_PERIOD,
+        top_down_msgs: vec![over_limit.clone(), msg.clone()],
+    };
+    for i in 0..3 {
+        h.submit_topdown_check(&mut rt, Address::new_id(i), checkpoint.clone())
+            .unwrap();
+    }
+
+    // the treasury refuses to unlock the funds of the first message, it fails
+    // without reverting the checkpoint and the following message is applied.
+    let expect_unlock = |rt: &mut MockRuntime, amount: &TokenAmount, code: ExitCode| {
+        rt.expect_send(
+            treasury,
+            ipc_treasury::Method::Unlock as MethodNum,
+            IpldBlock::serialize_cbor(&UnlockParams {
+                amount: amount.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            None,
+            code,
+        );
+    };
+    expect_unlock(&mut rt, &over_limit.msg.value, ExitCode::USR_FORBIDDEN);
+    rt.set_balance(msg.msg.value.clone());
+    expect_unlock(&mut rt, &msg.msg.value, ExitCode::OK);
+    rt.expect_send(
+        msg.msg.to.raw_addr().unwrap(),
+        METHOD_SEND,
+        None,
+        msg.msg.value.clone(),
+        None,
+        ExitCode::OK,
+    );
+    expect_event(
+        &mut rt,
+        GatewayEvent::SubmitTopDownCheck(CheckpointEvent {
+            subnet: h.net_name.clone(),
+            epoch: checkpoint.epoch,
+            num_msgs: 2,
+            value: &over_limit.msg.value + &msg.msg.value,
+            fee: TokenAmount::zero(),
+        }),
+    );
+    expect_event(
+        &mut rt,
+        GatewayEvent::ApplyMsg(msg_event(&msg, &TokenAmount::zero())),
+    );
+    h.submit_topdown_check(&mut rt, Address::new_id(3), checkpoint)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 2);
+    let receipt = CrossMsgReceipt {
+        msg: over_limit.msg.clone(),
+        exit_code: ExitCode::USR_FORBIDDEN,
+        return_data: RawBytes::default(),
+    };
+    assert_eq!(
+        st.get_failed_msg(rt.store(), &cross_msg_cid(&over_limit).unwrap())
+            .unwrap(),
+        Some(receipt)
+    );
+    // the value is refunded to the sender without burning it, as it was never minted
+    assert_eq!(st.bottomup_nonce, 1);
+}
+
+fn expect_topdown_execution(h: &Harness, rt: &mut MockRuntime, checkpoint: &TopDownCheckpoint) {
+    expect_event(
+        rt,
+        GatewayEvent::SubmitTopDownCheck(CheckpointEvent {
+            subnet: h.net_name.clone(),
+            epoch: checkpoint.epoch,
+            num_msgs: checkpoint.top_down_msgs.len() as u64,
+            value: checkpoint
+                .top_down_msgs
+                .iter()
+                .fold(TokenAmount::zero(), |value, m| value + &m.msg.value),
+            fee: TokenAmount::zero(),
+        }),
+    );
+    for m in checkpoint.top_down_msgs.iter() {
+        expect_event(
+            rt,
+            GatewayEvent::ApplyMsg(msg_event(m, &TokenAmount::zero())),
+        );
+    }
+}
+
 fn cross_msg(nonce: u64) -> CrossMsg {
     CrossMsg {
         msg: StorableMsg {
@@ -1613,6 +3638,8 @@ fn cross_msg(nonce: u64) -> CrossMsg {
             nonce,
         },
         wrapped: false,
+        reply_method: None,
+        fee: TokenAmount::zero(),
     }
 }
 
@@ -1753,6 +3780,7 @@ fn test_submit_topdown_check_sequential_execution() {
         None,
         ExitCode::OK,
     );
+    expect_topdown_execution(&h, &mut rt, &checkpoint);
     h.submit_topdown_check(&mut rt, submitter, checkpoint.clone())
         .unwrap();
     let submission = get_epoch_submissions(&mut rt, epoch);
@@ -1763,12 +3791,20 @@ fn test_submit_topdown_check_sequential_execution() {
         epoch
     );
 
-    // now we submit to the next epoch
+    // now we submit to the next epoch, executing the pending one
     let epoch = genesis_epoch + *DEFAULT_TOPDOWN_PERIOD * 3;
     let checkpoint = TopDownCheckpoint {
         epoch,
         top_down_msgs: vec![],
     };
+    expect_topdown_execution(
+        &h,
+        &mut rt,
+        &TopDownCheckpoint {
+            epoch: pending_epoch,
+            top_down_msgs: vec![],
+        },
+    );
     h.submit_topdown_check(&mut rt, submitter, checkpoint.clone())
         .unwrap();
     let st: State = rt.get_state();
@@ -1778,3 +3814,257 @@ fn test_submit_topdown_check_sequential_execution() {
     );
     assert_eq!(*st.topdown_checkpoint_voting.executable_epoch_queue(), None);
 }
+
+#[test]
+fn test_query_methods() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let funder = Address::new_id(1001);
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &(value.clone() * 2),
+    )
+    .unwrap();
+
+    // get subnet
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::GetSubnet as MethodNum,
+            IpldBlock::serialize_cbor(&shid).unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let sub: Option<Subnet> = deserialize_block(ret).unwrap();
+    assert_eq!(sub, h.get_subnet(&rt, &shid));
+
+    // list subnets paginated, skipping the killed ones
+    let third = Address::new_id(103);
+    h.register(&mut rt, &third, &value, ExitCode::OK).unwrap();
+    let killed = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    h.kill(&mut rt, &killed, &value, ExitCode::OK).unwrap();
+
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::ListSubnets as MethodNum,
+            IpldBlock::serialize_cbor(&ListSubnetsParams {
+                cursor: 0,
+                limit: 1,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let page: ListSubnetsReturn = deserialize_block(ret).unwrap();
+    assert_eq!(page.subnets.len(), 1);
+    assert_eq!(page.subnets[0].id, shid);
+    assert_eq!(page.next_cursor, Some(1));
+
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::ListSubnets as MethodNum,
+            IpldBlock::serialize_cbor(&ListSubnetsParams {
+                cursor: page.next_cursor.unwrap(),
+                limit: 10,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let page: ListSubnetsReturn = deserialize_block(ret).unwrap();
+    assert_eq!(page.subnets.len(), 1);
+    assert_eq!(
+        page.subnets[0].id,
+        SubnetID::new_from_parent(&h.net_name, third)
+    );
+    assert_eq!(page.next_cursor, None);
+
+    // top-down messages of unregistered subnets can't be queried
+    rt.expect_validate_caller_any();
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(
+            Method::GetTopDownMsgs as MethodNum,
+            IpldBlock::serialize_cbor(&GetTopDownMsgsParams {
+                subnet: killed,
+                from_nonce: 0,
+                limit: 10,
+            })
+            .unwrap(),
+        ),
+    );
+    rt.verify();
+
+    // get top-down messages
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::GetTopDownMsgs as MethodNum,
+            IpldBlock::serialize_cbor(&GetTopDownMsgsParams {
+                subnet: shid.clone(),
+                from_nonce: 1,
+                limit: 10,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let msgs: Vec<CrossMsg> = deserialize_block(ret).unwrap();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].msg.nonce, 1);
+
+    // no checkpoint has been populated yet
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(
+            Method::GetBottomUpCheckpoint as MethodNum,
+            IpldBlock::serialize_cbor(&GetCheckpointParams { epoch: 10 }).unwrap(),
+        )
+        .unwrap();
+    rt.verify();
+    let ch: Option<BottomUpCheckpoint> = deserialize_block(ret).unwrap();
+    assert!(ch.is_none());
+}
+
+#[test]
+fn test_set_cross_msg_fees() {
+    let (h, mut rt) = setup_root();
+
+    let st: State = rt.get_state();
+    assert_eq!(
+        st.cross_msg_fees,
+        CrossMsgFees::uniform(DEFAULT_CROSS_MSG_FEE.clone())
+    );
+
+    let mut fees = CrossMsgFees::uniform(TokenAmount::from_nano(50));
+    fees.send_cross = TokenAmount::from_nano(200);
+    fees.per_byte = TokenAmount::from_atto(10);
+
+    // only the governor can update the fees
+    h.set_cross_msg_fees(
+        &mut rt,
+        &Address::new_id(1001),
+        fees.clone(),
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+    h.set_cross_msg_fees(&mut rt, &GOVERNOR, fees, ExitCode::OK)
+        .unwrap();
+}
+
+#[test]
+fn test_prune_bottomup_checkpoints() {
+    let (h, mut rt) = setup(SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE));
+
+    // windows are offset by the genesis epoch, so epochs before it are
+    // assigned to the window at index zero
+    let mut st: State = rt.get_state();
+    st.bottomup_genesis_epoch = 5;
+    rt.replace_state(&st);
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // commit a child checkpoint in each of the windows 5, 15, 25 and 35
+    let mut prev: Option<BottomUpCheckpoint> = None;
+    let mut commit_in_window = |rt: &mut MockRuntime, window: ChainEpoch| {
+        rt.set_epoch(window - 5);
+        let mut ch = BottomUpCheckpoint::new(shid.clone(), window);
+        if let Some(p) = &prev {
+            ch.data.prev_check = p.cid().into();
+        }
+        h.commit_child_check(rt, &shid, &ch, ExitCode::OK).unwrap();
+        prev = Some(ch);
+    };
+    for window in [5, 15, 25, 35] {
+        commit_in_window(&mut rt, window);
+    }
+
+    let has_window = |rt: &MockRuntime, epoch: ChainEpoch| {
+        let st: State = rt.get_state();
+        let checkpoints = st.bottomup_checkpoints.load(rt.store()).unwrap();
+        get_checkpoint(&checkpoints, epoch, st.bottomup_check_period)
+            .unwrap()
+            .is_some()
+    };
+    for window in [5, 15, 25, 35] {
+        assert!(has_window(&rt, window));
+    }
+
+    // only the governor can prune checkpoints
+    h.prune_bottomup_checkpoints(&mut rt, &Address::new_id(1001), 20, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    // the window being populated can't be pruned
+    h.prune_bottomup_checkpoints(&mut rt, &GOVERNOR, 35, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    // nor the windows not executed in the parent yet
+    h.prune_bottomup_checkpoints(&mut rt, &GOVERNOR, 20, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    h.set_executed_checkpoint(&mut rt, 15, ExitCode::OK)
+        .unwrap();
+    // the executed epoch can't decrease, nor reach the window being populated
+    h.set_executed_checkpoint(&mut rt, 5, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    h.set_executed_checkpoint(&mut rt, 35, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    h.prune_bottomup_checkpoints(&mut rt, &GOVERNOR, 15, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_pruned_epoch, Some(15));
+    assert!(!has_window(&rt, 5));
+    assert!(!has_window(&rt, 15));
+    assert!(has_window(&rt, 25));
+    assert!(has_window(&rt, 35));
+
+    // windows out of the retention period are pruned when new ones are
+    // populated, but only once they have been executed in the parent
+    let mut st: State = rt.get_state();
+    st.bottomup_checkpoint_retention = 2;
+    rt.replace_state(&st);
+    commit_in_window(&mut rt, 45);
+
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_pruned_epoch, Some(15));
+    assert!(has_window(&rt, 25));
+
+    h.set_executed_checkpoint(&mut rt, 25, ExitCode::OK)
+        .unwrap();
+    commit_in_window(&mut rt, 55);
+
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_pruned_epoch, Some(25));
+    assert!(!has_window(&rt, 25));
+    assert!(has_window(&rt, 35));
+    assert!(has_window(&rt, 45));
+    assert!(has_window(&rt, 55));
+}
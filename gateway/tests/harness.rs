@@ -23,13 +23,20 @@ use fvm_shared::error::ExitCode;
 use fvm_shared::MethodNum;
 use fvm_shared::METHOD_SEND;
 use ipc_gateway::checkpoint::ChildCheck;
+use ipc_gateway::upgrade::STATE_VERSION;
 use ipc_gateway::{
-    get_topdown_msg, Actor, AmountParams, BottomUpCheckpoint, ConstructorParams, CrossMsg,
-    CrossMsgParams, FundParams, IPCAddress, InitGenesisEpoch, Method, PropagateParams,
-    ReleaseParams, State, StorableMsg, Subnet, SubnetID, TopDownCheckpoint, CROSS_MSG_FEE,
-    DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT, SUBNET_ACTOR_REWARD_METHOD,
+    cross_msg_cid, get_checkpoint, get_topdown_msg, Actor, AmountParams, BottomUpCheckpoint,
+    CheckpointArray, CheckpointEvent, CheckpointLimits, ConstructorParams, CrossMsg, CrossMsgEvent,
+    CrossMsgFees, CrossMsgParams, ExecutedCheckpointParams, FundParams, GatewayEvent, IPCAddress,
+    IPCMsgType, InitGenesisEpoch, ListPostboxCidsParams, ListPostboxCidsReturn, Method,
+    PropagateParams, PruneCheckpointsParams, ReclaimPostboxParams, RecoverFundsParams,
+    RegisterParams, ReleaseHeldMsgsParams, ReleaseParams, SetSubnetLimitsParams, State,
+    StorableMsg, Subnet, SubnetID, TopDownCheckpoint, UpgradeStateParams,
+    DEFAULT_CHECKPOINT_PERIOD, DEFAULT_CROSS_MSG_FEE, MIN_COLLATERAL_AMOUNT,
+    SUBNET_ACTOR_REWARD_METHOD,
 };
 use ipc_sdk::cross::is_bottomup;
+use ipc_sdk::route::{FeeKind, Route};
 use ipc_sdk::ValidatorSet;
 use lazy_static::lazy_static;
 use primitives::{TCid, TCidContent};
@@ -45,6 +52,10 @@ lazy_static! {
     pub static ref DEFAULT_TOPDOWN_PERIOD: ChainEpoch = 20;
     pub static ref DEFAULT_GENESIS_EPOCH: ChainEpoch = 1;
     pub static ref ROOTNET_ID: SubnetID = SubnetID::new(123, vec![]);
+    pub static ref GOVERNOR: Address = Address::new_id(200);
+    pub static ref GUARDIAN: Address = Address::new_id(201);
+    pub static ref DEFAULT_POSTBOX_TTL: ChainEpoch = 100;
+    pub static ref DEFAULT_RECOVERY_DEADLINE: ChainEpoch = 1000;
 }
 
 pub fn new_runtime() -> MockRuntime {
@@ -88,6 +99,14 @@ impl Harness {
             network_name: self.net_name.to_string(),
             bottomup_check_period: 10,
             topdown_check_period: *DEFAULT_TOPDOWN_PERIOD,
+            cross_msg_fees: CrossMsgFees::uniform(DEFAULT_CROSS_MSG_FEE.clone()),
+            governor: *GOVERNOR,
+            checkpoint_retention: 0,
+            postbox_ttl: *DEFAULT_POSTBOX_TTL,
+            recovery_deadline: *DEFAULT_RECOVERY_DEADLINE,
+            guardian: *GUARDIAN,
+            treasury: None,
+            checkpoint_limits: CheckpointLimits::default(),
         };
         rt.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
         rt.call::<Actor>(
@@ -101,6 +120,7 @@ impl Harness {
         rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
         let params = InitGenesisEpoch {
             genesis_epoch: *DEFAULT_GENESIS_EPOCH,
+            bottomup_genesis_epoch: 0,
         };
         rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
         rt.call::<Actor>(
@@ -121,6 +141,7 @@ impl Harness {
 
         let st: State = rt.get_state();
 
+        assert_eq!(st.version, STATE_VERSION);
         assert_eq!(st.network_name, self.net_name);
         assert_eq!(st.min_stake, TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
         assert_eq!(st.bottomup_check_period, DEFAULT_CHECKPOINT_PERIOD);
@@ -137,7 +158,7 @@ impl Harness {
             }
         );
         verify_empty_map(rt, st.subnets.cid());
-        verify_empty_map(rt, st.bottomup_checkpoints.cid());
+        verify_empty_array(rt, st.bottomup_checkpoints.cid());
     }
 
     pub fn register(
@@ -152,15 +173,22 @@ impl Harness {
         rt.set_balance(value.clone());
         rt.expect_validate_caller_any();
 
+        let params = IpldBlock::serialize_cbor(&RegisterParams {
+            bottomup_genesis_epoch: 0,
+        })
+        .unwrap();
         if code != ExitCode::OK {
-            expect_abort(code, rt.call::<Actor>(Method::Register as MethodNum, None));
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::Register as MethodNum, params),
+            );
             rt.verify();
             return Ok(());
         }
 
         let register_ret = SubnetID::new_from_parent(&self.net_name, *subnet_addr);
         let ret = rt
-            .call::<Actor>(Method::Register as MethodNum, None)
+            .call::<Actor>(Method::Register as MethodNum, params)
             .unwrap();
         rt.verify();
         let ret: SubnetID = deserialize_block(ret).unwrap();
@@ -287,6 +315,24 @@ impl Harness {
             rt.verify();
             return Ok(());
         }
+
+        let msgs = ch.data.cross_msgs.cross_msgs.clone().unwrap_or_default();
+        expect_event(
+            rt,
+            GatewayEvent::CommitChildCheck(CheckpointEvent {
+                subnet: ch.source().clone(),
+                epoch: ch.epoch(),
+                num_msgs: msgs.len() as u64,
+                value: ch.total_value(),
+                fee: ch.total_fee().clone(),
+            }),
+        );
+        for m in msgs.iter() {
+            expect_event(
+                rt,
+                GatewayEvent::ApplyMsg(msg_event(m, &TokenAmount::zero())),
+            );
+        }
         rt.call::<Actor>(
             Method::CommitChildCheckpoint as MethodNum,
             IpldBlock::serialize_cbor(&ch).unwrap(),
@@ -350,14 +396,27 @@ impl Harness {
                 ExitCode::OK,
             );
         }
+        let (hop_fee, left_fee) = split_hop_fee(&self.net_name, id);
         rt.expect_send(
-            id.subnet_actor(),
+            id.down(&self.net_name).unwrap().subnet_actor(),
             SUBNET_ACTOR_REWARD_METHOD,
             None,
-            CROSS_MSG_FEE.clone(),
+            hop_fee,
             None,
             ExitCode::OK,
         );
+        let mut f_msg = CrossMsg {
+            msg: StorableMsg::new_fund_msg(id, &resolved(funder), &resolved(to), value.clone())
+                .unwrap(),
+            wrapped: false,
+            reply_method: None,
+            fee: left_fee,
+        };
+        f_msg.msg.nonce = expected_nonce - 1;
+        expect_event(
+            rt,
+            GatewayEvent::Fund(msg_event(&f_msg, &DEFAULT_CROSS_MSG_FEE)),
+        );
         rt.call::<Actor>(
             Method::Fund as MethodNum,
             IpldBlock::serialize_cbor(&params).unwrap(),
@@ -448,6 +507,23 @@ impl Harness {
             None,
             ExitCode::OK,
         );
+        let mut r_msg = CrossMsg {
+            msg: StorableMsg::new_release_msg(
+                &self.net_name,
+                &resolved(releaser),
+                &resolved(to),
+                value.clone(),
+            )
+            .unwrap(),
+            wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
+        };
+        r_msg.msg.nonce = expected_nonce;
+        expect_event(
+            rt,
+            GatewayEvent::Release(msg_event(&r_msg, &DEFAULT_CROSS_MSG_FEE)),
+        );
         rt.call::<Actor>(
             Method::Release as MethodNum,
             IpldBlock::serialize_cbor(&params).unwrap(),
@@ -458,12 +534,20 @@ impl Harness {
         let st: State = rt.get_state();
 
         let parent = &self.net_name.parent().unwrap();
-        let ch = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+        // the message may have overflowed into a window following the current one
+        let checkpoints = st.bottomup_checkpoints.load(rt.store()).unwrap();
+        let ch = get_checkpoint(
+            &checkpoints,
+            st.bottomup_msgs_window,
+            st.bottomup_check_period,
+        )
+        .unwrap()
+        .unwrap();
         // check that is included in the next checkpoint to be committed and not
         // in a checkpoint template of the past
         assert_eq!(ch.data.epoch > epoch, true);
 
-        let msg = ch.data.cross_msgs.cross_msgs.unwrap()[expected_msg_index].clone();
+        let msg = ch.data.cross_msgs.cross_msgs.as_ref().unwrap()[expected_msg_index].clone();
 
         let mut from = IPCAddress::new(&self.net_name, &releaser).unwrap();
         if releaser.protocol() == Protocol::ID {
@@ -505,12 +589,14 @@ impl Harness {
             nonce,
             method: METHOD_SEND,
             params: RawBytes::default(),
-            value: value.clone() + &*CROSS_MSG_FEE,
+            value: value.clone() + &*DEFAULT_CROSS_MSG_FEE,
         };
         let dest = sub.clone();
         let cross = CrossMsg {
             msg,
             wrapped: false,
+            reply_method: None,
+            fee: TokenAmount::zero(),
         };
         let params = CrossMsgParams {
             destination: sub,
@@ -529,12 +615,15 @@ impl Harness {
         }
 
         let is_bu = is_bottomup(&self.net_name, &dest);
+        // the fee is split between the hops in the route
+        let (hop_fee, left_fee) = split_hop_fee(&self.net_name, &dest);
         if is_bu {
+            // the fee left for the hops ahead is burnt with the value
             rt.expect_send(
                 BURNT_FUNDS_ACTOR_ADDR,
                 METHOD_SEND,
                 None,
-                value.clone(),
+                value.clone() + &left_fee,
                 None,
                 ExitCode::OK,
             );
@@ -544,11 +633,28 @@ impl Harness {
                 dest.down(&self.net_name).unwrap().subnet_actor(),
                 SUBNET_ACTOR_REWARD_METHOD,
                 None,
-                CROSS_MSG_FEE.clone(),
+                hop_fee,
                 None,
                 ExitCode::OK,
             );
         }
+        let committed = CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(&self.net_name, &SYSTEM_ACTOR_ADDR).unwrap(),
+                to: IPCAddress::new(&dest, to).unwrap(),
+                nonce: if is_bu { nonce } else { nonce - 1 },
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: value.clone(),
+            },
+            wrapped: false,
+            reply_method: None,
+            fee: left_fee.clone(),
+        };
+        expect_event(
+            rt,
+            GatewayEvent::SendCross(msg_event(&committed, &DEFAULT_CROSS_MSG_FEE)),
+        );
         rt.call::<Actor>(
             Method::SendCross as MethodNum,
             IpldBlock::serialize_cbor(&params).unwrap(),
@@ -568,6 +674,7 @@ impl Harness {
             assert_eq!(msg.msg.to, to);
             assert_eq!(msg.msg.nonce, nonce);
             assert_eq!(msg.msg.value, value);
+            assert_eq!(msg.fee, left_fee);
         } else {
             // top-down
             let sub = self
@@ -598,13 +705,41 @@ impl Harness {
     ) -> Result<(), ActorError> {
         rt.set_caller(Default::default(), owner);
         rt.expect_validate_caller_any();
-        rt.set_balance(msg_value.clone() + CROSS_MSG_FEE.clone() + excess.clone());
-        rt.set_received(CROSS_MSG_FEE.clone() + excess.clone());
+        rt.set_balance(msg_value.clone() + DEFAULT_CROSS_MSG_FEE.clone() + excess.clone());
+        rt.set_received(DEFAULT_CROSS_MSG_FEE.clone() + excess.clone());
 
         if excess > TokenAmount::zero() {
             rt.expect_send(owner, METHOD_SEND, None, excess.clone(), None, ExitCode::OK);
         }
 
+        // the message is committed with the next nonce of the subnet it is routed to
+        let st: State = rt.get_state();
+        let mut cross_msg = st.load_from_postbox(rt.store(), cid).unwrap().cross_msg;
+        let sto = cross_msg.msg.to.subnet().unwrap();
+        let is_topdown = match cross_msg.msg.apply_type(&self.net_name).unwrap() {
+            IPCMsgType::TopDown => true,
+            IPCMsgType::BottomUp => {
+                let sfrom = cross_msg.msg.from.subnet().unwrap();
+                sto.common_parent(&sfrom).unwrap().1 == self.net_name
+            }
+        };
+        cross_msg.msg.nonce = match is_topdown {
+            true => {
+                self.get_subnet(rt, &sto.down(&self.net_name).unwrap())
+                    .unwrap()
+                    .topdown_nonce
+            }
+            false => st.bottomup_nonce,
+        };
+        cross_msg.fee += &*DEFAULT_CROSS_MSG_FEE;
+        cross_msg.fee -= cross_msg
+            .fee
+            .div_floor(self.net_name.hops_to(&sto).unwrap() as u64);
+        expect_event(
+            rt,
+            GatewayEvent::Propagate(msg_event(&cross_msg, &DEFAULT_CROSS_MSG_FEE)),
+        );
+
         rt.call::<Actor>(
             Method::Propagate as MethodNum,
             IpldBlock::serialize_cbor(&PropagateParams { postbox_cid: cid })?,
@@ -614,6 +749,400 @@ impl Harness {
         Ok(())
     }
 
+    pub fn update_postbox_owners(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        method: Method,
+        params: Option<IpldBlock>,
+    ) -> Result<Cid, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_any();
+        let ret = rt.call::<Actor>(method as MethodNum, params);
+        rt.verify();
+        Ok(deserialize_block(ret?)?)
+    }
+
+    pub fn list_postbox_cids(
+        &self,
+        rt: &mut MockRuntime,
+        owner: &Address,
+        cursor: u64,
+        limit: u64,
+    ) -> ListPostboxCidsReturn {
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::ListPostboxCids as MethodNum,
+                IpldBlock::serialize_cbor(&ListPostboxCidsParams {
+                    owner: *owner,
+                    cursor,
+                    limit,
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn freeze_subnet(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        id: &SubnetID,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::FreezeSubnet as MethodNum,
+                    IpldBlock::serialize_cbor(id).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::FreezeSubnet as MethodNum,
+            IpldBlock::serialize_cbor(id).unwrap(),
+        )?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn recover_funds(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        params: RecoverFundsParams,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::RecoverFunds as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.expect_send(
+            params.address,
+            METHOD_SEND,
+            None,
+            params.amount.clone(),
+            None,
+            ExitCode::OK,
+        );
+        rt.call::<Actor>(
+            Method::RecoverFunds as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_checkpoint_limits(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        limits: CheckpointLimits,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::SetCheckpointLimits as MethodNum,
+                    IpldBlock::serialize_cbor(&limits).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::SetCheckpointLimits as MethodNum,
+            IpldBlock::serialize_cbor(&limits).unwrap(),
+        )?;
+        rt.verify();
+
+        let st: State = rt.get_state();
+        assert_eq!(st.bottomup_checkpoint_limits, limits);
+        Ok(())
+    }
+
+    pub fn set_subnet_limits(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        params: SetSubnetLimitsParams,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::SetSubnetLimits as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::SetSubnetLimits as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+
+        let sub = self.get_subnet(rt, &params.subnet).unwrap();
+        assert_eq!(sub.limits, params.limits);
+        Ok(())
+    }
+
+    pub fn release_held_msgs(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        params: ReleaseHeldMsgsParams,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        if params.force {
+            rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+        } else {
+            rt.expect_validate_caller_any();
+        }
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::ReleaseHeldMsgs as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::ReleaseHeldMsgs as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn reclaim_postbox(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        cid: Cid,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_any();
+
+        let params = ReclaimPostboxParams { postbox_cid: cid };
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::ReclaimPostbox as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::ReclaimPostbox as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_paused(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        paused: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GUARDIAN]);
+        let method = match paused {
+            true => Method::Pause,
+            false => Method::Unpause,
+        };
+
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(method as MethodNum, None));
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(method as MethodNum, None)?;
+        rt.verify();
+
+        let st: State = rt.get_state();
+        assert_eq!(st.paused, paused);
+        Ok(())
+    }
+
+    pub fn upgrade_state(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        params: UpgradeStateParams,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::UpgradeState as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::UpgradeState as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+
+        let st: State = rt.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        Ok(())
+    }
+
+    pub fn set_cross_msg_fees(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        fees: CrossMsgFees,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::SetCrossMsgFees as MethodNum,
+                    IpldBlock::serialize_cbor(&fees).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::SetCrossMsgFees as MethodNum,
+            IpldBlock::serialize_cbor(&fees).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+
+        let st: State = rt.get_state();
+        assert_eq!(st.cross_msg_fees, fees);
+        Ok(())
+    }
+
+    pub fn prune_bottomup_checkpoints(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        epoch: ChainEpoch,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = PruneCheckpointsParams { epoch };
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::PruneBottomUpCheckpoints as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::PruneBottomUpCheckpoints as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_executed_checkpoint(
+        &self,
+        rt: &mut MockRuntime,
+        epoch: ChainEpoch,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+
+        let params = ExecutedCheckpointParams { epoch };
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::SetExecutedCheckpoint as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::SetExecutedCheckpoint as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+        Ok(())
+    }
+
     pub fn check_state(&self) {
         // TODO: https://github.com/filecoin-project/builtin-actors/issues/44
     }
@@ -640,6 +1169,32 @@ impl Harness {
         Ok(())
     }
 
+    pub fn commit_membership(
+        &self,
+        rt: &mut MockRuntime,
+        subnet_addr: &Address,
+        validator_set: &ValidatorSet,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, *subnet_addr);
+        rt.expect_validate_caller_any();
+        let params = IpldBlock::serialize_cbor(validator_set).unwrap();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::CommitMembership as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::CommitMembership as MethodNum, params)?;
+        rt.verify();
+
+        Ok(())
+    }
+
     pub fn submit_topdown_check(
         &self,
         rt: &mut MockRuntime,
@@ -660,12 +1215,46 @@ impl Harness {
     }
 }
 
+/// Resolves an address as the mocked `PUBLIC_RESOLVE_ADDRESS_METHOD` calls do.
+fn resolved(addr: &Address) -> Address {
+    match addr.protocol() {
+        Protocol::ID => *TEST_BLS,
+        _ => *addr,
+    }
+}
+
+/// Splits the default cross-message fee between the hops in the route from `from`
+/// to `to`, returning the share of the first hop and the fee left for the rest.
+pub fn split_hop_fee(from: &SubnetID, to: &SubnetID) -> (TokenAmount, TokenAmount) {
+    let route = Route::with_fee(from, to, |_, kind| match kind {
+        FeeKind::Send => DEFAULT_CROSS_MSG_FEE.clone(),
+        FeeKind::Propagate => TokenAmount::zero(),
+    })
+    .unwrap();
+    let hop_fee = route.hops[0].fee.clone();
+    let left_fee = DEFAULT_CROSS_MSG_FEE.clone() - &hop_fee;
+    (hop_fee, left_fee)
+}
+
+pub fn msg_event(cross_msg: &CrossMsg, fee: &TokenAmount) -> CrossMsgEvent {
+    CrossMsgEvent::new(cross_msg_cid(cross_msg).unwrap(), cross_msg, fee.clone())
+}
+
+pub fn expect_event(rt: &mut MockRuntime, event: GatewayEvent) {
+    rt.expect_emitted_event(event.to_actor_event().unwrap());
+}
+
 pub fn get_subnet(rt: &MockRuntime, id: &SubnetID) -> Option<Subnet> {
     let st: State = rt.get_state();
     let subnets = st.subnets.load(rt.store()).unwrap();
     subnets.get(&id.to_bytes()).unwrap().cloned()
 }
 
+pub fn verify_empty_array(rt: &MockRuntime, key: Cid) {
+    let array = CheckpointArray::load(&key, &rt.store).unwrap();
+    assert_eq!(array.count(), 0);
+}
+
 pub fn verify_empty_map(rt: &MockRuntime, key: Cid) {
     let map =
         make_map_with_root_and_bitwidth::<_, BigIntDe>(&key, &rt.store, HAMT_BIT_WIDTH).unwrap();
@@ -686,7 +1275,7 @@ pub fn has_cid<'a, T: TCidContent>(children: &'a Vec<TCid<T>>, cid: &Cid) -> boo
 
 fn set_rt_value_with_cross_fee(rt: &mut MockRuntime, value: &TokenAmount) {
     rt.set_value(if value.clone() != TokenAmount::zero() {
-        value.clone() + &*CROSS_MSG_FEE
+        value.clone() + &*DEFAULT_CROSS_MSG_FEE
     } else {
         value.clone()
     });
@@ -6,8 +6,10 @@ use anyhow::anyhow;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
 use fvm_shared::MethodNum;
 use fvm_shared::METHOD_SEND;
+use num_traits::Zero;
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
 /// StorableMsg stores all the relevant information required
@@ -31,9 +33,31 @@ pub struct StorableMsg {
 pub struct CrossMsg {
     pub msg: StorableMsg,
     pub wrapped: bool,
+    /// Method of the sender invoked with the receipt of the execution of the
+    /// message at its destination. If `None`, no reply is sent back to the
+    /// sender on success (fire-and-forget).
+    pub reply_method: Option<MethodNum>,
+    /// Fee left to pay the hops ahead in the route of the message. Every gateway
+    /// in the route takes its share and rewards its validators with it.
+    pub fee: TokenAmount,
 }
 
-#[derive(PartialEq, Eq)]
+/// Receipt of the execution of a cross-message at its destination.
+///
+/// It is sent back to the sender of the message as the params of a receipt
+/// cross-message when the message fails, refunding its value, or when the
+/// sender requested a reply through `CrossMsg::reply_method`.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+pub struct CrossMsgReceipt {
+    /// The message executed
+    pub msg: StorableMsg,
+    /// Exit code of the execution of the message
+    pub exit_code: ExitCode,
+    /// Data returned by the execution of the message
+    pub return_data: RawBytes,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum IPCMsgType {
     BottomUp,
     TopDown,
@@ -89,6 +113,36 @@ impl StorableMsg {
         })
     }
 
+    /// Creates the message that sends back a receipt to the sender of the message,
+    /// invoking `method`. It carries no value, refunds are sent with `new_refund_msg`.
+    pub fn new_receipt_msg(
+        from: IPCAddress,
+        receipt: &CrossMsgReceipt,
+        method: MethodNum,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            from,
+            to: receipt.msg.from.clone(),
+            method,
+            params: RawBytes::serialize(receipt)?,
+            value: TokenAmount::zero(),
+            nonce: 0,
+        })
+    }
+
+    /// Creates the message that refunds the value of a failed message to its sender
+    /// as a plain transfer, including the receipt of the failure as params.
+    pub fn new_refund_msg(from: IPCAddress, receipt: &CrossMsgReceipt) -> anyhow::Result<Self> {
+        Ok(Self {
+            from,
+            to: receipt.msg.from.clone(),
+            method: METHOD_SEND,
+            params: RawBytes::serialize(receipt)?,
+            value: receipt.msg.value.clone(),
+            nonce: 0,
+        })
+    }
+
     pub fn ipc_type(&self) -> anyhow::Result<IPCMsgType> {
         let sto = self.to.subnet()?;
         let sfrom = self.from.subnet()?;
@@ -0,0 +1,182 @@
+//! Schema of the actor events emitted by the gateway.
+//!
+//! Every event has two entries: `kind`, holding the CBOR-encoded name of the operation
+//! that emitted it and indexed so clients can filter by operation, and `data`, holding
+//! the CBOR-encoded payload of the event, a `CrossMsgEvent` or a `CheckpointEvent`
+//! according to its kind.
+
+use crate::address::IPCAddress;
+use crate::cross::CrossMsg;
+use crate::subnet_id::SubnetID;
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_encoding::{from_slice, to_vec, DAG_CBOR};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::event::{ActorEvent, Entry, Flags};
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+
+/// Key of the entry holding the kind of the event.
+pub const EVENT_KIND_KEY: &str = "kind";
+/// Key of the entry holding the payload of the event.
+pub const EVENT_DATA_KEY: &str = "data";
+
+/// Payload of the events emitted for a cross-message.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+pub struct CrossMsgEvent {
+    /// Cid of the cross-message as committed or applied by the gateway
+    pub msg_cid: Cid,
+    pub from: IPCAddress,
+    pub to: IPCAddress,
+    pub value: TokenAmount,
+    pub nonce: u64,
+    /// Cross-message fee charged by the gateway
+    pub fee: TokenAmount,
+}
+
+impl CrossMsgEvent {
+    pub fn new(msg_cid: Cid, cross_msg: &CrossMsg, fee: TokenAmount) -> Self {
+        Self {
+            msg_cid,
+            from: cross_msg.msg.from.clone(),
+            to: cross_msg.msg.to.clone(),
+            value: cross_msg.msg.value.clone(),
+            nonce: cross_msg.msg.nonce,
+            fee,
+        }
+    }
+}
+
+/// Payload of the events emitted for a checkpoint.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+pub struct CheckpointEvent {
+    /// The child subnet for bottom-up checkpoints, the subnet applying the
+    /// checkpoint for top-down ones
+    pub subnet: SubnetID,
+    pub epoch: ChainEpoch,
+    /// Number of cross-messages included in the checkpoint
+    pub num_msgs: u64,
+    /// Total value of the cross-messages included in the checkpoint
+    pub value: TokenAmount,
+    /// Total fee of the cross-messages included in the checkpoint
+    pub fee: TokenAmount,
+}
+
+/// Events emitted by the gateway, one variant per operation.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum GatewayEvent {
+    Fund(CrossMsgEvent),
+    Release(CrossMsgEvent),
+    SendCross(CrossMsgEvent),
+    Propagate(CrossMsgEvent),
+    ApplyMsg(CrossMsgEvent),
+    CommitChildCheck(CheckpointEvent),
+    SubmitTopDownCheck(CheckpointEvent),
+}
+
+impl GatewayEvent {
+    /// Returns the kind of the event, stored in the `kind` entry.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GatewayEvent::Fund(_) => "fund",
+            GatewayEvent::Release(_) => "release",
+            GatewayEvent::SendCross(_) => "send_cross",
+            GatewayEvent::Propagate(_) => "propagate",
+            GatewayEvent::ApplyMsg(_) => "apply_msg",
+            GatewayEvent::CommitChildCheck(_) => "commit_child_check",
+            GatewayEvent::SubmitTopDownCheck(_) => "submit_topdown_check",
+        }
+    }
+
+    /// Encodes the event as an actor event.
+    pub fn to_actor_event(&self) -> anyhow::Result<ActorEvent> {
+        let data = match self {
+            GatewayEvent::Fund(e)
+            | GatewayEvent::Release(e)
+            | GatewayEvent::SendCross(e)
+            | GatewayEvent::Propagate(e)
+            | GatewayEvent::ApplyMsg(e) => to_vec(e)?,
+            GatewayEvent::CommitChildCheck(e) | GatewayEvent::SubmitTopDownCheck(e) => to_vec(e)?,
+        };
+        Ok(ActorEvent::from(vec![
+            Entry {
+                flags: Flags::FLAG_INDEXED_ALL,
+                key: EVENT_KIND_KEY.to_string(),
+                codec: DAG_CBOR,
+                value: to_vec(self.kind())?,
+            },
+            Entry {
+                flags: Flags::empty(),
+                key: EVENT_DATA_KEY.to_string(),
+                codec: DAG_CBOR,
+                value: data,
+            },
+        ]))
+    }
+}
+
+impl TryFrom<&ActorEvent> for GatewayEvent {
+    type Error = anyhow::Error;
+
+    /// Decodes a gateway event from an actor event emitted by the gateway.
+    fn try_from(event: &ActorEvent) -> Result<Self, Self::Error> {
+        let entry = |key: &str| {
+            event
+                .entries
+                .iter()
+                .find(|e| e.key == key)
+                .ok_or_else(|| anyhow!("event has no {} entry", key))
+        };
+        let kind: String = from_slice(&entry(EVENT_KIND_KEY)?.value)?;
+        let data = &entry(EVENT_DATA_KEY)?.value;
+
+        Ok(match kind.as_str() {
+            "fund" => GatewayEvent::Fund(from_slice(data)?),
+            "release" => GatewayEvent::Release(from_slice(data)?),
+            "send_cross" => GatewayEvent::SendCross(from_slice(data)?),
+            "propagate" => GatewayEvent::Propagate(from_slice(data)?),
+            "apply_msg" => GatewayEvent::ApplyMsg(from_slice(data)?),
+            "commit_child_check" => GatewayEvent::CommitChildCheck(from_slice(data)?),
+            "submit_topdown_check" => GatewayEvent::SubmitTopDownCheck(from_slice(data)?),
+            _ => return Err(anyhow!("unknown gateway event kind: {}", kind)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address::IPCAddress;
+    use crate::events::{CheckpointEvent, CrossMsgEvent, GatewayEvent};
+    use crate::subnet_id::SubnetID;
+    use cid::Cid;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_event_roundtrip() {
+        let subnet = SubnetID::from_str("/r123/f01").unwrap();
+        let events = vec![
+            GatewayEvent::Fund(CrossMsgEvent {
+                msg_cid: Cid::default(),
+                from: IPCAddress::new(&subnet.parent().unwrap(), &Address::new_id(100)).unwrap(),
+                to: IPCAddress::new(&subnet, &Address::new_id(101)).unwrap(),
+                value: TokenAmount::from_atto(1000),
+                nonce: 3,
+                fee: TokenAmount::from_atto(10),
+            }),
+            GatewayEvent::CommitChildCheck(CheckpointEvent {
+                subnet,
+                epoch: 10,
+                num_msgs: 2,
+                value: TokenAmount::from_atto(2000),
+                fee: TokenAmount::from_atto(20),
+            }),
+        ];
+
+        for event in events {
+            let actor_event = event.to_actor_event().unwrap();
+            assert_eq!(GatewayEvent::try_from(&actor_event).unwrap(), event);
+        }
+    }
+}
@@ -6,6 +6,8 @@ use integer_encoding::VarInt;
 pub mod address;
 pub mod cross;
 pub mod error;
+pub mod events;
+pub mod route;
 pub mod subnet_id;
 
 /// Encodes the a ChainEpoch as a varInt for its use
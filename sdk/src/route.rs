@@ -0,0 +1,182 @@
+//! Route planning and fee estimation for cross-messages.
+//!
+//! A cross-message travels up the hierarchy from its source subnet to the
+//! common parent with its destination, and then down to the destination.
+//! Every gateway in the route adds the fee it charges to the one attached to
+//! the message, and takes an even share of it to reward its validators.
+
+use crate::cross::IPCMsgType;
+use crate::error::Error;
+use crate::subnet_id::SubnetID;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+/// Operation a gateway charges a fee for when committing a cross-message.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FeeKind {
+    /// The message is sent from the source subnet of the route.
+    Send,
+    /// The message is propagated from the postbox of an intermediate subnet.
+    Propagate,
+}
+
+/// A hop in the route of a cross-message between two adjacent subnets.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Hop {
+    pub from: SubnetID,
+    pub to: SubnetID,
+    pub direction: IPCMsgType,
+    /// Share of the fee rewarded to the validators for this hop.
+    pub fee: TokenAmount,
+}
+
+/// Route followed by a cross-message from its source to its destination.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Route {
+    pub hops: Vec<Hop>,
+}
+
+impl Route {
+    /// Plans the route from `from` to `to` with no fee attached to the message.
+    pub fn new(from: &SubnetID, to: &SubnetID) -> Result<Self, Error> {
+        let (_, common) = from.common_parent(to).ok_or(Error::InvalidID)?;
+
+        let mut hops = Vec::new();
+        let mut curr = from.clone();
+        // go up to the common parent...
+        while curr != common {
+            let next = from.up(&curr).ok_or(Error::InvalidID)?;
+            hops.push(Hop {
+                from: curr,
+                to: next.clone(),
+                direction: IPCMsgType::BottomUp,
+                fee: TokenAmount::zero(),
+            });
+            curr = next;
+        }
+        // ...and down to the destination.
+        while curr != *to {
+            let next = to.down(&curr).ok_or(Error::InvalidID)?;
+            hops.push(Hop {
+                from: curr,
+                to: next.clone(),
+                direction: IPCMsgType::TopDown,
+                fee: TokenAmount::zero(),
+            });
+            curr = next;
+        }
+
+        Ok(Self { hops })
+    }
+
+    /// Plans the route from `from` to `to`, estimating the share of the fee taken
+    /// in each hop. `gateway_fee` returns the fee charged by the gateway of a subnet
+    /// to commit the message in it: when it is sent from the source subnet and when
+    /// it is propagated from every intermediate subnet.
+    pub fn with_fee(
+        from: &SubnetID,
+        to: &SubnetID,
+        gateway_fee: impl Fn(&SubnetID, FeeKind) -> TokenAmount,
+    ) -> Result<Self, Error> {
+        let mut route = Self::new(from, to)?;
+        let mut left = TokenAmount::zero();
+        let len = route.hops.len();
+        for (i, hop) in route.hops.iter_mut().enumerate() {
+            let kind = if i == 0 {
+                FeeKind::Send
+            } else {
+                FeeKind::Propagate
+            };
+            left += gateway_fee(&hop.from, kind);
+            hop.fee = hop_fee(&left, len - i);
+            left -= &hop.fee;
+        }
+        Ok(route)
+    }
+
+    /// Number of hops in the route.
+    pub fn len(&self) -> usize {
+        self.hops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    /// Fee paid along the whole route, the one charged when sending the message
+    /// plus the ones charged to propagate it.
+    pub fn total_fee(&self) -> TokenAmount {
+        self.hops
+            .iter()
+            .fold(TokenAmount::zero(), |acc, hop| acc + &hop.fee)
+    }
+}
+
+/// Share of `fee` taken in the next hop when `hops` are left in the route of
+/// a message. The fee is split evenly and the last hop takes any remainder.
+pub fn hop_fee(fee: &TokenAmount, hops: usize) -> TokenAmount {
+    if hops == 0 {
+        return TokenAmount::zero();
+    }
+    fee.div_floor(hops as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cross::IPCMsgType;
+    use crate::route::{FeeKind, Route};
+    use crate::subnet_id::SubnetID;
+    use fvm_shared::econ::TokenAmount;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_route() {
+        let from = SubnetID::from_str("/r123/f01/f02").unwrap();
+        let to = SubnetID::from_str("/r123/f01/f03/f04").unwrap();
+        let route = Route::new(&from, &to).unwrap();
+        let expected = vec![
+            ("/r123/f01/f02", "/r123/f01", IPCMsgType::BottomUp),
+            ("/r123/f01", "/r123/f01/f03", IPCMsgType::TopDown),
+            ("/r123/f01/f03", "/r123/f01/f03/f04", IPCMsgType::TopDown),
+        ];
+        assert_eq!(route.len(), from.hops_to(&to).unwrap());
+        for (hop, (f, t, dir)) in route.hops.iter().zip(expected) {
+            assert_eq!(hop.from, SubnetID::from_str(f).unwrap());
+            assert_eq!(hop.to, SubnetID::from_str(t).unwrap());
+            assert_eq!(hop.direction, dir);
+        }
+
+        // no route to the same subnet or across different roots
+        assert!(Route::new(&from, &from).unwrap().is_empty());
+        let other = SubnetID::from_str("/r124/f01").unwrap();
+        assert!(Route::new(&from, &other).is_err());
+    }
+
+    #[test]
+    fn test_route_fee() {
+        let from = SubnetID::from_str("/r123/f01/f02").unwrap();
+        let to = SubnetID::from_str("/r123/f01/f03/f04").unwrap();
+        let fee = TokenAmount::from_atto(10);
+        let route = Route::with_fee(&from, &to, |_, kind| match kind {
+            FeeKind::Send => fee.clone(),
+            FeeKind::Propagate => TokenAmount::zero(),
+        })
+        .unwrap();
+        let fees: Vec<_> = route.hops.iter().map(|h| h.fee.atto().clone()).collect();
+        assert_eq!(fees, vec![3.into(), 3.into(), 4.into()]);
+        assert_eq!(route.total_fee(), fee);
+
+        // the message is propagated in the common parent and the subnet after it,
+        // the fees charged there are added to the one left attached to the message
+        let propagated = SubnetID::from_str("/r123/f01").unwrap();
+        let route = Route::with_fee(&from, &to, |subnet, kind| match kind {
+            FeeKind::Send => fee.clone(),
+            FeeKind::Propagate if *subnet == propagated => TokenAmount::from_atto(5),
+            FeeKind::Propagate => TokenAmount::from_atto(2),
+        })
+        .unwrap();
+        let fees: Vec<_> = route.hops.iter().map(|h| h.fee.atto().clone()).collect();
+        assert_eq!(fees, vec![3.into(), 6.into(), 8.into()]);
+        assert_eq!(route.total_fee(), TokenAmount::from_atto(17));
+    }
+}
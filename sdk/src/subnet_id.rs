@@ -155,6 +155,14 @@ impl SubnetID {
         Some((common, SubnetID::new(self.root_id(), children)))
     }
 
+    /// Number of hops in the path from the current subnet to `to`, going
+    /// up to their common parent and then down to `to`. Returns `None` if
+    /// the subnets don't belong to the same hierarchy.
+    pub fn hops_to(&self, to: &SubnetID) -> Option<usize> {
+        let (common, _) = self.common_parent(to)?;
+        Some(self.children_as_ref().len() + to.children_as_ref().len() - 2 * common)
+    }
+
     /// In the path determined by the current subnet id, it moves
     /// down in the path from the subnet id given as argument.
     pub fn down(&self, from: &SubnetID) -> Option<SubnetID> {
@@ -273,6 +281,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hops_to() {
+        hops_to("/r123/f01", "/r123/f01/f02", Some(1));
+        hops_to("/r123/f01/f02", "/r123", Some(2));
+        hops_to("/r123/f01/f02", "/r123/f03/f04/f05", Some(5));
+        hops_to("/r123/f01", "/r123/f01", Some(0));
+        hops_to("/r122/f01", "/r123/f01", None);
+    }
+
+    fn hops_to(a: &str, b: &str, res: Option<usize>) {
+        assert_eq!(
+            SubnetID::from_str(a)
+                .unwrap()
+                .hops_to(&SubnetID::from_str(b).unwrap()),
+            res
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_panic_different_root() {
@@ -1,18 +1,23 @@
 pub mod state;
 pub mod types;
 
+use fil_actors_runtime::runtime::fvm::resolve_secp_bls;
 use fil_actors_runtime::runtime::{ActorCode, Runtime};
 use fil_actors_runtime::{
     actor_dispatch, actor_error, restrict_internal_api, ActorDowncast, ActorError,
-    CALLER_TYPES_SIGNABLE, INIT_ACTOR_ADDR,
+    BURNT_FUNDS_ACTOR_ADDR, CALLER_TYPES_SIGNABLE, INIT_ACTOR_ADDR, SYSTEM_ACTOR_ADDR,
 };
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR, METHOD_SEND};
-use ipc_gateway::{AmountParams, BottomUpCheckpoint, MIN_COLLATERAL_AMOUNT};
+use ipc_gateway::{
+    AmountParams, BottomUpCheckpoint, RegisterParams, SubnetID, MIN_COLLATERAL_AMOUNT,
+};
 use num::BigInt;
 use num_derive::FromPrimitive;
 use num_traits::{FromPrimitive, Zero};
@@ -34,6 +39,9 @@ pub enum Method {
     SubmitCheckpoint = frc42_dispatch::method_hash!("SubmitCheckpoint"),
     SetValidatorNetAddr = frc42_dispatch::method_hash!("SetValidatorNetAddr"),
     Reward = frc42_dispatch::method_hash!("Reward"),
+    MigrateCheckpoints = frc42_dispatch::method_hash!("MigrateCheckpoints"),
+    SubmitSignedCheckpoint = frc42_dispatch::method_hash!("SubmitSignedCheckpoint"),
+    ReportEquivocation = frc42_dispatch::method_hash!("ReportEquivocation"),
 }
 
 /// SubnetActor trait. Custom subnet actors need to implement this trait
@@ -79,12 +87,9 @@ impl SubnetActor for Actor {
     fn constructor(rt: &mut impl Runtime, params: ConstructParams) -> Result<(), ActorError> {
         rt.validate_immediate_caller_is(std::iter::once(&INIT_ACTOR_ADDR))?;
 
-        // NOTE: we currently use 0 as the genesis_epoch for subnets so checkpoints
-        // are submitted directly from epoch 0.
-        // In the future we can use the current epoch. This will be really
-        // useful once we support the docking of subnets to new parents, etc.
-        // let genesis_epoch = rt.curr_epoch();
-        let genesis_epoch = 0;
+        // checkpoints are counted from the genesis epoch of the child, which is
+        // registered in the parent to initialize the gateway of the subnet.
+        let genesis_epoch = params.bottomup_genesis_epoch;
         let st = State::new(rt, params, genesis_epoch).map_err(|e| {
             e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "Failed to create actor state")
         })?;
@@ -110,7 +115,9 @@ impl SubnetActor for Actor {
         }
 
         let mut msg = None;
+        let mut membership = None;
         rt.transaction(|st: &mut State, rt| {
+            let config_number = st.validator_set.config_number();
             // increase collateral
             st.add_stake(rt.store(), &caller, &params.validator_net_addr, &amount)
                 .map_err(|e| {
@@ -124,7 +131,9 @@ impl SubnetActor for Actor {
                     msg = Some(CrossActorPayload::new(
                         st.ipc_gateway_addr,
                         ipc_gateway::Method::Register as u64,
-                        None,
+                        IpldBlock::serialize_cbor(&RegisterParams {
+                            bottomup_genesis_epoch: st.bottomup_checkpoint_voting.genesis_epoch(),
+                        })?,
                         total_stake,
                     ));
                 }
@@ -137,6 +146,10 @@ impl SubnetActor for Actor {
                 ));
             }
 
+            if msg.is_some() {
+                membership = commit_membership(st, config_number)?;
+            }
+
             st.mutate_state();
 
             Ok(())
@@ -145,6 +158,9 @@ impl SubnetActor for Actor {
         if let Some(p) = msg {
             rt.send(&p.to, p.method, p.params, p.value)?;
         }
+        if let Some(p) = membership {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
 
         Ok(None)
     }
@@ -155,7 +171,9 @@ impl SubnetActor for Actor {
 
         let caller = rt.message().caller();
         let mut msg = None;
+        let mut membership = None;
         let value = rt.transaction(|st: &mut State, rt| {
+            let config_number = st.validator_set.config_number();
             let stake = st.get_stake(rt.store(), &caller).map_err(|e| {
                 e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
             })?;
@@ -181,6 +199,10 @@ impl SubnetActor for Actor {
                 e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot remove stake")
             })?;
 
+            if msg.is_some() {
+                membership = commit_membership(st, config_number)?;
+            }
+
             st.mutate_state();
 
             Ok(ret_amount)
@@ -192,6 +214,9 @@ impl SubnetActor for Actor {
             // return stake to caller
             rt.send(&caller, METHOD_SEND, None, value)?;
         }
+        if let Some(p) = membership {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
 
         Ok(None)
     }
@@ -264,59 +289,7 @@ impl SubnetActor for Actor {
             return Err(actor_error!(illegal_state, "not validator"));
         }
 
-        // always translate to f0-based SubnetID
-        let mut ch = ch;
-        ch.enforce_f0_source(rt)
-            .map_err(|_| actor_error!(illegal_argument, "cannot translate source subnetID"))?;
-
-        state
-            .verify_checkpoint(rt, &ch)
-            .map_err(|e| actor_error!(illegal_state, format!("checkpoint failed: {e}")))?;
-
-        let msg = rt.transaction(|st: &mut State, rt| {
-            let store = rt.store();
-
-            let total_validator_weight = st.total_stake.clone();
-            let submitter_weight = st
-                .get_stake(store, &caller)
-                .map_err(|_| actor_error!(illegal_state, "cannot get validator stake"))?
-                .unwrap_or_else(TokenAmount::zero);
-            let submission_epoch = ch.epoch();
-
-            let some_checkpoint = st
-                .bottomup_checkpoint_voting
-                .submit_vote(
-                    rt.store(),
-                    ch,
-                    submission_epoch,
-                    caller,
-                    submitter_weight,
-                    total_validator_weight,
-                )
-                .map_err(|e| {
-                    log::error!("encountered error submitting checkpoint: {:?}", e);
-                    actor_error!(illegal_state, e.to_string())
-                })?;
-
-            if let Some(ch) = some_checkpoint {
-                commit_checkpoint(st, store, &ch)
-            } else if let Some(ch) = st
-                .bottomup_checkpoint_voting
-                .get_next_executable_vote(store)
-                .map_err(|_| actor_error!(illegal_state, "cannot check previous checkpoint"))?
-            {
-                commit_checkpoint(st, store, &ch)
-            } else {
-                Ok(None)
-            }
-        })?;
-
-        // propagate to gateway
-        if let Some(p) = msg {
-            rt.send(&p.to, p.method, p.params, p.value)?;
-        }
-
-        Ok(None)
+        submit_checkpoint_votes(rt, &state, ch, vec![caller])
     }
 
     /// Distributes the rewards for the subnet to validators.
@@ -384,6 +357,170 @@ impl Actor {
         })?;
         Ok(None)
     }
+
+    /// Submits a checkpoint signed by a set of validators of the subnet.
+    ///
+    /// The signatures over the cid of the checkpoint are verified against the keys of
+    /// the validators, and each of them is counted as the vote of the validator, so a
+    /// single relayer can submit a checkpoint signed by a quorum of validators.
+    pub fn submit_signed_checkpoint(
+        rt: &mut impl Runtime,
+        params: SignedCheckpointParams,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+
+        let SignedCheckpointParams {
+            checkpoint,
+            signatures,
+        } = params;
+        if signatures.is_empty() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no validator signatures for checkpoint"
+            ));
+        }
+
+        let state: State = rt.state()?;
+        let mut signers = Vec::with_capacity(signatures.len());
+        for ValidatorSignature {
+            validator,
+            signature,
+        } in signatures
+        {
+            if !state.is_validator(&validator) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "signer {} is not a validator",
+                    validator
+                ));
+            }
+            if signers.contains(&validator) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "duplicated signature from validator {}",
+                    validator
+                ));
+            }
+
+            verify_validator_signature(rt, &validator, &signature, &checkpoint)?;
+            signers.push(validator);
+        }
+
+        submit_checkpoint_votes(rt, &state, checkpoint, signers)
+    }
+
+    /// Reports a validator that signed two different checkpoints of this subnet for the same epoch.
+    ///
+    /// Once both signatures are verified, the whole stake of the validator is slashed
+    /// and burnt, and the validator is removed from the validator set.
+    pub fn report_equivocation(
+        rt: &mut impl Runtime,
+        params: ReportEquivocationParams,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+
+        let ReportEquivocationParams {
+            validator,
+            first,
+            first_signature,
+            second,
+            second_signature,
+        } = params;
+        if first.epoch() != second.epoch() {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoints don't belong to the same epoch"
+            ));
+        }
+        if first.cid() == second.cid() {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoints are not conflicting"
+            ));
+        }
+        if first.source() != second.source() {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoints don't belong to the same subnet"
+            ));
+        }
+
+        let state: State = rt.state()?;
+        // validators may be validating other subnets, only checkpoints of this one count
+        if *first.source() != SubnetID::new_from_parent(&state.parent_id, rt.message().receiver()) {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoints don't belong to this subnet"
+            ));
+        }
+        if !state.is_validator(&validator) {
+            return Err(actor_error!(
+                illegal_argument,
+                "address {} is not a validator",
+                validator
+            ));
+        }
+        verify_validator_signature(rt, &validator, &first_signature, &first)?;
+        verify_validator_signature(rt, &validator, &second_signature, &second)?;
+
+        let mut msg = None;
+        let mut membership = None;
+        let slashed = rt.transaction(|st: &mut State, rt| {
+            let config_number = st.validator_set.config_number();
+            let slashed = st.slash_stake(rt.store(), &validator).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot slash stake")
+            })?;
+
+            if st.status != Status::Terminating && !slashed.is_zero() {
+                msg = Some(CrossActorPayload::new(
+                    st.ipc_gateway_addr,
+                    ipc_gateway::Method::ReleaseStake as u64,
+                    IpldBlock::serialize_cbor(&AmountParams {
+                        value: slashed.clone(),
+                    })?,
+                    TokenAmount::zero(),
+                ));
+            }
+            if msg.is_some() {
+                membership = commit_membership(st, config_number)?;
+            }
+
+            st.mutate_state();
+
+            Ok(slashed)
+        })?;
+
+        if let Some(p) = msg {
+            // release the collateral from the gateway
+            rt.send(&p.to, p.method, p.params, p.value)?;
+            // and burn it
+            rt.send(&BURNT_FUNDS_ACTOR_ADDR, METHOD_SEND, None, slashed)?;
+        }
+        if let Some(p) = membership {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Migrates the committed checkpoints and pending checkpoint votes of subnet
+    /// actors deployed with the legacy HAMT layout to the AMT indexed by period.
+    /// It is invoked by the system actor after a code upgrade, and it is a no-op
+    /// if checkpoints have already been migrated.
+    pub fn migrate_checkpoints(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])?;
+
+        rt.transaction(|st: &mut State, rt| {
+            let migrated = st.migrate_checkpoints(rt.store()).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error migrating checkpoints")
+            })?;
+            if migrated {
+                log::info!("migrated committed checkpoints to the period-indexed layout");
+            }
+            Ok(())
+        })?;
+        Ok(None)
+    }
 }
 
 impl ActorCode for Actor {
@@ -397,9 +534,120 @@ impl ActorCode for Actor {
         SubmitCheckpoint => submit_checkpoint,
         Reward => reward,
         SetValidatorNetAddr => set_validator_net_addr,
+        MigrateCheckpoints => migrate_checkpoints,
+        SubmitSignedCheckpoint => submit_signed_checkpoint,
+        ReportEquivocation => report_equivocation,
     }
 }
 
+/// Verifies the signature of a validator over the cid of a checkpoint using
+/// the key the validator address resolves to.
+fn verify_validator_signature(
+    rt: &mut impl Runtime,
+    validator: &Address,
+    signature: &Signature,
+    ch: &BottomUpCheckpoint,
+) -> Result<(), ActorError> {
+    let key = resolve_secp_bls(rt, validator)?;
+    rt.verify_signature(signature, &key, &ch.cid().to_bytes())
+        .map_err(|e| {
+            actor_error!(
+                illegal_argument,
+                "invalid signature from validator {}: {}",
+                validator,
+                e
+            )
+        })
+}
+
+/// Submits the votes of `voters` for a checkpoint, committing it if the voting
+/// threshold is reached, and propagates the committed checkpoint to the gateway.
+fn submit_checkpoint_votes(
+    rt: &mut impl Runtime,
+    state: &State,
+    mut ch: BottomUpCheckpoint,
+    voters: Vec<Address>,
+) -> Result<Option<RawBytes>, ActorError> {
+    // always translate to f0-based SubnetID
+    ch.enforce_f0_source(rt)
+        .map_err(|_| actor_error!(illegal_argument, "cannot translate source subnetID"))?;
+
+    state
+        .verify_checkpoint(rt, &ch)
+        .map_err(|e| actor_error!(illegal_state, format!("checkpoint failed: {e}")))?;
+
+    let msg = rt.transaction(|st: &mut State, rt| {
+        let store = rt.store();
+
+        let total_validator_weight = st.total_stake.clone();
+        let submission_epoch = ch.epoch();
+
+        let mut some_checkpoint = None;
+        for voter in voters {
+            let submitter_weight = st
+                .get_stake(store, &voter)
+                .map_err(|_| actor_error!(illegal_state, "cannot get validator stake"))?
+                .unwrap_or_else(TokenAmount::zero);
+
+            some_checkpoint = st
+                .bottomup_checkpoint_voting
+                .submit_vote(
+                    rt.store(),
+                    ch.clone(),
+                    submission_epoch,
+                    voter,
+                    submitter_weight,
+                    total_validator_weight.clone(),
+                )
+                .map_err(|e| {
+                    log::error!("encountered error submitting checkpoint: {:?}", e);
+                    actor_error!(illegal_state, e.to_string())
+                })?;
+            // the voting for the epoch is over once the checkpoint can be committed
+            if some_checkpoint.is_some() {
+                break;
+            }
+        }
+
+        if let Some(ch) = some_checkpoint {
+            commit_checkpoint(st, store, &ch)
+        } else if let Some(ch) = st
+            .bottomup_checkpoint_voting
+            .get_next_executable_vote(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot check previous checkpoint"))?
+        {
+            commit_checkpoint(st, store, &ch)
+        } else {
+            Ok(None)
+        }
+    })?;
+
+    // propagate to gateway
+    if let Some(p) = msg {
+        rt.send(&p.to, p.method, p.params, p.value)?;
+    }
+
+    Ok(None)
+}
+
+/// Prepares the message committing the current validator set in the gateway, so
+/// it is propagated top-down to the gateway of the subnet. Nothing needs to be
+/// committed if the set didn't change since `config_number`.
+fn commit_membership(
+    st: &State,
+    config_number: u64,
+) -> Result<Option<CrossActorPayload>, ActorError> {
+    if st.validator_set.config_number() == config_number {
+        return Ok(None);
+    }
+    Ok(Some(CrossActorPayload::new(
+        st.ipc_gateway_addr,
+        ipc_gateway::Method::CommitMembership as u64,
+        IpldBlock::serialize_cbor(&st.validator_set)?,
+        TokenAmount::zero(),
+    )))
+}
+
 /// The checkpoint to be committed should be the same as the previous executed checkpoint's cid before execution
 fn commit_checkpoint(
     st: &mut State,
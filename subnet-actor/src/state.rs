@@ -10,16 +10,17 @@ use fvm_shared::bigint::Zero;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use ipc_actor_common::vote::Voting;
+use ipc_gateway::checkpoint::{checkpoint_index, migrate_checkpoints};
+use ipc_gateway::upgrade::{migrate_voting_v0, BottomUpCheckpointV0};
 use ipc_gateway::{
-    BottomUpCheckpoint, SubnetID, CHECKPOINT_GENESIS_CID, DEFAULT_CHECKPOINT_PERIOD,
-    MIN_COLLATERAL_AMOUNT,
+    get_checkpoint, BottomUpCheckpoint, SubnetID, CHECKPOINT_AMT_BITWIDTH, CHECKPOINT_GENESIS_CID,
+    DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT,
 };
-use ipc_sdk::epoch_key;
 use ipc_sdk::{Validator, ValidatorSet};
 use lazy_static::lazy_static;
 use num::rational::Ratio;
 use num::BigInt;
-use primitives::{TCid, THamt};
+use primitives::{TAmt, TCid, THamt};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
 use crate::types::*;
@@ -51,9 +52,8 @@ pub struct State {
     pub bottomup_check_period: ChainEpoch,
     pub topdown_check_period: ChainEpoch,
 
-    // FIXME: Consider making checkpoints a HAMT instead of an AMT so we use
-    // the AMT index instead of and epoch k for object indexing.
-    pub committed_checkpoints: TCid<THamt<ChainEpoch, BottomUpCheckpoint>>,
+    /// Committed checkpoints indexed by `checkpoint_index`.
+    pub committed_checkpoints: TCid<TAmt<BottomUpCheckpoint, CHECKPOINT_AMT_BITWIDTH>>,
     pub validator_set: ValidatorSet,
     pub min_validators: u64,
     pub previous_executed_checkpoint_cid: Cid,
@@ -95,7 +95,7 @@ impl State {
             min_validators: params.min_validators,
             bottomup_check_period,
             topdown_check_period,
-            committed_checkpoints: TCid::new_hamt(store)?,
+            committed_checkpoints: TCid::new_amt(store)?,
             genesis: params.genesis,
             status: Status::Instantiated,
             stake: TCid::new_hamt(store)?,
@@ -206,6 +206,25 @@ impl State {
         })
     }
 
+    /// Slashes the whole stake of a validator, removing it from the validator set.
+    /// Returns the amount slashed.
+    pub(crate) fn slash_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        self.stake.modify(store, |hamt| {
+            let key = BytesKey::from(addr.to_bytes());
+            let stake = hamt.get(&key)?.cloned().unwrap_or_else(TokenAmount::zero);
+
+            hamt.delete(&key)?;
+            self.total_stake -= &stake;
+            self.validator_set.rm(addr);
+
+            Ok(stake)
+        })
+    }
+
     pub fn has_majority_vote<BS: Blockstore>(
         &self,
         store: &BS,
@@ -325,14 +344,46 @@ impl State {
         store: &BS,
         ch: &BottomUpCheckpoint,
     ) -> anyhow::Result<()> {
-        let epoch = ch.epoch();
-        self.committed_checkpoints.modify(store, |hamt| {
-            hamt.set(epoch_key(epoch), ch.clone())
+        let index = checkpoint_index(ch.epoch(), self.bottomup_check_period);
+        self.committed_checkpoints.modify(store, |amt| {
+            amt.set(index, ch.clone())
                 .map_err(|e| anyhow!("failed to set checkpoint: {:?}", e))?;
             Ok(true)
         })?;
         Ok(())
     }
+
+    /// Migrates committed checkpoints from the legacy HAMT keyed by epoch
+    /// to the AMT indexed by period, together with the pending checkpoint votes.
+    /// Both are decoded in their version 0 layout. Returns false if they were
+    /// already migrated.
+    pub fn migrate_checkpoints<BS: Blockstore>(&mut self, store: &BS) -> anyhow::Result<bool> {
+        let period = self.bottomup_check_period;
+        self.committed_checkpoints =
+            match migrate_checkpoints(store, &self.committed_checkpoints.cid(), period)? {
+                Some(checkpoints) => checkpoints,
+                None => return Ok(false),
+            };
+
+        let voting = &self.bottomup_checkpoint_voting;
+        let legacy = Voting::<BottomUpCheckpointV0> {
+            genesis_epoch: voting.genesis_epoch,
+            submission_period: voting.submission_period,
+            last_voting_executed_epoch: voting.last_voting_executed_epoch,
+            executable_epoch_queue: voting.executable_epoch_queue.clone(),
+            epoch_vote_submissions: TCid::from(voting.epoch_vote_submissions.cid()),
+            threshold_ratio: voting.threshold_ratio,
+        };
+        self.bottomup_checkpoint_voting = migrate_voting_v0(store, legacy)?;
+
+        // the next checkpoint is chained to the cid of the executed one in its new layout
+        let checkpoints = self.committed_checkpoints.load(store)?;
+        let last = self.bottomup_checkpoint_voting.last_voting_executed_epoch;
+        if let Some(ch) = get_checkpoint(&checkpoints, last, period)? {
+            self.previous_executed_checkpoint_cid = ch.cid();
+        }
+        Ok(true)
+    }
 }
 
 impl Default for State {
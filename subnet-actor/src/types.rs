@@ -4,9 +4,10 @@ use fvm_ipld_encoding::serde_bytes;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::MethodNum;
-use ipc_gateway::SubnetID;
+use ipc_gateway::{BottomUpCheckpoint, SubnetID};
 
 /// ID used in the builtin-actors bundle manifest
 pub const MANIFEST_ID: &str = "ipc_subnet_actor";
@@ -111,6 +112,10 @@ pub struct ConstructParams {
     pub min_validator_stake: TokenAmount,
     pub min_validators: u64,
     pub bottomup_check_period: ChainEpoch,
+    /// Epoch of the subnet from which its bottom-up checkpoints are counted. It
+    /// is registered in the parent gateway, from which the gateway of the subnet
+    /// is initialized.
+    pub bottomup_genesis_epoch: ChainEpoch,
     pub topdown_check_period: ChainEpoch,
     // genesis is no longer generated by the actor
     // on-the-fly, but it is accepted as a construct
@@ -124,6 +129,30 @@ pub struct JoinParams {
     pub validator_net_addr: String,
 }
 
+/// Signature of a validator over the cid of a checkpoint.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorSignature {
+    pub validator: Address,
+    pub signature: Signature,
+}
+
+/// Evidence of a validator signing two different checkpoints of the subnet for the same epoch.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ReportEquivocationParams {
+    pub validator: Address,
+    pub first: BottomUpCheckpoint,
+    pub first_signature: Signature,
+    pub second: BottomUpCheckpoint,
+    pub second_signature: Signature,
+}
+
+/// Checkpoint submitted on behalf of the validators that signed it.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SignedCheckpointParams {
+    pub checkpoint: BottomUpCheckpoint,
+    pub signatures: Vec<ValidatorSignature>,
+}
+
 pub(crate) struct CrossActorPayload {
     pub to: Address,
     pub method: MethodNum,
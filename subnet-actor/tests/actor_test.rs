@@ -1,25 +1,35 @@
 #[cfg(test)]
 mod test {
     use cid::Cid;
+    use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
+    use fil_actors_runtime::runtime::fvm::PUBLIC_RESOLVE_ADDRESS_METHOD;
     use fil_actors_runtime::runtime::Runtime;
     use fil_actors_runtime::test_utils::{
-        expect_abort, expect_abort_contains_message, MockRuntime, ACCOUNT_ACTOR_CODE_ID,
-        INIT_ACTOR_CODE_ID, MULTISIG_ACTOR_CODE_ID,
+        expect_abort, expect_abort_contains_message, ExpectedVerifySig, MockRuntime,
+        ACCOUNT_ACTOR_CODE_ID, INIT_ACTOR_CODE_ID, MULTISIG_ACTOR_CODE_ID, SYSTEM_ACTOR_CODE_ID,
+    };
+    use fil_actors_runtime::{
+        make_empty_map, ActorError, BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR, SYSTEM_ACTOR_ADDR,
     };
-    use fil_actors_runtime::{ActorError, INIT_ACTOR_ADDR};
     use fvm_ipld_encoding::ipld_block::IpldBlock;
     use fvm_ipld_encoding::RawBytes;
+    use fvm_ipld_hamt::BytesKey;
     use fvm_shared::address::Address;
     use fvm_shared::clock::ChainEpoch;
     use fvm_shared::crypto::signature::Signature;
     use fvm_shared::econ::TokenAmount;
     use fvm_shared::error::ExitCode;
     use fvm_shared::METHOD_SEND;
+    use ipc_actor_common::vote::{EpochVoteSubmissions, UniqueBytesKey};
+    use ipc_gateway::upgrade::{BatchCrossMsgsV0, BottomUpCheckpointV0, CheckDataV0};
     use ipc_gateway::{
-        AmountParams, BottomUpCheckpoint, SubnetID, CHECKPOINT_GENESIS_CID, MIN_COLLATERAL_AMOUNT,
+        get_checkpoint, AmountParams, BottomUpCheckpoint, RegisterParams, SubnetID,
+        CHECKPOINT_GENESIS_CID, MIN_COLLATERAL_AMOUNT,
     };
+    use ipc_sdk::{epoch_key, Validator, ValidatorSet};
     use ipc_subnet_actor::{
-        Actor, ConsensusType, ConstructParams, JoinParams, Method, State, Status,
+        Actor, ConsensusType, ConstructParams, JoinParams, Method, ReportEquivocationParams,
+        SignedCheckpointParams, State, Status, ValidatorSignature,
     };
     use lazy_static::lazy_static;
     use num::BigInt;
@@ -48,6 +58,7 @@ mod test {
             min_validators: 0,
             topdown_check_period: 0,
             bottomup_check_period: 0,
+            bottomup_genesis_epoch: DEFAULT_GENESIS_EPOCH,
             genesis: vec![],
         }
     }
@@ -62,11 +73,13 @@ mod test {
     }
 
     fn construct_runtime_with_receiver(receiver: Address) -> MockRuntime {
+        construct_runtime_with_params(receiver, std_construct_param())
+    }
+
+    fn construct_runtime_with_params(receiver: Address, params: ConstructParams) -> MockRuntime {
         let mut runtime = new_runtime(receiver);
         runtime.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
 
-        let params = std_construct_param();
-
         runtime.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
 
         runtime.set_epoch(DEFAULT_GENESIS_EPOCH);
@@ -138,11 +151,18 @@ mod test {
         runtime.expect_send(
             gateway.clone(),
             ipc_gateway::Method::Register as u64,
-            None,
+            register_params(&runtime),
             TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.push(Validator {
+                addr: caller,
+                net_addr: validator.to_string(),
+                weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -245,11 +265,18 @@ mod test {
         runtime.expect_send(
             gateway.clone(),
             ipc_gateway::Method::Register as u64,
-            None,
+            register_params(&runtime),
             TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.push(Validator {
+                addr: caller,
+                net_addr: validator.to_string(),
+                weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -289,6 +316,13 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.push(Validator {
+                addr: caller,
+                net_addr: validator.to_string(),
+                weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -328,6 +362,9 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.update_weight(&caller, &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2))
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -415,11 +452,18 @@ mod test {
         runtime.expect_send(
             Address::new_id(IPC_GATEWAY_ADDR),
             ipc_gateway::Method::Register as u64,
-            None,
+            register_params(&runtime),
             TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.push(Validator {
+                addr: caller,
+                net_addr: validator.to_string(),
+                weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -454,6 +498,13 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| {
+            vs.push(Validator {
+                addr: caller,
+                net_addr: caller.to_string(),
+                weight: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+        });
         runtime
             .call::<Actor>(
                 Method::Join as u64,
@@ -531,6 +582,7 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| vs.rm(&caller));
         runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
 
         let st: State = runtime.get_state();
@@ -577,6 +629,7 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| vs.rm(&caller));
         runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
 
         let st: State = runtime.get_state();
@@ -615,6 +668,7 @@ mod test {
             None,
             ExitCode::new(0),
         );
+        expect_commit_membership(&mut runtime, |vs| vs.rm(&caller));
         runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
         let st: State = runtime.get_state();
         assert_eq!(st.validator_set.validators().len(), 0);
@@ -668,7 +722,7 @@ mod test {
                 runtime.expect_send(
                     Address::new_id(IPC_GATEWAY_ADDR),
                     ipc_gateway::Method::Register as u64,
-                    None,
+                    register_params(&runtime),
                     TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
                     None,
                     ExitCode::new(0),
@@ -688,6 +742,13 @@ mod test {
                 validator_net_addr: caller.to_string(),
             };
 
+            expect_commit_membership(&mut runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
             runtime
                 .call::<Actor>(
                     Method::Join as u64,
@@ -794,6 +855,55 @@ mod test {
         send_checkpoint(&mut runtime, sender.clone(), &checkpoint_4, false).unwrap();
     }
 
+    #[test]
+    fn test_submit_checkpoint_non_zero_genesis() {
+        let test_actor_address = Address::new_id(9999);
+        let genesis_epoch = 3;
+        let mut runtime = construct_runtime_with_params(
+            test_actor_address.clone(),
+            ConstructParams {
+                bottomup_genesis_epoch: genesis_epoch,
+                ..std_construct_param()
+            },
+        );
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+            Address::new_id(40),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.bottomup_checkpoint_voting.genesis_epoch, genesis_epoch);
+        let period = st.bottomup_checkpoint_voting.submission_period;
+
+        let root_subnet = SubnetID::from_str(ROOT_STR_ID).unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+
+        // checkpoints of windows not aligned with the genesis epoch are rejected
+        let checkpoint = BottomUpCheckpoint::new(subnet.clone(), period);
+        expect_abort_contains_message(
+            ExitCode::USR_ILLEGAL_STATE,
+            "epoch not allowed",
+            send_checkpoint(&mut runtime, miners[0], &checkpoint, false),
+        );
+
+        // the first window of the child closes one period after its genesis
+        let checkpoint = BottomUpCheckpoint::new(subnet.clone(), genesis_epoch + period);
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, miners[2], &checkpoint, true).unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.bottomup_checkpoint_voting.last_voting_executed_epoch,
+            genesis_epoch + period
+        );
+        assert_eq!(st.previous_executed_checkpoint_cid, checkpoint.cid());
+    }
+
     /// Tests the checkpoint will abort when checkpoints are not chained and the submitted epoch is the
     /// next executable epoch, we stop the epoch from submission
     #[test]
@@ -820,7 +930,7 @@ mod test {
                 runtime.expect_send(
                     Address::new_id(IPC_GATEWAY_ADDR),
                     ipc_gateway::Method::Register as u64,
-                    None,
+                    register_params(&runtime),
                     TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
                     None,
                     ExitCode::new(0),
@@ -840,6 +950,13 @@ mod test {
                 validator_net_addr: caller.to_string(),
             };
 
+            expect_commit_membership(&mut runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
             runtime
                 .call::<Actor>(
                     Method::Join as u64,
@@ -930,7 +1047,7 @@ mod test {
                 runtime.expect_send(
                     Address::new_id(IPC_GATEWAY_ADDR),
                     ipc_gateway::Method::Register as u64,
-                    None,
+                    register_params(&runtime),
                     TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
                     None,
                     ExitCode::new(0),
@@ -950,6 +1067,13 @@ mod test {
                 validator_net_addr: caller.to_string(),
             };
 
+            expect_commit_membership(&mut runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
             runtime
                 .call::<Actor>(
                     Method::Join as u64,
@@ -1059,6 +1183,552 @@ mod test {
         assert_eq!(st.bottomup_checkpoint_voting.executable_epoch_queue, None);
     }
 
+    #[test]
+    fn test_submit_signed_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+            Address::new_id(40),
+        ];
+        let keys: Vec<Address> = (1..=4)
+            .map(|i| Address::new_bls(&[i; fvm_shared::address::BLS_PUB_LEN]).unwrap())
+            .collect();
+
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        for (i, caller) in miners.iter().enumerate() {
+            runtime.set_value(value.clone());
+            runtime.set_balance(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, caller.clone());
+            runtime.expect_validate_caller_type(SIG_TYPES.clone());
+            let method = match i {
+                0 => ipc_gateway::Method::Register,
+                _ => ipc_gateway::Method::AddStake,
+            };
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                if i == 0 {
+                    register_params(&runtime)
+                } else {
+                    None
+                },
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                None,
+                ExitCode::new(0),
+            );
+            let params = JoinParams {
+                validator_net_addr: caller.to_string(),
+            };
+            expect_commit_membership(&mut runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+        }
+
+        let st: State = runtime.get_state();
+        let root_subnet = SubnetID::from_str(ROOT_STR_ID).unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let epoch = DEFAULT_GENESIS_EPOCH + st.bottomup_checkpoint_voting.submission_period;
+        let checkpoint = BottomUpCheckpoint::new(subnet.clone(), epoch);
+        let plaintext = checkpoint.cid().to_bytes();
+        let signature = |i: usize| ValidatorSignature {
+            validator: miners[i],
+            signature: Signature::new_bls(vec![i as u8; 4]),
+        };
+
+        // any signable actor can relay the checkpoint
+        let relayer = Address::new_id(50);
+
+        // only validators can sign checkpoints
+        runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, relayer);
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        let mut params = SignedCheckpointParams {
+            checkpoint: checkpoint.clone(),
+            signatures: vec![ValidatorSignature {
+                validator: relayer,
+                signature: Signature::new_bls(vec![0; 4]),
+            }],
+        };
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::SubmitSignedCheckpoint as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+
+        // signatures are verified against the key of the validator
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        params.signatures = vec![signature(0)];
+        expect_resolve_and_verify(
+            &mut runtime,
+            miners[0],
+            keys[0],
+            signature(0).signature,
+            &plaintext,
+            false,
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::SubmitSignedCheckpoint as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // a quorum of signatures commits the checkpoint in a single message
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        params.signatures = (0..3).map(signature).collect();
+        for i in 0..3 {
+            expect_resolve_and_verify(
+                &mut runtime,
+                miners[i],
+                keys[i],
+                signature(i).signature,
+                &plaintext,
+                true,
+            );
+        }
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            IpldBlock::serialize_cbor(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitSignedCheckpoint as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.previous_executed_checkpoint_cid, checkpoint.cid());
+        assert_eq!(
+            st.bottomup_checkpoint_voting.last_voting_executed_epoch,
+            epoch
+        );
+    }
+
+    #[test]
+    fn test_report_equivocation() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let miners = vec![Address::new_id(10), Address::new_id(20)];
+        let key = Address::new_bls(&[1; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        for (i, caller) in miners.iter().enumerate() {
+            runtime.set_value(value.clone());
+            runtime.set_balance(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, caller.clone());
+            runtime.expect_validate_caller_type(SIG_TYPES.clone());
+            let method = match i {
+                0 => ipc_gateway::Method::Register,
+                _ => ipc_gateway::Method::AddStake,
+            };
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                if i == 0 {
+                    register_params(&runtime)
+                } else {
+                    None
+                },
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                None,
+                ExitCode::new(0),
+            );
+            let params = JoinParams {
+                validator_net_addr: caller.to_string(),
+            };
+            expect_commit_membership(&mut runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+        }
+
+        let st: State = runtime.get_state();
+        let root_subnet = SubnetID::from_str(ROOT_STR_ID).unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let epoch = DEFAULT_GENESIS_EPOCH + st.bottomup_checkpoint_voting.submission_period;
+        let first = BottomUpCheckpoint::new(subnet.clone(), epoch);
+        let mut second = BottomUpCheckpoint::new(subnet.clone(), epoch);
+        second.data.proof = vec![1];
+        let other_subnet = SubnetID::new_from_parent(&root_subnet, Address::new_id(1000));
+        let first_signature = Signature::new_bls(vec![1; 4]);
+        let second_signature = Signature::new_bls(vec![2; 4]);
+
+        let reporter = Address::new_id(50);
+        runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, reporter);
+
+        // checkpoints for different epochs are not an equivocation
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        let mut params = ReportEquivocationParams {
+            validator: miners[0],
+            first: first.clone(),
+            first_signature: first_signature.clone(),
+            second: BottomUpCheckpoint::new(subnet.clone(), epoch + 1),
+            second_signature: second_signature.clone(),
+        };
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+
+        // neither is signing the same checkpoint twice
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        params.second = first.clone();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+
+        // nor signing checkpoints of different subnets
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        params.second = BottomUpCheckpoint::new(other_subnet.clone(), epoch);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+
+        // and conflicting checkpoints of another subnet are not reported here
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        let mut other = params.clone();
+        other.first = BottomUpCheckpoint::new(other_subnet.clone(), epoch);
+        other.second.data.proof = vec![1];
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&other).unwrap(),
+            ),
+        );
+
+        // both signatures need to be valid
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        params.second = second.clone();
+        expect_resolve_and_verify(
+            &mut runtime,
+            miners[0],
+            key,
+            first_signature.clone(),
+            &first.cid().to_bytes(),
+            true,
+        );
+        expect_resolve_and_verify(
+            &mut runtime,
+            miners[0],
+            key,
+            second_signature.clone(),
+            &second.cid().to_bytes(),
+            false,
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // the stake of the validator is released from the gateway and burnt
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        expect_resolve_and_verify(
+            &mut runtime,
+            miners[0],
+            key,
+            first_signature.clone(),
+            &first.cid().to_bytes(),
+            true,
+        );
+        expect_resolve_and_verify(
+            &mut runtime,
+            miners[0],
+            key,
+            second_signature.clone(),
+            &second.cid().to_bytes(),
+            true,
+        );
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            IpldBlock::serialize_cbor(&AmountParams {
+                value: value.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            None,
+            value.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        expect_commit_membership(&mut runtime, |vs| vs.rm(&miners[0]));
+        runtime
+            .call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert!(!st.is_validator(&miners[0]));
+        assert_eq!(st.validator_set.validators().len(), 1);
+        assert_eq!(st.total_stake, value);
+        assert_eq!(st.get_stake(runtime.store(), &miners[0]).unwrap(), None);
+
+        // the validator can't be slashed twice
+        runtime.expect_validate_caller_type(SIG_TYPES.clone());
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::ReportEquivocation as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_migrate_checkpoints() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let subnet = SubnetID::new_from_parent(
+            &SubnetID::from_str(ROOT_STR_ID).unwrap(),
+            test_actor_address,
+        );
+        let checkpoint_v0 = |epoch: ChainEpoch| BottomUpCheckpointV0 {
+            data: CheckDataV0 {
+                source: subnet.clone(),
+                proof: vec![1, 2, 3],
+                epoch,
+                prev_check: (*CHECKPOINT_GENESIS_CID).into(),
+                children: vec![],
+                cross_msgs: BatchCrossMsgsV0(vec![], TokenAmount::zero()),
+            },
+            sig: vec![],
+        };
+
+        // legacy state with an executed checkpoint and a pending vote for the next one
+        let mut st: State = runtime.get_state();
+        let period = st.bottomup_check_period;
+        let store = runtime.store();
+        let mut committed = make_empty_map(store, HAMT_BIT_WIDTH);
+        committed
+            .set(epoch_key(period), checkpoint_v0(period))
+            .unwrap();
+
+        let key = UniqueBytesKey(vec![1, 2, 3]);
+        let mut submitters = make_empty_map(store, HAMT_BIT_WIDTH);
+        submitters
+            .set(BytesKey::from(Address::new_id(10).to_bytes()), ())
+            .unwrap();
+        let mut weights = make_empty_map(store, HAMT_BIT_WIDTH);
+        weights
+            .set(BytesKey::from(key.as_slice()), TokenAmount::from_atto(100))
+            .unwrap();
+        let mut votes = make_empty_map(store, HAMT_BIT_WIDTH);
+        votes
+            .set(BytesKey::from(key.as_slice()), checkpoint_v0(2 * period))
+            .unwrap();
+        let mut epochs = make_empty_map(store, HAMT_BIT_WIDTH);
+        epochs
+            .set(
+                epoch_key(2 * period),
+                EpochVoteSubmissions::<BottomUpCheckpointV0> {
+                    total_submission_weight: TokenAmount::from_atto(100),
+                    most_voted_key: Some(key),
+                    submitters: submitters.flush().unwrap().into(),
+                    submission_weights: weights.flush().unwrap().into(),
+                    submissions: votes.flush().unwrap().into(),
+                },
+            )
+            .unwrap();
+
+        st.committed_checkpoints = TCid::from(committed.flush().unwrap());
+        st.bottomup_checkpoint_voting.last_voting_executed_epoch = period;
+        st.bottomup_checkpoint_voting.epoch_vote_submissions = TCid::from(epochs.flush().unwrap());
+        runtime.replace_state(&st);
+
+        // only the system actor can migrate the checkpoints
+        runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, Address::new_id(10));
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::MigrateCheckpoints as u64, None),
+        );
+
+        runtime.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        runtime
+            .call::<Actor>(Method::MigrateCheckpoints as u64, None)
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        let executed = BottomUpCheckpoint::from(checkpoint_v0(period));
+        let checkpoints = st.committed_checkpoints.load(runtime.store()).unwrap();
+        assert_eq!(
+            get_checkpoint(&checkpoints, period, period).unwrap(),
+            Some(&executed)
+        );
+        assert_eq!(st.previous_executed_checkpoint_cid, executed.cid());
+        assert_eq!(
+            st.bottomup_checkpoint_voting
+                .load_most_voted_submission(runtime.store(), 2 * period)
+                .unwrap(),
+            Some(BottomUpCheckpoint::from(checkpoint_v0(2 * period)))
+        );
+
+        // migrating the checkpoints again is a no-op
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        runtime
+            .call::<Actor>(Method::MigrateCheckpoints as u64, None)
+            .unwrap();
+        let migrated: State = runtime.get_state();
+        assert_eq!(migrated.committed_checkpoints, st.committed_checkpoints);
+        assert_eq!(
+            migrated.bottomup_checkpoint_voting,
+            st.bottomup_checkpoint_voting
+        );
+    }
+
+    /// Params with which the subnet is expected to register in the gateway.
+    fn register_params(runtime: &MockRuntime) -> Option<IpldBlock> {
+        let st: State = runtime.get_state();
+        IpldBlock::serialize_cbor(&RegisterParams {
+            bottomup_genesis_epoch: st.bottomup_checkpoint_voting.genesis_epoch(),
+        })
+        .unwrap()
+    }
+
+    /// Expects the validator set resulting from applying `update` to the current
+    /// one to be committed in the gateway.
+    fn expect_commit_membership(runtime: &mut MockRuntime, update: impl FnOnce(&mut ValidatorSet)) {
+        let mut validator_set = runtime.get_state::<State>().validator_set;
+        update(&mut validator_set);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitMembership as u64,
+            IpldBlock::serialize_cbor(&validator_set).unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+    }
+
+    fn expect_resolve_and_verify(
+        runtime: &mut MockRuntime,
+        validator: Address,
+        key: Address,
+        sig: Signature,
+        plaintext: &[u8],
+        valid: bool,
+    ) {
+        runtime.expect_send(
+            validator,
+            PUBLIC_RESOLVE_ADDRESS_METHOD,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&key).unwrap(),
+            ExitCode::new(0),
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig,
+            signer: key,
+            plaintext: plaintext.to_vec(),
+            result: match valid {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!("invalid signature")),
+            },
+        });
+    }
+
+    fn join_validators(runtime: &mut MockRuntime, validators: &[Address]) {
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        for (i, caller) in validators.iter().enumerate() {
+            runtime.set_value(value.clone());
+            runtime.set_balance(value.clone());
+            runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+            runtime.expect_validate_caller_type(SIG_TYPES.clone());
+            let method = if i == 0 {
+                ipc_gateway::Method::Register
+            } else {
+                ipc_gateway::Method::AddStake
+            };
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                if i == 0 {
+                    register_params(runtime)
+                } else {
+                    None
+                },
+                value.clone(),
+                None,
+                ExitCode::new(0),
+            );
+            expect_commit_membership(runtime, |vs| {
+                vs.push(Validator {
+                    addr: *caller,
+                    net_addr: caller.to_string(),
+                    weight: value.clone(),
+                })
+            });
+            let params = JoinParams {
+                validator_net_addr: caller.to_string(),
+            };
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+        }
+    }
+
     fn send_checkpoint(
         runtime: &mut MockRuntime,
         sender: Address,
@@ -0,0 +1,87 @@
+pub mod state;
+pub mod types;
+
+use fil_actors_runtime::runtime::{ActorCode, Runtime};
+use fil_actors_runtime::{
+    actor_dispatch, actor_error, restrict_internal_api, ActorError, INIT_ACTOR_ADDR,
+};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::{METHOD_CONSTRUCTOR, METHOD_SEND};
+use num_derive::FromPrimitive;
+use num_traits::Zero;
+
+pub use crate::state::State;
+pub use crate::types::*;
+
+#[cfg(feature = "fil-treasury-actor")]
+fil_actors_runtime::wasm_trampoline!(Actor);
+
+/// Treasury actor methods available
+#[derive(FromPrimitive)]
+#[repr(u64)]
+pub enum Method {
+    Constructor = METHOD_CONSTRUCTOR,
+    Unlock = frc42_dispatch::method_hash!("Unlock"),
+    SetLimits = frc42_dispatch::method_hash!("SetLimits"),
+}
+
+/// Treasury actor.
+///
+/// It holds the balance used to mint the circulating supply of a subnet, so
+/// funds only leave it when the gateway unlocks them for the value of a
+/// top-down message, and within the limits configured for the treasury. An
+/// exploit in the gateway can't drain more than these limits allow.
+pub struct Actor;
+
+impl Actor {
+    fn constructor(rt: &mut impl Runtime, params: ConstructorParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is(std::iter::once(&INIT_ACTOR_ADDR))?;
+
+        let st = State::new(params)?;
+        rt.create(&st)?;
+
+        Ok(())
+    }
+
+    /// Unlocks `amount` of circulating supply, sending it to the gateway. It can
+    /// only be called by the gateway, and only within the limits of the treasury.
+    fn unlock(rt: &mut impl Runtime, params: UnlockParams) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.gateway])?;
+
+        let UnlockParams { amount } = params;
+        if amount <= TokenAmount::zero() {
+            return Err(actor_error!(illegal_argument, "no funds to unlock"));
+        }
+        if rt.current_balance() < amount {
+            return Err(actor_error!(
+                illegal_state,
+                "not enough balance in the treasury to unlock {}",
+                amount
+            ));
+        }
+
+        rt.transaction(|st: &mut State, rt| st.unlock(&amount, rt.curr_epoch()))?;
+
+        rt.send(&st.gateway, METHOD_SEND, None, amount)?;
+        Ok(())
+    }
+
+    /// Updates the limits of the treasury. It can only be called by the governor.
+    fn set_limits(rt: &mut impl Runtime, limits: UnlockLimits) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is([&st.governor])?;
+
+        rt.transaction(|st: &mut State, _| st.set_limits(limits))
+    }
+}
+
+impl ActorCode for Actor {
+    type Methods = Method;
+
+    actor_dispatch! {
+        Constructor => constructor,
+        Unlock => unlock,
+        SetLimits => set_limits,
+    }
+}
@@ -0,0 +1,94 @@
+use fil_actors_runtime::{actor_error, ActorError};
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+use crate::types::*;
+
+/// Treasury actor state
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct State {
+    /// Gateway allowed to unlock funds from the treasury.
+    pub gateway: Address,
+    /// Address allowed to update the limits of the treasury.
+    pub governor: Address,
+    pub limits: UnlockLimits,
+    /// First epoch of the window in which `window_unlocked` was unlocked.
+    pub window_start: ChainEpoch,
+    /// Funds unlocked in the current window.
+    pub window_unlocked: TokenAmount,
+    /// Funds unlocked since the treasury was deployed.
+    pub total_unlocked: TokenAmount,
+}
+
+impl State {
+    pub fn new(params: ConstructorParams) -> Result<State, ActorError> {
+        check_limits(&params.limits)?;
+        Ok(State {
+            gateway: params.gateway,
+            governor: params.governor,
+            limits: params.limits,
+            window_start: 0,
+            window_unlocked: TokenAmount::zero(),
+            total_unlocked: TokenAmount::zero(),
+        })
+    }
+
+    /// Updates the limits of the treasury.
+    pub(crate) fn set_limits(&mut self, limits: UnlockLimits) -> Result<(), ActorError> {
+        check_limits(&limits)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Accounts for `amount` being unlocked at `epoch`, failing if it exceeds any
+    /// of the limits of the treasury.
+    pub(crate) fn unlock(
+        &mut self,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<(), ActorError> {
+        if !self.limits.per_msg.is_zero() && amount > &self.limits.per_msg {
+            return Err(actor_error!(
+                forbidden,
+                "amount {} exceeds the per-message limit {}",
+                amount,
+                self.limits.per_msg
+            ));
+        }
+
+        if self.limits.window > 0 {
+            let window_start = epoch - epoch % self.limits.window;
+            if window_start != self.window_start {
+                self.window_start = window_start;
+                self.window_unlocked = TokenAmount::zero();
+            }
+        }
+        if !self.limits.per_window.is_zero()
+            && &self.window_unlocked + amount > self.limits.per_window
+        {
+            return Err(actor_error!(
+                forbidden,
+                "amount {} exceeds the limit left in the window {}",
+                amount,
+                &self.limits.per_window - &self.window_unlocked
+            ));
+        }
+
+        self.window_unlocked += amount;
+        self.total_unlocked += amount;
+        Ok(())
+    }
+}
+
+fn check_limits(limits: &UnlockLimits) -> Result<(), ActorError> {
+    if limits.window < 0 || (!limits.per_window.is_zero() && limits.window == 0) {
+        return Err(actor_error!(
+            illegal_argument,
+            "a positive window is required to enforce a per-window limit"
+        ));
+    }
+    Ok(())
+}
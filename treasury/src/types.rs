@@ -0,0 +1,32 @@
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+
+/// ID used in the builtin-actors bundle manifest
+pub const MANIFEST_ID: &str = "ipc_treasury";
+
+/// Limits on the funds unlocked by the treasury, zero values disable the limit.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct UnlockLimits {
+    /// Maximum amount unlocked for a single cross-message
+    pub per_msg: TokenAmount,
+    /// Maximum amount unlocked in every window of `window` epochs
+    pub per_window: TokenAmount,
+    /// Length in epochs of the windows `per_window` is enforced for
+    pub window: ChainEpoch,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+pub struct ConstructorParams {
+    /// Gateway allowed to unlock funds from the treasury
+    pub gateway: Address,
+    /// Address allowed to update the limits of the treasury
+    pub governor: Address,
+    pub limits: UnlockLimits,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+pub struct UnlockParams {
+    pub amount: TokenAmount,
+}
@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod test {
+    use fil_actors_runtime::test_utils::{
+        expect_abort, MockRuntime, ACCOUNT_ACTOR_CODE_ID, INIT_ACTOR_CODE_ID,
+    };
+    use fil_actors_runtime::INIT_ACTOR_ADDR;
+    use fvm_ipld_encoding::ipld_block::IpldBlock;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::METHOD_SEND;
+    use ipc_treasury::{Actor, ConstructorParams, Method, State, UnlockLimits, UnlockParams};
+    use num_traits::Zero;
+
+    const GATEWAY_ADDR: u64 = 64;
+    const GOVERNOR_ADDR: u64 = 200;
+    const TREASURY_ADDR: u64 = 1000;
+
+    fn std_limits() -> UnlockLimits {
+        UnlockLimits {
+            per_msg: TokenAmount::from_atto(100),
+            per_window: TokenAmount::from_atto(150),
+            window: 10,
+        }
+    }
+
+    fn construct_runtime(limits: UnlockLimits) -> MockRuntime {
+        let mut rt = MockRuntime {
+            receiver: Address::new_id(TREASURY_ADDR),
+            caller: INIT_ACTOR_ADDR,
+            caller_type: *INIT_ACTOR_CODE_ID,
+            ..Default::default()
+        };
+        let params = ConstructorParams {
+            gateway: Address::new_id(GATEWAY_ADDR),
+            governor: Address::new_id(GOVERNOR_ADDR),
+            limits,
+        };
+
+        rt.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
+        rt.call::<Actor>(
+            Method::Constructor as u64,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+        rt
+    }
+
+    fn unlock(rt: &mut MockRuntime, caller: u64, amount: u64, code: ExitCode) {
+        let caller = Address::new_id(caller);
+        let amount = TokenAmount::from_atto(amount);
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, caller);
+        rt.expect_validate_caller_addr(vec![Address::new_id(GATEWAY_ADDR)]);
+        let params = IpldBlock::serialize_cbor(&UnlockParams {
+            amount: amount.clone(),
+        })
+        .unwrap();
+
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(Method::Unlock as u64, params));
+        } else {
+            rt.expect_send(
+                Address::new_id(GATEWAY_ADDR),
+                METHOD_SEND,
+                None,
+                amount.clone(),
+                None,
+                ExitCode::OK,
+            );
+            rt.call::<Actor>(Method::Unlock as u64, params).unwrap();
+        }
+        rt.verify();
+    }
+
+    #[test]
+    fn test_constructor() {
+        let rt = construct_runtime(std_limits());
+        let st: State = rt.get_state();
+        assert_eq!(st.gateway, Address::new_id(GATEWAY_ADDR));
+        assert_eq!(st.governor, Address::new_id(GOVERNOR_ADDR));
+        assert_eq!(st.limits, std_limits());
+        assert_eq!(st.total_unlocked, TokenAmount::zero());
+
+        // per-window limits need a window
+        let mut rt = MockRuntime {
+            receiver: Address::new_id(TREASURY_ADDR),
+            caller: INIT_ACTOR_ADDR,
+            caller_type: *INIT_ACTOR_CODE_ID,
+            ..Default::default()
+        };
+        let params = ConstructorParams {
+            gateway: Address::new_id(GATEWAY_ADDR),
+            governor: Address::new_id(GOVERNOR_ADDR),
+            limits: UnlockLimits {
+                window: 0,
+                ..std_limits()
+            },
+        };
+        rt.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            rt.call::<Actor>(
+                Method::Constructor as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_unlock() {
+        let mut rt = construct_runtime(std_limits());
+        rt.set_balance(TokenAmount::from_atto(1000));
+        rt.set_epoch(5);
+
+        // only the gateway can unlock funds
+        unlock(&mut rt, GOVERNOR_ADDR, 10, ExitCode::USR_FORBIDDEN);
+        // nothing to unlock
+        unlock(&mut rt, GATEWAY_ADDR, 0, ExitCode::USR_ILLEGAL_ARGUMENT);
+        // over the per-message limit
+        unlock(&mut rt, GATEWAY_ADDR, 101, ExitCode::USR_FORBIDDEN);
+
+        unlock(&mut rt, GATEWAY_ADDR, 100, ExitCode::OK);
+        // over the limit left in the window
+        unlock(&mut rt, GATEWAY_ADDR, 60, ExitCode::USR_FORBIDDEN);
+        unlock(&mut rt, GATEWAY_ADDR, 50, ExitCode::OK);
+
+        let st: State = rt.get_state();
+        assert_eq!(st.window_start, 0);
+        assert_eq!(st.window_unlocked, TokenAmount::from_atto(150));
+        assert_eq!(st.total_unlocked, TokenAmount::from_atto(150));
+
+        // the limit is reset in the next window
+        rt.set_epoch(12);
+        unlock(&mut rt, GATEWAY_ADDR, 100, ExitCode::OK);
+        let st: State = rt.get_state();
+        assert_eq!(st.window_start, 10);
+        assert_eq!(st.window_unlocked, TokenAmount::from_atto(100));
+        assert_eq!(st.total_unlocked, TokenAmount::from_atto(250));
+
+        // not enough balance
+        rt.set_balance(TokenAmount::from_atto(10));
+        unlock(&mut rt, GATEWAY_ADDR, 20, ExitCode::USR_ILLEGAL_STATE);
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let mut rt = construct_runtime(std_limits());
+        let limits = UnlockLimits {
+            per_msg: TokenAmount::zero(),
+            per_window: TokenAmount::zero(),
+            window: 0,
+        };
+
+        // only the governor can set the limits
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, Address::new_id(GATEWAY_ADDR));
+        rt.expect_validate_caller_addr(vec![Address::new_id(GOVERNOR_ADDR)]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            rt.call::<Actor>(
+                Method::SetLimits as u64,
+                IpldBlock::serialize_cbor(&limits).unwrap(),
+            ),
+        );
+        rt.verify();
+
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, Address::new_id(GOVERNOR_ADDR));
+        rt.expect_validate_caller_addr(vec![Address::new_id(GOVERNOR_ADDR)]);
+        rt.call::<Actor>(
+            Method::SetLimits as u64,
+            IpldBlock::serialize_cbor(&limits).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+        let st: State = rt.get_state();
+        assert_eq!(st.limits, limits);
+
+        // without limits any amount within the balance can be unlocked
+        rt.set_balance(TokenAmount::from_atto(1000));
+        unlock(&mut rt, GATEWAY_ADDR, 1000, ExitCode::OK);
+    }
+}